@@ -0,0 +1,271 @@
+// Metallic-roughness PBR shader.
+// MATERIAL_FEATURES is prepended by the pipeline cache, see MaterialFeatures::shader_flags().
+
+let FEATURE_BASE_COLOR_TEXTURE: u32 = 1u;
+let FEATURE_METALLIC_ROUGHNESS_TEXTURE: u32 = 2u;
+let FEATURE_NORMAL_TEXTURE: u32 = 4u;
+let FEATURE_OCCLUSION_TEXTURE: u32 = 8u;
+let FEATURE_EMISSIVE_TEXTURE: u32 = 16u;
+let FEATURE_ALPHA_MASK: u32 = 32u;
+let FEATURE_DOUBLE_SIDED: u32 = 64u;
+
+let PI: f32 = 3.14159265359;
+
+let LIGHT_TYPE_DIRECTIONAL: u32 = 0u;
+let LIGHT_TYPE_POINT: u32 = 1u;
+let LIGHT_TYPE_SPOT: u32 = 2u;
+
+// Must match shadow::SHADOW_MAP_SIZE and shadow::MAX_SHADOW_MAPS
+let SHADOW_MAP_SIZE: f32 = 1024.0;
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>;
+    position: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> camera: CameraUniform;
+
+struct ModelUniform {
+    model: mat4x4<f32>;
+    normal: mat4x4<f32>;
+};
+
+[[group(1), binding(0)]]
+var<uniform> model: ModelUniform;
+
+struct MaterialUniform {
+    base_color_factor: vec4<f32>;
+    emissive_factor: vec4<f32>;
+    metallic_factor: f32;
+    roughness_factor: f32;
+    normal_scale: f32;
+    occlusion_strength: f32;
+    alpha_cutoff: f32;
+};
+
+[[group(2), binding(0)]]
+var<uniform> material: MaterialUniform;
+[[group(2), binding(1)]]
+var material_sampler: sampler;
+[[group(2), binding(2)]]
+var base_color_texture: texture_2d<f32>;
+[[group(2), binding(3)]]
+var metallic_roughness_texture: texture_2d<f32>;
+[[group(2), binding(4)]]
+var normal_texture: texture_2d<f32>;
+[[group(2), binding(5)]]
+var occlusion_texture: texture_2d<f32>;
+[[group(2), binding(6)]]
+var emissive_texture: texture_2d<f32>;
+
+struct Light {
+    // w: range
+    position: vec4<f32>;
+    // w: light type
+    direction: vec4<f32>;
+    // rgb: color multiplied with intensity
+    color: vec4<f32>;
+    // x: cosine of the outer cone angle, y: cosine of the inner cone angle
+    cone: vec4<f32>;
+    // x: first shadow map layer (negative if the light casts no shadows), y: number of shadow map layers
+    shadow: vec4<f32>;
+};
+
+struct LightsUniform {
+    count: u32;
+    shadow_view_projs: array<mat4x4<f32>, 8>;
+    lights: array<Light, 64>;
+};
+
+[[group(3), binding(0)]]
+var<uniform> lights: LightsUniform;
+[[group(3), binding(1)]]
+var shadow_maps: texture_depth_2d_array;
+[[group(3), binding(2)]]
+var shadow_sampler: sampler_comparison;
+
+struct VSInput {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] normal: vec3<f32>;
+    [[location(2)]] tex_coords: vec2<f32>;
+    [[location(3)]] tangent: vec4<f32>;
+};
+
+struct VSOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] world_position: vec3<f32>;
+    [[location(1)]] normal: vec3<f32>;
+    [[location(2)]] tex_coords: vec2<f32>;
+    [[location(3)]] tangent: vec4<f32>;
+};
+
+fn has_feature(feature: u32) -> bool {
+    return (MATERIAL_FEATURES & feature) != 0u;
+}
+
+[[stage(vertex)]]
+fn vs_main(in: VSInput) -> VSOutput {
+    let world_position = model.model * vec4<f32>(in.position, 1.0);
+    var out: VSOutput;
+    out.clip_position = camera.view_proj * world_position;
+    out.world_position = world_position.xyz;
+    out.normal = (model.normal * vec4<f32>(in.normal, 0.0)).xyz;
+    out.tex_coords = in.tex_coords;
+    out.tangent = vec4<f32>((model.model * vec4<f32>(in.tangent.xyz, 0.0)).xyz, in.tangent.w);
+    return out;
+}
+
+// Trowbridge-Reitz GGX normal distribution
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+// Smith's geometry term with Schlick-GGX
+fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    let g_v = n_dot_v / (n_dot_v * (1.0 - k) + k);
+    let g_l = n_dot_l / (n_dot_l * (1.0 - k) + k);
+    return g_v * g_l;
+}
+
+fn fresnel_schlick(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+    return f0 + (vec3<f32>(1.0, 1.0, 1.0) - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+// Radiance reflected towards [v] of light arriving from direction [l] with [radiance].
+fn brdf(n: vec3<f32>, v: vec3<f32>, l: vec3<f32>, radiance: vec3<f32>, base_color: vec3<f32>, metallic: f32, roughness: f32) -> vec3<f32> {
+    let h = normalize(v + l);
+    let n_dot_l = max(dot(n, l), 0.0);
+    let n_dot_v = max(dot(n, v), 0.0001);
+    let n_dot_h = max(dot(n, h), 0.0);
+
+    let f0 = mix(vec3<f32>(0.04, 0.04, 0.04), base_color, vec3<f32>(metallic, metallic, metallic));
+    let f = fresnel_schlick(max(dot(h, v), 0.0), f0);
+    let d = distribution_ggx(n_dot_h, roughness);
+    let g = geometry_smith(n_dot_v, n_dot_l, roughness);
+
+    let specular = d * g * f / (4.0 * n_dot_v * n_dot_l + 0.0001);
+    let k_d = (vec3<f32>(1.0, 1.0, 1.0) - f) * (1.0 - metallic);
+    return (k_d * base_color / PI + specular) * radiance * n_dot_l;
+}
+
+// Smooth falloff to zero at the light's range, as recommended by KHR_lights_punctual
+fn range_attenuation(distance: f32, range: f32) -> f32 {
+    let ratio = distance / range;
+    return clamp(1.0 - ratio * ratio * ratio * ratio, 0.0, 1.0) / max(distance * distance, 0.0001);
+}
+
+// 3x3 percentage closer filtering of shadow map [layer] at [light_ndc].
+// Each tap is bilinearly filtered by the comparison sampler.
+fn sample_shadow_map(layer: i32, light_ndc: vec3<f32>) -> f32 {
+    let uv = light_ndc.xy * vec2<f32>(0.5, -0.5) + vec2<f32>(0.5, 0.5);
+    let texel_size = 1.0 / SHADOW_MAP_SIZE;
+    var visibility = 0.0;
+    for (var x: i32 = -1; x <= 1; x = x + 1) {
+        for (var y: i32 = -1; y <= 1; y = y + 1) {
+            let offset = vec2<f32>(f32(x), f32(y)) * texel_size;
+            visibility = visibility + textureSampleCompareLevel(shadow_maps, shadow_sampler, uv + offset, layer, light_ndc.z);
+        }
+    }
+    return visibility / 9.0;
+}
+
+// The fraction of [light] that reaches [world_position] unoccluded.
+// Cascades are ordered by distance from the camera, the first cascade containing the position is used.
+fn shadow_visibility(light: Light, world_position: vec3<f32>) -> f32 {
+    let first_layer = i32(light.shadow.x);
+    if (first_layer < 0) {
+        return 1.0;
+    }
+    let layer_count = i32(light.shadow.y);
+    for (var i: i32 = 0; i < layer_count; i = i + 1) {
+        let layer = first_layer + i;
+        let light_clip = lights.shadow_view_projs[layer] * vec4<f32>(world_position, 1.0);
+        let light_ndc = light_clip.xyz / light_clip.w;
+        if (abs(light_ndc.x) <= 1.0 && abs(light_ndc.y) <= 1.0 && light_ndc.z >= 0.0 && light_ndc.z <= 1.0) {
+            return sample_shadow_map(layer, light_ndc);
+        }
+    }
+    return 1.0;
+}
+
+// Radiance reflected towards [v] from [light].
+fn shade_light(light: Light, world_position: vec3<f32>, n: vec3<f32>, v: vec3<f32>, base_color: vec3<f32>, metallic: f32, roughness: f32) -> vec3<f32> {
+    let light_type = u32(light.direction.w);
+    let visibility = shadow_visibility(light, world_position);
+    if (light_type == LIGHT_TYPE_DIRECTIONAL) {
+        return brdf(n, v, -light.direction.xyz, light.color.rgb * visibility, base_color, metallic, roughness);
+    }
+    let to_light = light.position.xyz - world_position;
+    let distance = length(to_light);
+    let l = to_light / distance;
+    var attenuation = range_attenuation(distance, light.position.w) * visibility;
+    if (light_type == LIGHT_TYPE_SPOT) {
+        let cos_angle = dot(light.direction.xyz, -l);
+        attenuation = attenuation * smoothStep(light.cone.x, light.cone.y, cos_angle);
+    }
+    return brdf(n, v, l, light.color.rgb * attenuation, base_color, metallic, roughness);
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VSOutput, [[builtin(front_facing)]] front_facing: bool) -> [[location(0)]] vec4<f32> {
+    // Sample everything up front to stay in uniform control flow.
+    // Textures the material does not have are bound to a 1x1 white placeholder.
+    let base_color_sample = textureSample(base_color_texture, material_sampler, in.tex_coords);
+    let metallic_roughness_sample = textureSample(metallic_roughness_texture, material_sampler, in.tex_coords);
+    let normal_sample = textureSample(normal_texture, material_sampler, in.tex_coords);
+    let occlusion_sample = textureSample(occlusion_texture, material_sampler, in.tex_coords);
+    let emissive_sample = textureSample(emissive_texture, material_sampler, in.tex_coords);
+
+    var base_color = material.base_color_factor;
+    if (has_feature(FEATURE_BASE_COLOR_TEXTURE)) {
+        base_color = base_color * base_color_sample;
+    }
+    if (has_feature(FEATURE_ALPHA_MASK) && base_color.a < material.alpha_cutoff) {
+        discard;
+    }
+
+    var metallic = material.metallic_factor;
+    var roughness = material.roughness_factor;
+    if (has_feature(FEATURE_METALLIC_ROUGHNESS_TEXTURE)) {
+        roughness = roughness * metallic_roughness_sample.g;
+        metallic = metallic * metallic_roughness_sample.b;
+    }
+    roughness = clamp(roughness, 0.04, 1.0);
+
+    var n = normalize(in.normal);
+    if (has_feature(FEATURE_DOUBLE_SIDED) && !front_facing) {
+        n = -n;
+    }
+    if (has_feature(FEATURE_NORMAL_TEXTURE)) {
+        let t = normalize(in.tangent.xyz - n * dot(n, in.tangent.xyz));
+        let b = cross(n, t) * in.tangent.w;
+        let tangent_normal = (normal_sample.xyz * 2.0 - vec3<f32>(1.0, 1.0, 1.0)) * vec3<f32>(material.normal_scale, material.normal_scale, 1.0);
+        n = normalize(mat3x3<f32>(t, b, n) * tangent_normal);
+    }
+
+    let v = normalize(camera.position.xyz - in.world_position);
+
+    var color = vec3<f32>(0.0, 0.0, 0.0);
+    for (var i: u32 = 0u; i < lights.count; i = i + 1u) {
+        color = color + shade_light(lights.lights[i], in.world_position, n, v, base_color.rgb, metallic, roughness);
+    }
+
+    var ambient = vec3<f32>(0.03, 0.03, 0.03) * base_color.rgb;
+    if (has_feature(FEATURE_OCCLUSION_TEXTURE)) {
+        ambient = ambient * mix(1.0, occlusion_sample.r, material.occlusion_strength);
+    }
+    color = color + ambient;
+
+    var emissive = material.emissive_factor.rgb;
+    if (has_feature(FEATURE_EMISSIVE_TEXTURE)) {
+        emissive = emissive * emissive_sample.rgb;
+    }
+    color = color + emissive;
+
+    return vec4<f32>(color, base_color.a);
+}
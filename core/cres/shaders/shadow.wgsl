@@ -0,0 +1,21 @@
+// Depth-only shader for rendering shadow maps from a light's point of view.
+
+struct LightCameraUniform {
+    view_proj: mat4x4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> light_camera: LightCameraUniform;
+
+struct ModelUniform {
+    model: mat4x4<f32>;
+    normal: mat4x4<f32>;
+};
+
+[[group(1), binding(0)]]
+var<uniform> model: ModelUniform;
+
+[[stage(vertex)]]
+fn vs_main([[location(0)]] position: vec3<f32>) -> [[builtin(position)]] vec4<f32> {
+    return light_camera.view_proj * model.model * vec4<f32>(position, 1.0);
+}
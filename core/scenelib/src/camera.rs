@@ -2,7 +2,8 @@ use std::any::Any;
 use std::f32::consts::PI;
 use glam::{Mat4, Quat, Vec3, Vec3A};
 use wgpu::util::DeviceExt;
-use crate::scene::{StaticRenderState, RenderNode, RenderScene, RenderCallState, RenderNodeHandle};
+use crate::render_phase::RenderPhase;
+use crate::scene::{StaticRenderState, RenderNode, RenderScene, RenderCallState, RenderNodeHandle, CAMERA_BIND_GROUP};
 
 // We need this for Rust to store our data correctly for the shaders
 #[repr(C)]
@@ -11,6 +12,15 @@ use crate::scene::{StaticRenderState, RenderNode, RenderScene, RenderCallState,
 pub struct CameraShaderState {
     // The cameras view matrix and projection matrix multiplied together
     view_proj: [[f32; 4]; 4],
+    // The camera's position in world space (w is unused)
+    position: [f32; 4],
+}
+
+impl CameraShaderState {
+    /// The view projection matrix as of the last update of the camera.
+    pub fn view_proj(&self) -> Mat4 {
+        return Mat4::from_cols_array_2d(&self.view_proj);
+    }
 }
 
 pub struct PerspectiveCamera {
@@ -61,7 +71,8 @@ impl PerspectiveCamera {
                     [0.0, 1.0, 0.0, 0.0],
                     [0.0, 0.0, 1.0, 0.0],
                     [0.0, 0.0, 0.0, 1.0],
-                ]
+                ],
+                position: position.extend(1.0).to_array(),
             },
         };
     }
@@ -73,12 +84,15 @@ impl PerspectiveCamera {
 
         let view_matrix = Mat4::look_at_lh(Vec3::from(self.position), Vec3::from(self.position + self.direction), Vec3::from(self.up));
 
+        // Reverse-Z: the near plane maps to depth 1 and the far plane to depth 0.
+        // Swapping near and far of the regular projection yields the reversed mapping.
         let projection_matrix = match self.far {
-            Some(far) => Mat4::perspective_lh(self.fov, self.aspect, self.near, far),
-            None => Mat4::perspective_infinite_lh(self.fov, self.aspect, self.near),
+            Some(far) => Mat4::perspective_lh(self.fov, self.aspect, far, self.near),
+            None => Mat4::perspective_infinite_reverse_lh(self.fov, self.aspect, self.near),
         };
 
         self.camera_shader_state.view_proj = (projection_matrix * view_matrix).to_cols_array_2d();
+        self.camera_shader_state.position = self.position.extend(1.0).to_array();
         self.dirty = false;
     }
 
@@ -209,8 +223,345 @@ impl PerspectiveCamera {
     }
 }
 
+pub struct OrthographicCamera {
+    // The camera's position.
+    position: Vec3A,
+    // The camera's direction.
+    direction: Vec3A,
+    // The camera's right vector.
+    right: Vec3A,
+    // The camera's forward axis.
+    forward_axis: Vec3A,
+    // The camera's up axis
+    up_axis: Vec3A,
+    // The camera's up vector
+    up: Vec3A,
+    // The camera's aspect ratio. The width of the view volume is [height] * [aspect].
+    aspect: f32,
+    // The height of the camera's view volume in world units.
+    height: f32,
+    // The camera's near plane.
+    near: f32,
+    // The camera's far plane. Unlike perspective cameras, orthographic cameras cannot have an infinite far plane.
+    far: f32,
+    // Whether the camera's state has changed since last frame
+    dirty: bool,
+    // The state of the camera passed to the shader for vertex space transformation
+    pub camera_shader_state: CameraShaderState,
+}
+
+impl OrthographicCamera {
+    pub fn new(position: Vec3A, direction: Vec3A, forward_axis: Vec3A, up_axis: Vec3A, height: f32, near: f32, far: f32, aspect: f32) -> OrthographicCamera {
+        return OrthographicCamera {
+            position,
+            direction,
+            right: up_axis.cross(direction),
+            forward_axis,
+            up_axis,
+            up: direction.cross(up_axis.cross(direction)),
+            aspect,
+            height,
+            near,
+            far,
+            dirty: true,
+            camera_shader_state: CameraShaderState {
+                view_proj: Mat4::IDENTITY.to_cols_array_2d(),
+                position: position.extend(1.0).to_array(),
+            },
+        };
+    }
+
+    pub fn update(&mut self) {
+        if !self.dirty {
+            return;
+        }
+
+        let view_matrix = Mat4::look_at_lh(Vec3::from(self.position), Vec3::from(self.position + self.direction), Vec3::from(self.up));
+
+        // Reverse-Z, see PerspectiveCamera::update()
+        let half_height = self.height * 0.5;
+        let half_width = half_height * self.aspect;
+        let projection_matrix = Mat4::orthographic_lh(-half_width, half_width, -half_height, half_height, self.far, self.near);
+
+        self.camera_shader_state.view_proj = (projection_matrix * view_matrix).to_cols_array_2d();
+        self.camera_shader_state.position = self.position.extend(1.0).to_array();
+        self.dirty = false;
+    }
+
+    pub fn set_up_axis(&mut self, up_axis: Vec3A) {
+        if self.up_axis == up_axis {
+            return;
+        }
+        self.up_axis = up_axis;
+        self.up = self.direction.cross(self.up_axis.cross(self.direction));
+        self.dirty = true;
+    }
+
+    pub fn set_position(&mut self, position: Vec3A) {
+        if self.position == position {
+            return;
+        }
+        self.position = position;
+        self.dirty = true;
+    }
+
+    pub fn set_direction(&mut self, direction: Vec3A) {
+        if self.direction == direction {
+            return;
+        }
+        self.direction = direction;
+        self.right = self.up_axis.cross(self.direction);
+        self.up = self.direction.cross(self.right.cross(self.direction));
+        self.dirty = true;
+    }
+
+    pub fn set_aspect(&mut self, aspect: f32) {
+        if self.aspect == aspect {
+            return;
+        }
+        self.aspect = aspect;
+        self.dirty = true;
+    }
+
+    pub fn set_height(&mut self, height: f32) {
+        if self.height == height {
+            return;
+        }
+        self.height = height;
+        self.dirty = true;
+    }
+
+    pub fn set_near(&mut self, near: f32) {
+        if self.near == near {
+            return;
+        }
+        self.near = near;
+        self.dirty = true;
+    }
+
+    pub fn set_far(&mut self, far: f32) {
+        if self.far == far {
+            return;
+        }
+        self.far = far;
+        self.dirty = true;
+    }
+
+    pub fn set_rotation(&mut self, rotation: Quat) {
+        let direction = rotation * self.forward_axis;
+        let up = rotation * self.up_axis;
+        let right = up.cross(direction);
+
+        if self.direction != direction || self.up != up || self.right != right {
+            self.dirty = true;
+        }
+        self.direction = direction;
+        self.up = up;
+        self.right = right;
+    }
+
+    pub fn position(&self) -> Vec3A {
+        self.position
+    }
+    pub fn direction(&self) -> Vec3A {
+        self.direction
+    }
+    pub fn up_axis(&self) -> Vec3A {
+        self.up_axis
+    }
+    pub fn aspect(&self) -> f32 {
+        self.aspect
+    }
+    pub fn height(&self) -> f32 {
+        self.height
+    }
+    pub fn near(&self) -> f32 {
+        self.near
+    }
+    pub fn far(&self) -> f32 {
+        self.far
+    }
+    pub fn right(&self) -> Vec3A {
+        self.right
+    }
+    pub fn up(&self) -> Vec3A {
+        self.up
+    }
+}
+
+/// A camera that a [CameraRenderNode] renders from.
+pub enum Camera {
+    Perspective(PerspectiveCamera),
+    Orthographic(OrthographicCamera),
+}
+
+impl From<PerspectiveCamera> for Camera {
+    fn from(camera: PerspectiveCamera) -> Self {
+        return Camera::Perspective(camera);
+    }
+}
+
+impl From<OrthographicCamera> for Camera {
+    fn from(camera: OrthographicCamera) -> Self {
+        return Camera::Orthographic(camera);
+    }
+}
+
+impl Camera {
+    pub fn update(&mut self) {
+        match self {
+            Camera::Perspective(camera) => camera.update(),
+            Camera::Orthographic(camera) => camera.update(),
+        }
+    }
+
+    /// Whether the camera's state has changed since the last [Self::update()].
+    pub fn is_dirty(&self) -> bool {
+        return match self {
+            Camera::Perspective(camera) => camera.dirty,
+            Camera::Orthographic(camera) => camera.dirty,
+        };
+    }
+
+    pub fn shader_state(&self) -> &CameraShaderState {
+        return match self {
+            Camera::Perspective(camera) => &camera.camera_shader_state,
+            Camera::Orthographic(camera) => &camera.camera_shader_state,
+        };
+    }
+
+    pub fn set_position(&mut self, position: Vec3A) {
+        match self {
+            Camera::Perspective(camera) => camera.set_position(position),
+            Camera::Orthographic(camera) => camera.set_position(position),
+        }
+    }
+
+    pub fn set_direction(&mut self, direction: Vec3A) {
+        match self {
+            Camera::Perspective(camera) => camera.set_direction(direction),
+            Camera::Orthographic(camera) => camera.set_direction(direction),
+        }
+    }
+
+    pub fn set_rotation(&mut self, rotation: Quat) {
+        match self {
+            Camera::Perspective(camera) => camera.set_rotation(rotation),
+            Camera::Orthographic(camera) => camera.set_rotation(rotation),
+        }
+    }
+
+    pub fn set_up_axis(&mut self, up_axis: Vec3A) {
+        match self {
+            Camera::Perspective(camera) => camera.set_up_axis(up_axis),
+            Camera::Orthographic(camera) => camera.set_up_axis(up_axis),
+        }
+    }
+
+    pub fn set_aspect(&mut self, aspect: f32) {
+        match self {
+            Camera::Perspective(camera) => camera.set_aspect(aspect),
+            Camera::Orthographic(camera) => camera.set_aspect(aspect),
+        }
+    }
+
+    pub fn set_near(&mut self, near: f32) {
+        match self {
+            Camera::Perspective(camera) => camera.set_near(near),
+            Camera::Orthographic(camera) => camera.set_near(near),
+        }
+    }
+
+    pub fn set_far(&mut self, far: f32) {
+        match self {
+            Camera::Perspective(camera) => camera.set_far(far),
+            Camera::Orthographic(camera) => camera.set_far(far),
+        }
+    }
+
+    pub fn position(&self) -> Vec3A {
+        return match self {
+            Camera::Perspective(camera) => camera.position(),
+            Camera::Orthographic(camera) => camera.position(),
+        };
+    }
+
+    pub fn direction(&self) -> Vec3A {
+        return match self {
+            Camera::Perspective(camera) => camera.direction(),
+            Camera::Orthographic(camera) => camera.direction(),
+        };
+    }
+
+    pub fn right(&self) -> Vec3A {
+        return match self {
+            Camera::Perspective(camera) => camera.right(),
+            Camera::Orthographic(camera) => camera.right(),
+        };
+    }
+
+    pub fn up(&self) -> Vec3A {
+        return match self {
+            Camera::Perspective(camera) => camera.up(),
+            Camera::Orthographic(camera) => camera.up(),
+        };
+    }
+
+    pub fn up_axis(&self) -> Vec3A {
+        return match self {
+            Camera::Perspective(camera) => camera.up_axis(),
+            Camera::Orthographic(camera) => camera.up_axis(),
+        };
+    }
+
+    pub fn aspect(&self) -> f32 {
+        return match self {
+            Camera::Perspective(camera) => camera.aspect(),
+            Camera::Orthographic(camera) => camera.aspect(),
+        };
+    }
+
+    pub fn near(&self) -> f32 {
+        return match self {
+            Camera::Perspective(camera) => camera.near(),
+            Camera::Orthographic(camera) => camera.near(),
+        };
+    }
+
+    /// The far plane of the camera. None if it is infinitely far away.
+    pub fn far(&self) -> Option<f32> {
+        return match self {
+            Camera::Perspective(camera) => camera.far(),
+            Camera::Orthographic(camera) => Some(camera.far()),
+        };
+    }
+
+    /// Returns the world space corners of the slice of the camera's view volume between [near] and [far].
+    /// The four corners of the near plane come first.
+    pub fn frustum_slice_corners(&self, near: f32, far: f32) -> [Vec3A; 8] {
+        return match self {
+            Camera::Perspective(camera) => {
+                crate::shadow::frustum_slice_corners(camera.position(), camera.direction(), camera.up(), camera.fov(), camera.aspect(), near, far)
+            }
+            Camera::Orthographic(camera) => {
+                let half_height = camera.up() * (camera.height() * 0.5);
+                let half_width = camera.right() * (camera.height() * 0.5 * camera.aspect());
+                let mut corners = [Vec3A::ZERO; 8];
+                for (plane, distance) in [near, far].iter().enumerate() {
+                    let center = camera.position() + camera.direction() * *distance;
+                    corners[plane * 4] = center - half_width - half_height;
+                    corners[plane * 4 + 1] = center + half_width - half_height;
+                    corners[plane * 4 + 2] = center + half_width + half_height;
+                    corners[plane * 4 + 3] = center - half_width + half_height;
+                }
+                corners
+            }
+        };
+    }
+}
+
 pub struct CameraRenderNode {
-    camera: PerspectiveCamera,
+    camera: Camera,
     camera_buffer: wgpu::Buffer,
     camera_bind_group: wgpu::BindGroup,
 
@@ -237,22 +588,33 @@ impl CameraRenderNode {
         self.is_active_camera = false;
         self.dirty = true;
     }
+
+    pub fn is_active(&self) -> bool {
+        return self.is_active_camera;
+    }
 }
 
 impl RenderNode for CameraRenderNode {
     fn is_dirty(&self) -> bool {
-        return self.dirty || self.camera.dirty;
+        return self.dirty || self.camera.is_dirty();
     }
 
     #[profiling::function]
-    fn render<'a, 'b: 'a>(&'b mut self, _static_render_state: &mut StaticRenderState, render_call: &mut RenderCallState<'_, 'b>) {
-        render_call.render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+    fn render<'a, 'b: 'a>(&'b mut self, _static_render_state: &'b StaticRenderState, render_call: &mut RenderCallState<'_, 'b>) {
+        // Only the buffer of the active camera is up to date
+        if self.is_active_camera {
+            render_call.render_pass.set_bind_group(CAMERA_BIND_GROUP, &self.camera_bind_group, &[]);
+        }
+    }
+
+    fn render_phase(&self) -> RenderPhase {
+        return RenderPhase::Setup;
     }
 
     fn resolve_dirty_state(&mut self, static_render_state: &mut StaticRenderState) {
-        let was_camera_dirty = self.camera.dirty;
+        let was_camera_dirty = self.camera.is_dirty();
         let was_camera_render_node_dirty = self.dirty;
-        if self.camera.dirty {
+        if was_camera_dirty {
             self.camera.update();
         }
         if self.dirty {
@@ -262,11 +624,21 @@ impl RenderNode for CameraRenderNode {
             if was_camera_dirty || was_camera_render_node_dirty {
                 // If the camera's state has changed OR this camera has just become the active camera, meaning that
                 // the contents of self.camera_buffer are some other camera's data, we need to update the camera buffer
-                static_render_state.queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[self.camera.camera_shader_state]));
+                static_render_state.queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[*self.camera.shader_state()]));
             }
         }
     }
 
+    fn set_world_transform(&mut self, world_transform: &Mat4) {
+        let (_, rotation, translation) = world_transform.to_scale_rotation_translation();
+        self.camera.set_position(Vec3A::from(translation));
+        self.camera.set_rotation(rotation);
+    }
+
+    fn release_resources(&mut self, _static_render_state: &mut StaticRenderState) {
+        self.camera_buffer.destroy();
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
@@ -277,32 +649,18 @@ impl RenderNode for CameraRenderNode {
 }
 
 impl CameraRenderNode {
-    pub fn add_new(camera: PerspectiveCamera, scene: &mut RenderScene) -> RenderNodeHandle {
+    pub fn add_new<C: Into<Camera>>(camera: C, scene: &mut RenderScene) -> RenderNodeHandle {
+        let camera = camera.into();
         let render_context = &mut scene.static_render_state;
         let camera_buffer = render_context.device.create_buffer_init(
             &wgpu::util::BufferInitDescriptor {
                 label: Some("CameraBuffer"),
-                contents: bytemuck::cast_slice(&[camera.camera_shader_state]),
+                contents: bytemuck::cast_slice(&[*camera.shader_state()]),
                 usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             }
         );
-        let camera_bind_group_layout = render_context.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }
-            ],
-            label: Some("camera_bind_group_layout"),
-        });
         let camera_bind_group = render_context.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &camera_bind_group_layout,
+            layout: render_context.camera_bind_group_layout(),
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
@@ -311,7 +669,6 @@ impl CameraRenderNode {
             ],
             label: Some("camera_bind_group"),
         });
-        render_context.push_bind_group_layout(camera_bind_group_layout);
         let camera_node = CameraRenderNode {
             camera,
             camera_buffer,
@@ -330,24 +687,12 @@ impl CameraRenderNode {
         self.camera.set_rotation(quat);
     }
 
-    pub fn set_rotation_euler(&mut self, yaw_degrees: f32, pitch_degrees: f32) {
-        self.camera.set_rotation_euler(yaw_degrees, pitch_degrees);
-    }
-
-    pub fn set_roll_euler(&mut self, roll_degrees: f32) {
-        self.camera.set_roll_euler(roll_degrees);
+    pub fn camera(&self) -> &Camera {
+        return &self.camera;
     }
 
-    pub fn yaw(&self) -> f32 {
-        return self.camera.yaw();
-    }
-
-    pub fn pitch(&self) -> f32 {
-        return self.camera.pitch();
-    }
-
-    pub fn roll(&self) -> f32 {
-        return self.camera.roll();
+    pub fn camera_mut(&mut self) -> &mut Camera {
+        return &mut self.camera;
     }
 
     pub fn position(&self) -> Vec3A {
@@ -374,8 +719,12 @@ impl CameraRenderNode {
         return self.camera.aspect();
     }
 
-    pub fn fov(&self) -> f32 {
-        return self.camera.fov();
+    /// The vertical field of view in radians. None for orthographic cameras.
+    pub fn fov(&self) -> Option<f32> {
+        return match &self.camera {
+            Camera::Perspective(camera) => Some(camera.fov()),
+            Camera::Orthographic(_) => None,
+        };
     }
 
     pub fn near(&self) -> f32 {
@@ -390,8 +739,11 @@ impl CameraRenderNode {
         self.camera.set_aspect(aspect);
     }
 
+    /// Sets the vertical field of view in radians. Has no effect on orthographic cameras.
     pub fn set_fov(&mut self, fov: f32) {
-        self.camera.set_fov(fov);
+        if let Camera::Perspective(camera) = &mut self.camera {
+            camera.set_fov(fov);
+        }
     }
 
     pub fn set_up_axis(&mut self, up_axis: Vec3A) {
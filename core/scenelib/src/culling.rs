@@ -0,0 +1,284 @@
+use std::collections::HashSet;
+use glam::{Mat4, Vec3A, Vec4};
+use crate::scene::RenderNodeHandle;
+
+/// An axis aligned bounding box.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3A,
+    pub max: Vec3A,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3A, max: Vec3A) -> Self {
+        return Aabb { min, max };
+    }
+
+    /// The smallest box containing all [points]. None if there are no points.
+    pub fn from_points<I: IntoIterator<Item=Vec3A>>(points: I) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        let mut aabb = Aabb { min: first, max: first };
+        for point in points {
+            aabb.min = aabb.min.min(point);
+            aabb.max = aabb.max.max(point);
+        }
+        return Some(aabb);
+    }
+
+    pub fn center(&self) -> Vec3A {
+        return (self.min + self.max) * 0.5;
+    }
+
+    pub fn half_extents(&self) -> Vec3A {
+        return (self.max - self.min) * 0.5;
+    }
+
+    /// The axis aligned box containing this box transformed by [transform].
+    pub fn transformed(&self, transform: &Mat4) -> Aabb {
+        let center = Vec3A::from(transform.transform_point3(self.center().into()));
+        let half_extents = self.half_extents();
+        // Projecting the transformed box axes onto the world axes yields the new half extents
+        let new_half_extents = Vec3A::from(transform.x_axis.truncate()).abs() * half_extents.x
+            + Vec3A::from(transform.y_axis.truncate()).abs() * half_extents.y
+            + Vec3A::from(transform.z_axis.truncate()).abs() * half_extents.z;
+        return Aabb { min: center - new_half_extents, max: center + new_half_extents };
+    }
+}
+
+/// The six planes bounding the view volume of a camera. The plane normals point inwards.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Frustum {
+    /// xyz is the plane normal, w the distance. A point p is inside of the plane if dot(normal, p) + w >= 0.
+    planes: [Vec4; 6],
+}
+
+impl Frustum {
+    /// Extracts the frustum planes from a view projection matrix with a [0, 1] depth range.
+    /// Works with reverse-Z and infinite far planes alike: the far plane of an infinite projection contains
+    /// every point.
+    pub fn from_view_proj(view_proj: &Mat4) -> Self {
+        let row_x = view_proj.row(0);
+        let row_y = view_proj.row(1);
+        let row_z = view_proj.row(2);
+        let row_w = view_proj.row(3);
+        let mut planes = [
+            row_w + row_x,
+            row_w - row_x,
+            row_w + row_y,
+            row_w - row_y,
+            row_z,
+            row_w - row_z,
+        ];
+        for plane in &mut planes {
+            let normal_length = plane.truncate().length();
+            if normal_length > f32::EPSILON {
+                *plane /= normal_length;
+            }
+        }
+        return Frustum { planes };
+    }
+
+    /// Whether any part of [aabb] may be inside of the frustum.
+    /// Conservative: boxes close to the frustum corners may be reported as intersecting although they are not.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        let center = aabb.center();
+        let half_extents = aabb.half_extents();
+        for plane in &self.planes {
+            let normal = Vec3A::from(plane.truncate());
+            let distance = normal.dot(center) + plane.w;
+            let projected_radius = normal.abs().dot(half_extents);
+            if distance < -projected_radius {
+                return false;
+            }
+        }
+        return true;
+    }
+
+    /// Whether any part of the sphere at [center] with [radius] may be inside of the frustum.
+    pub fn intersects_sphere(&self, center: Vec3A, radius: f32) -> bool {
+        return self.planes.iter()
+            .all(|plane| Vec3A::from(plane.truncate()).dot(center) + plane.w >= -radius);
+    }
+}
+
+/// Statistics of the culling performed for the last frame.
+/// Only render nodes with a bounding box are tested against the camera's view. Nodes without one, eg. cameras and
+/// lights, are always rendered and are counted as neither visible nor culled.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CullingStats {
+    /// The number of render nodes in the scene.
+    pub total_nodes: usize,
+    /// The number of render nodes with a bounding box inside of the active camera's view.
+    pub visible_nodes: usize,
+    /// The number of render nodes with a bounding box outside of the active camera's view.
+    pub culled_nodes: usize,
+    /// The number of render nodes whose dirty state was left unresolved, because they are neither visible
+    /// nor casting a visible shadow.
+    pub skipped_nodes: usize,
+    /// The number of render nodes rendered into shadow maps, summed over all shadow maps.
+    pub shadow_caster_draws: usize,
+}
+
+/// The render nodes needed for a frame, see [cull_nodes()].
+pub(crate) struct CullingResult {
+    /// The nodes rendered from the active camera, including all nodes without a bounding box.
+    pub(crate) visible_nodes: HashSet<RenderNodeHandle>,
+    /// The nodes rendered into each shadow map, sorted by handle.
+    pub(crate) shadow_casters: Vec<Vec<RenderNodeHandle>>,
+    shadow_caster_set: HashSet<RenderNodeHandle>,
+    pub(crate) stats: CullingStats,
+}
+
+impl CullingResult {
+    /// Whether [handle] is rendered from the camera or into any shadow map.
+    pub(crate) fn is_rendered(&self, handle: &RenderNodeHandle) -> bool {
+        return self.visible_nodes.contains(handle) || self.shadow_caster_set.contains(handle);
+    }
+}
+
+/// Culls [nodes], given by their handle and bounding box, against the camera's view and the views of the shadow
+/// maps. Without a camera frustum, all nodes are rendered from the camera.
+pub(crate) fn cull_nodes<I>(nodes: I, camera_frustum: Option<&Frustum>, shadow_frusta: &[Frustum]) -> CullingResult
+    where I: IntoIterator<Item=(RenderNodeHandle, Option<Aabb>)> {
+    let mut visible_nodes = HashSet::new();
+    let mut shadow_casters: Vec<Vec<RenderNodeHandle>> = vec![Vec::new(); shadow_frusta.len()];
+    let mut shadow_caster_set = HashSet::new();
+    let mut stats = CullingStats::default();
+    for (handle, bounding_box) in nodes {
+        stats.total_nodes += 1;
+        let bounding_box = match bounding_box {
+            Some(bounding_box) => bounding_box,
+            None => {
+                visible_nodes.insert(handle);
+                continue;
+            }
+        };
+        match camera_frustum {
+            Some(frustum) if frustum.intersects_aabb(&bounding_box) => {
+                visible_nodes.insert(handle);
+                stats.visible_nodes += 1;
+            }
+            Some(_) => stats.culled_nodes += 1,
+            None => {
+                visible_nodes.insert(handle);
+            }
+        }
+        for (layer, frustum) in shadow_frusta.iter().enumerate() {
+            if frustum.intersects_aabb(&bounding_box) {
+                shadow_casters[layer].push(handle);
+                shadow_caster_set.insert(handle);
+            }
+        }
+        if !visible_nodes.contains(&handle) && !shadow_caster_set.contains(&handle) {
+            stats.skipped_nodes += 1;
+        }
+    }
+
+    for casters in &mut shadow_casters {
+        casters.sort();
+    }
+    stats.shadow_caster_draws = shadow_casters.iter().map(|casters| casters.len()).sum();
+
+    return CullingResult { visible_nodes, shadow_casters, shadow_caster_set, stats };
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Mat4, Quat, Vec3};
+    use super::*;
+
+    fn camera_view_proj(far: Option<f32>) -> Mat4 {
+        let view = Mat4::look_at_lh(Vec3::ZERO, Vec3::Z, Vec3::Y);
+        let projection = match far {
+            Some(far) => Mat4::perspective_lh(90.0_f32.to_radians(), 1.0, far, 0.1),
+            None => Mat4::perspective_infinite_reverse_lh(90.0_f32.to_radians(), 1.0, 0.1),
+        };
+        return projection * view;
+    }
+
+    fn unit_box_at(center: Vec3A) -> Aabb {
+        return Aabb::new(center - Vec3A::splat(0.5), center + Vec3A::splat(0.5));
+    }
+
+    #[test]
+    fn frustum_contains_boxes_in_front_of_the_camera() {
+        let frustum = Frustum::from_view_proj(&camera_view_proj(Some(100.0)));
+        assert!(frustum.intersects_aabb(&unit_box_at(Vec3A::new(0.0, 0.0, 10.0))));
+        assert!(frustum.intersects_aabb(&unit_box_at(Vec3A::new(9.0, 0.0, 10.0))));
+        // Straddles the near plane
+        assert!(frustum.intersects_aabb(&unit_box_at(Vec3A::ZERO)));
+    }
+
+    #[test]
+    fn frustum_culls_boxes_outside_of_the_view() {
+        let frustum = Frustum::from_view_proj(&camera_view_proj(Some(100.0)));
+        // Behind the camera
+        assert!(!frustum.intersects_aabb(&unit_box_at(Vec3A::new(0.0, 0.0, -10.0))));
+        // Outside of the 90 degree field of view
+        assert!(!frustum.intersects_aabb(&unit_box_at(Vec3A::new(20.0, 0.0, 10.0))));
+        assert!(!frustum.intersects_aabb(&unit_box_at(Vec3A::new(0.0, -20.0, 10.0))));
+        // Beyond the far plane
+        assert!(!frustum.intersects_aabb(&unit_box_at(Vec3A::new(0.0, 0.0, 200.0))));
+    }
+
+    #[test]
+    fn infinite_frustum_has_no_far_plane() {
+        let frustum = Frustum::from_view_proj(&camera_view_proj(None));
+        assert!(frustum.intersects_aabb(&unit_box_at(Vec3A::new(0.0, 0.0, 100000.0))));
+        assert!(!frustum.intersects_aabb(&unit_box_at(Vec3A::new(0.0, 0.0, -10.0))));
+        assert!(frustum.intersects_sphere(Vec3A::new(0.0, 0.0, 5000.0), 1.0));
+        assert!(!frustum.intersects_sphere(Vec3A::new(0.0, 0.0, -5.0), 1.0));
+    }
+
+    #[test]
+    fn transformed_aabb_contains_the_transformed_corners() {
+        let aabb = Aabb::new(Vec3A::new(-1.0, -2.0, -3.0), Vec3A::new(1.0, 2.0, 3.0));
+        let transform = Mat4::from_scale_rotation_translation(Vec3::splat(2.0), Quat::from_rotation_y(0.7), Vec3::new(5.0, 0.0, 1.0));
+        let transformed = aabb.transformed(&transform);
+        for x in [-1.0, 1.0] {
+            for y in [-2.0, 2.0] {
+                for z in [-3.0, 3.0] {
+                    let corner = Vec3A::from(transform.transform_point3(Vec3::new(x, y, z)));
+                    assert!(corner.cmpge(transformed.min - Vec3A::splat(1e-4)).all());
+                    assert!(corner.cmple(transformed.max + Vec3A::splat(1e-4)).all());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn counts_only_bounded_nodes_as_visible_or_culled() {
+        let frustum = Frustum::from_view_proj(&camera_view_proj(Some(100.0)));
+        let shadow_frustum = Frustum::from_view_proj(&camera_view_proj(Some(20.0)));
+        let handle = |index: u64| RenderNodeHandle::from_bits(index);
+        let nodes = vec![
+            // A camera or a light
+            (handle(0), None),
+            (handle(1), Some(unit_box_at(Vec3A::new(0.0, 0.0, 10.0)))),
+            (handle(2), Some(unit_box_at(Vec3A::new(0.0, 0.0, 50.0)))),
+            (handle(3), Some(unit_box_at(Vec3A::new(0.0, 0.0, -10.0)))),
+        ];
+
+        let result = cull_nodes(nodes, Some(&frustum), &[shadow_frustum]);
+        assert_eq!(result.stats, CullingStats {
+            total_nodes: 4,
+            visible_nodes: 2,
+            culled_nodes: 1,
+            skipped_nodes: 1,
+            shadow_caster_draws: 1,
+        });
+        // Nodes without a bounding box are still rendered
+        assert!(result.visible_nodes.contains(&handle(0)));
+        assert_eq!(result.shadow_casters, vec![vec![handle(1)]]);
+        assert!(result.is_rendered(&handle(2)));
+        assert!(!result.is_rendered(&handle(3)));
+    }
+
+    #[test]
+    fn aabb_from_points() {
+        assert_eq!(Aabb::from_points(Vec::new()), None);
+        let aabb = Aabb::from_points(vec![Vec3A::new(1.0, -1.0, 0.0), Vec3A::new(-2.0, 3.0, 0.5)]).unwrap();
+        assert_eq!(aabb, Aabb::new(Vec3A::new(-2.0, -1.0, 0.0), Vec3A::new(1.0, 3.0, 0.5)));
+    }
+}
@@ -1,63 +1,464 @@
 use std::any::{Any, TypeId};
 use std::collections::HashMap;
 use std::f32::consts::PI;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
 use glam::{DQuat, EulerRot, Quat, Vec3, Vec3A, Vec4};
-use specs::{Component, VecStorage, HashMapStorage, NullStorage, Entity, World, WorldExt, Builder, WriteStorage, ReadStorage, System, Read, Join, ParJoin, DispatcherBuilder, Dispatcher};
+use newton::{BodySettings, Collider, EventCollider, PhysicsEvent, PhysicsObject, PhysicsWorld, QueryFilter, QueryHit};
+use newton::rapier3d::math::{Isometry, Point, Real};
+use newton::rapier3d::na::{Quaternion, Translation3, UnitQuaternion, Vector3};
+use newton::rapier3d::prelude::RigidBodyHandle;
+use specs::{Component, VecStorage, HashMapStorage, Entity, Entities, World, WorldExt, Builder, WriteStorage, ReadStorage, System, Read, Write, Join, ParJoin, DispatcherBuilder, Dispatcher};
+use specs::hibitset::BitSet;
+use specs::shred::Resource;
+use specs::shrev::{EventChannel, ReaderId};
+use specs::storage::MaskedStorage;
 use specs::prelude::ParallelIterator;
-use crate::camera::{CameraRenderNode, PerspectiveCamera};
+use crate::camera::{CameraRenderNode, OrthographicCamera, PerspectiveCamera};
+use crate::gltf_loader::{self, Model, ModelLoadError};
+use crate::handle::{GenerationalHandle, HandleAllocator};
+use crate::light::{Light, LightRenderNode};
+use crate::mesh::MeshRenderNode;
 use crate::scene::{RenderNodeHandle, RenderScene};
+use crate::snapshot::{EntityRenderState, EntitySnapshot, RenderSnapshot};
+use crate::timestep::FixedTimestep;
+use crate::transform::Transform;
 
-#[derive(Component, Debug)]
+/// The position, rotation and scale of an entity, relative to its parent entity if it has a [ParentComponent].
+/// Synced into the local transform of the entity's render node, see [ECSEntity::snapshot()].
+#[derive(Component, Copy, Clone, Debug, PartialEq)]
 #[storage(VecStorage)]
-struct PositionComponent {
-    pub position: Vec3A,
+pub struct TransformComponent {
+    pub transform: Transform,
 }
 
-#[derive(Component, Debug)]
+/// The linear velocity of an entity, integrated into its [TransformComponent] every tick.
+/// unit: world units per second
+#[derive(Component, Copy, Clone, Debug, PartialEq)]
 #[storage(VecStorage)]
-struct VelocityComponent {
+pub struct VelocityComponent {
     pub velocity: Vec3A,
 }
 
-#[derive(Component, Debug)]
+/// The entity whose render node is the parent of this entity's render node, see [ECSWorld::set_parent()].
+#[derive(Component, Copy, Clone, Debug, PartialEq)]
+#[storage(HashMapStorage)]
+pub struct ParentComponent {
+    pub parent: ECSEntityHandle,
+}
+
+/// The glTF file the render nodes of a [MeshEntity] were spawned from, see [MeshEntity::load_model()].
+#[derive(Component, Clone, Debug, PartialEq)]
+#[storage(HashMapStorage)]
+pub struct ModelComponent {
+    pub path: PathBuf,
+}
+
+/// Makes the entity a rigid body simulated by the [PhysicsWorld] resource, shaped by its [ColliderComponent].
+/// The entity's [TransformComponent] is written back from the body every tick, so it should not have a parent.
+/// Setting its transform teleports the body, setting its [VelocityComponent] changes the body's velocity.
+/// The scale of the transform is not applied to the collider.
+/// Changing the settings recreates the body. The gravity and solver of the world are configured by replacing the
+/// [PhysicsWorld] resource, see [ECSWorldBuilder::with_resource()].
+#[derive(Component, Copy, Clone, Debug, PartialEq)]
+#[storage(HashMapStorage)]
+pub struct RigidBodyComponent {
+    pub settings: BodySettings,
+}
+
+impl RigidBodyComponent {
+    /// A body moved by the simulation, with the default [BodySettings].
+    pub fn dynamic() -> Self {
+        return RigidBodyComponent { settings: BodySettings::default() };
+    }
+
+    /// A body never moved by the simulation, with the default [BodySettings] otherwise.
+    pub fn fixed() -> Self {
+        return RigidBodyComponent { settings: BodySettings { is_static: true, ..BodySettings::default() } };
+    }
+}
+
+/// The shape of the entity's [RigidBodyComponent].
+#[derive(Component, Clone, Debug)]
+#[storage(HashMapStorage)]
+pub struct ColliderComponent {
+    pub collider: Arc<dyn Collider>,
+}
+
+impl ColliderComponent {
+    pub fn new<C: Collider + 'static>(collider: C) -> Self {
+        return ColliderComponent { collider: Arc::new(collider) };
+    }
+
+    /// Whether both components share the same collider.
+    fn is_same(&self, other: &ColliderComponent) -> bool {
+        let collider = Arc::as_ptr(&self.collider) as *const ();
+        let other_collider = Arc::as_ptr(&other.collider) as *const ();
+        return collider == other_collider;
+    }
+}
+
+/// The handle of the [ECSEntity] owning a specs entity, eg. to map physics events to entities.
+#[derive(Component, Copy, Clone, Debug, PartialEq)]
 #[storage(VecStorage)]
-struct RotationComponent {
-    /// [yaw], [pitch] and [roll] determine [quaternion]
-    /// unit: radians
-    pub yaw: f32,
-    pub pitch: f32,
-    pub roll: f32,
+struct EntityHandleComponent {
+    pub entity_handle: ECSEntityHandle,
+}
+
+/// Something that happened between the colliders of two entities during a tick, see
+/// [ECSWorld::register_collision_reader()].
+/// Events involving bodies that do not belong to an entity anymore are not reported.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CollisionEvent {
+    ContactStarted { entity1: ECSEntityHandle, entity2: ECSEntityHandle },
+    ContactStopped { entity1: ECSEntityHandle, entity2: ECSEntityHandle },
+    /// [entity] started intersecting the sensor of [sensor], see [BodySettings::is_sensor].
+    SensorEntered { sensor: ECSEntityHandle, entity: ECSEntityHandle },
+    SensorExited { sensor: ECSEntityHandle, entity: ECSEntityHandle },
+    /// The contact force between the entities exceeded the [BodySettings::contact_force_threshold] of either.
+    /// unit: [force] is in mass times world units per second squared
+    ContactForce { entity1: ECSEntityHandle, entity2: ECSEntityHandle, force: f32 },
+}
 
-    pub quaternion: Quat,
+/// The transform of an entity before the last tick. Render nodes are interpolated between the previous and the
+/// current transform, as frames are rendered in between ticks, see [RenderSnapshot].
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+struct PreviousTransformComponent {
+    pub transform: Transform,
 }
 
+/// The duration of a tick.
+/// unit: seconds
 #[derive(Default)]
 struct DeltaTimeResource(pub f32);
 
+struct StorePreviousTransformSystem;
+
+impl<'a> System<'a> for StorePreviousTransformSystem {
+    type SystemData = (Entities<'a>,
+                       ReadStorage<'a, TransformComponent>,
+                       WriteStorage<'a, PreviousTransformComponent>);
+
+    fn run(&mut self, (entities, transforms, mut previous_transforms): Self::SystemData) {
+        for (entity, transform) in (&entities, &transforms).join() {
+            previous_transforms.insert(entity, PreviousTransformComponent { transform: transform.transform }).unwrap();
+        }
+    }
+}
+
+/// Moves entities without a [RigidBodyComponent] by their velocity. Rigid bodies are moved by the [PhysicsSystem].
 struct NewtonianExplicitIntegratorSystem;
 
 impl<'a> System<'a> for NewtonianExplicitIntegratorSystem {
     type SystemData = (Read<'a, DeltaTimeResource>,
                        ReadStorage<'a, VelocityComponent>,
-                       WriteStorage<'a, PositionComponent>);
+                       ReadStorage<'a, RigidBodyComponent>,
+                       WriteStorage<'a, TransformComponent>);
 
-    fn run(&mut self, (delta_time, velocities, mut positions): Self::SystemData) {
-        (&velocities, &mut positions)
+    fn run(&mut self, (delta_time, velocities, rigid_bodies, mut transforms): Self::SystemData) {
+        (&velocities, !&rigid_bodies, &mut transforms)
             .par_join()
-            .for_each(|(velocity, position)| {
-                position.position += velocity.velocity * delta_time.0;
+            .for_each(|(velocity, _, transform)| {
+                transform.transform.translation += velocity.velocity * delta_time.0;
             });
     }
 }
 
-pub type ECSEntityHandle = u64;
+/// The [PhysicsObject] of an entity with a [RigidBodyComponent] and a [ColliderComponent].
+struct PhysicsBody {
+    object: PhysicsObject,
+    /// The components the object was created from. The object is recreated when they change.
+    rigid_body: RigidBodyComponent,
+    collider: ColliderComponent,
+    /// The transform and velocity written back after the last step.
+    /// Differing components have been set by the game and are applied to the body. The velocity is only applied
+    /// if the entity has a [VelocityComponent].
+    transform: Transform,
+    velocity: Vec3A,
+}
+
+/// The physics bodies of all entities, keyed by their specs entity.
+#[derive(Default)]
+struct PhysicsBodiesResource {
+    bodies: HashMap<Entity, PhysicsBody>,
+    /// The entities of [Self::bodies], keyed by their Rapier body, eg. to map query hits to entities.
+    entities: HashMap<RigidBodyHandle, Entity>,
+}
+
+/// Keeps the bodies of the [PhysicsWorld] in sync with the [RigidBodyComponent]s, steps the world, writes the
+/// poses of the bodies back into the [TransformComponent]s and publishes the [CollisionEvent]s of the step.
+struct PhysicsSystem;
+
+impl<'a> System<'a> for PhysicsSystem {
+    type SystemData = (Entities<'a>,
+                       Read<'a, DeltaTimeResource>,
+                       Write<'a, PhysicsWorld>,
+                       Write<'a, PhysicsBodiesResource>,
+                       Write<'a, EventChannel<CollisionEvent>>,
+                       ReadStorage<'a, EntityHandleComponent>,
+                       ReadStorage<'a, RigidBodyComponent>,
+                       ReadStorage<'a, ColliderComponent>,
+                       WriteStorage<'a, TransformComponent>,
+                       WriteStorage<'a, VelocityComponent>);
+
+    fn run(&mut self, (entities, delta_time, mut physics_world, mut physics_bodies, mut collision_events, entity_handles, rigid_bodies, colliders, mut transforms, mut velocities): Self::SystemData) {
+        let PhysicsBodiesResource { bodies, entities: body_entities } = &mut *physics_bodies;
+
+        // Remove the bodies of deleted entities and of entities whose components changed
+        {
+            let stale_entities: Vec<Entity> = bodies.iter()
+                .filter(|(entity, body)| {
+                    let is_current = entities.is_alive(**entity)
+                        && rigid_bodies.get(**entity) == Some(&body.rigid_body)
+                        && colliders.get(**entity).map_or(false, |collider| collider.is_same(&body.collider))
+                        && transforms.get(**entity).is_some();
+                    return !is_current;
+                })
+                .map(|(entity, _)| *entity)
+                .collect();
+            for entity in stale_entities {
+                let body = bodies.remove(&entity).unwrap();
+                body_entities.remove(&body.object.rigid_body_handle());
+                body.object.remove(&mut physics_world);
+            }
+        }
+
+        // Add bodies for new entities and apply transforms and velocities set since the last step
+        {
+            for (entity, rigid_body, collider, transform) in (&entities, &rigid_bodies, &colliders, &transforms).join() {
+                // Bodies of entities without a velocity keep the velocity they got from the simulation
+                let velocity = velocities.get(entity).map(|velocity| velocity.velocity);
+                match bodies.get_mut(&entity) {
+                    Some(body) => {
+                        if body.transform != transform.transform {
+                            body.object.set_position(&mut physics_world, isometry_from_transform(&transform.transform));
+                            body.transform = transform.transform;
+                        }
+                        if let Some(velocity) = velocity {
+                            if body.velocity != velocity {
+                                body.object.set_linear_velocity(&mut physics_world, Vector3::new(velocity.x, velocity.y, velocity.z));
+                                body.velocity = velocity;
+                            }
+                        }
+                    }
+                    None => {
+                        let isometry = isometry_from_transform(&transform.transform);
+                        let object = PhysicsObject::builder(collider.collider.clone())
+                            .with_settings(rigid_body.settings)
+                            .with_position(isometry.translation.vector)
+                            .with_orientation(isometry.rotation.scaled_axis())
+                            .build(&mut physics_world);
+                        if let Some(velocity) = velocity {
+                            object.set_linear_velocity(&mut physics_world, Vector3::new(velocity.x, velocity.y, velocity.z));
+                        }
+                        body_entities.insert(object.rigid_body_handle(), entity);
+                        bodies.insert(entity, PhysicsBody {
+                            object,
+                            rigid_body: *rigid_body,
+                            collider: collider.clone(),
+                            transform: transform.transform,
+                            velocity: velocity.unwrap_or(Vec3A::ZERO),
+                        });
+                    }
+                }
+            }
+        }
+
+        physics_world.step(delta_time.0);
+
+        // Map the colliders of the events to entities
+        {
+            let entity_handle = |event_collider: &EventCollider| -> Option<ECSEntityHandle> {
+                let entity = body_entities.get(&event_collider.rigid_body?)?;
+                return entity_handles.get(*entity).map(|entity_handle| entity_handle.entity_handle);
+            };
+            let events: Vec<CollisionEvent> = physics_world.events().iter()
+                .filter_map(|event| {
+                    let collision_event = match event {
+                        PhysicsEvent::ContactStarted { collider1, collider2 } => CollisionEvent::ContactStarted {
+                            entity1: entity_handle(collider1)?,
+                            entity2: entity_handle(collider2)?,
+                        },
+                        PhysicsEvent::ContactStopped { collider1, collider2 } => CollisionEvent::ContactStopped {
+                            entity1: entity_handle(collider1)?,
+                            entity2: entity_handle(collider2)?,
+                        },
+                        PhysicsEvent::SensorEntered { sensor, collider } => CollisionEvent::SensorEntered {
+                            sensor: entity_handle(sensor)?,
+                            entity: entity_handle(collider)?,
+                        },
+                        PhysicsEvent::SensorExited { sensor, collider } => CollisionEvent::SensorExited {
+                            sensor: entity_handle(sensor)?,
+                            entity: entity_handle(collider)?,
+                        },
+                        PhysicsEvent::ContactForce { collider1, collider2, force } => CollisionEvent::ContactForce {
+                            entity1: entity_handle(collider1)?,
+                            entity2: entity_handle(collider2)?,
+                            force: *force,
+                        },
+                    };
+                    return Some(collision_event);
+                })
+                .collect();
+            collision_events.iter_write(events);
+        }
+
+        // Write the poses and velocities of the bodies back
+        {
+            for (entity, body) in bodies.iter_mut() {
+                if body.rigid_body.settings.is_static {
+                    continue;
+                }
+                let isometry = body.object.position(&physics_world);
+                let transform = &mut transforms.get_mut(*entity).unwrap().transform;
+                transform.translation = Vec3A::new(isometry.translation.x, isometry.translation.y, isometry.translation.z);
+                transform.rotation = Quat::from_xyzw(isometry.rotation.i, isometry.rotation.j, isometry.rotation.k, isometry.rotation.w);
+                body.transform = *transform;
+
+                let linear_velocity = body.object.linear_velocity(&physics_world);
+                body.velocity = Vec3A::new(linear_velocity.x, linear_velocity.y, linear_velocity.z);
+                if let Some(velocity) = velocities.get_mut(*entity) {
+                    velocity.velocity = body.velocity;
+                }
+            }
+        }
+    }
+}
+
+/// The position and orientation of [transform], without its scale.
+fn isometry_from_transform(transform: &Transform) -> Isometry<Real> {
+    let translation = Translation3::new(transform.translation.x, transform.translation.y, transform.translation.z);
+    let rotation = transform.rotation;
+    let rotation = UnitQuaternion::new_normalize(Quaternion::new(rotation.w, rotation.x, rotation.y, rotation.z));
+    return Isometry::from_parts(translation, rotation);
+}
+
+/// An entity hit by a scene query, see [ECSWorld::raycast()].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EntityHit {
+    pub entity: ECSEntityHandle,
+    /// The point where the entity's collider was hit.
+    pub point: Vec3A,
+    /// The surface normal of the entity's collider at [EntityHit::point].
+    pub normal: Vec3A,
+    /// How far the ray or the shape travelled until the hit.
+    /// unit: world units
+    pub distance: f32,
+}
+
+/// A type used to reference an entity in the [ECSWorld].
+/// Handles of removed entities are stale and never refer to entities added later.
+pub type ECSEntityHandle = GenerationalHandle;
+
+/// The name of the built-in system storing the transforms before a tick, in [SystemStage::PreUpdate].
+pub const STORE_PREVIOUS_TRANSFORM_SYSTEM: &str = "store_previous_transform_system";
+/// The name of the built-in system moving flying cameras, in [SystemStage::Update].
+pub const FLYING_CAMERA_SYSTEM: &str = "flying_camera_system";
+/// The name of the built-in system integrating velocities into transforms, in [SystemStage::Update].
+pub const POSITION_INTEGRATOR_SYSTEM: &str = "position_integrator";
+/// The name of the built-in system stepping the [PhysicsWorld], in [SystemStage::Update] after the
+/// [POSITION_INTEGRATOR_SYSTEM].
+pub const PHYSICS_SYSTEM: &str = "physics_system";
+
+/// When a system runs.
+/// The systems of [SystemStage::PreUpdate], [SystemStage::Update] and [SystemStage::PostUpdate] run every tick,
+/// in that order. The systems of [SystemStage::PreRender] run once after the ticks of an update, before the
+/// render snapshot is taken, see [ECSWorld::simulate()].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SystemStage {
+    PreUpdate,
+    Update,
+    PostUpdate,
+    PreRender,
+}
+
+impl SystemStage {
+    pub const ALL: [SystemStage; 4] = [SystemStage::PreUpdate, SystemStage::Update, SystemStage::PostUpdate, SystemStage::PreRender];
+}
+
+/// The systems of one [SystemStage].
+struct StageDispatcher {
+    stage: SystemStage,
+    dispatcher: Dispatcher<'static, 'static>,
+}
+
+// A Dispatcher is only !Send because it may own thread-local systems, which the [ECSWorldBuilder] never adds.
+// All other systems are Send, see [ECSWorldBuilder::with_system()].
+unsafe impl Send for StageDispatcher {}
+
+/// Configures the components, resources and systems of an [ECSWorld].
+/// Starts out with the built-in components, resources and systems, see [ECSWorld::builder()].
+pub struct ECSWorldBuilder {
+    world: World,
+    dispatcher_builders: Vec<(SystemStage, DispatcherBuilder<'static, 'static>)>,
+}
+
+impl ECSWorldBuilder {
+    /// Registers the storage of [C], so entities can be created with it.
+    /// Components read or written by a system are registered along with it.
+    pub fn with_component<C: Component>(mut self) -> Self where C::Storage: Default {
+        self.world.register::<C>();
+        return self;
+    }
+
+    /// Inserts [resource], replacing a resource of the same type.
+    pub fn with_resource<R: Resource>(mut self, resource: R) -> Self {
+        self.world.insert(resource);
+        return self;
+    }
+
+    /// Adds [system] to [stage]. The system runs after the systems named in [dependencies], which must have been
+    /// added to the same stage before. Systems of earlier stages always run before.
+    /// [name] must be unique within the stage.
+    pub fn with_system<S>(mut self, system: S, name: &str, stage: SystemStage, dependencies: &[&str]) -> Self
+        where S: for<'c> System<'c> + Send + 'static {
+        let (_, dispatcher_builder) = self.dispatcher_builders.iter_mut()
+            .find(|(builder_stage, _)| *builder_stage == stage)
+            .unwrap();
+        dispatcher_builder.add(system, name, dependencies);
+        return self;
+    }
+
+    pub fn build(self) -> ECSWorld {
+        let mut world = self.world;
+        let dispatchers = self.dispatcher_builders.into_iter()
+            .map(|(stage, dispatcher_builder)| {
+                let mut dispatcher = dispatcher_builder.build();
+                // Registers the components and resources the systems access
+                dispatcher.setup(&mut world);
+                return StageDispatcher { stage, dispatcher };
+            })
+            .collect();
+
+        return ECSWorld {
+            world,
+            ecs_entities: HashMap::new(),
+            camera_handles: Vec::new(),
+            light_handles: Vec::new(),
+            entity_handle_allocator: HandleAllocator::new(),
+            specs_entity_handles: HashMap::new(),
+            dispatchers,
+            timestep: FixedTimestep::default(),
+            tick: 0,
+        };
+    }
+}
 
 pub struct ECSWorld {
     world: World,
     ecs_entities: HashMap<ECSEntityHandle, Box<dyn ECSEntity>>,
     camera_handles: Vec<ECSEntityHandle>,
-    next_entiy_handle: ECSEntityHandle,
-    dispatcher: Dispatcher<'static, 'static>,
+    light_handles: Vec<ECSEntityHandle>,
+    entity_handle_allocator: HandleAllocator,
+    /// The handles of the specs entities of [Self::ecs_entities].
+    specs_entity_handles: HashMap<Entity, ECSEntityHandle>,
+    /// Ordered by stage
+    dispatchers: Vec<StageDispatcher>,
+    timestep: FixedTimestep,
+    /// The number of ticks simulated so far.
+    tick: u64,
 }
 
 
@@ -109,34 +510,89 @@ impl MovementInput {
 }
 
 impl ECSWorld {
+    /// An ECS world with only the built-in components, resources and systems.
     pub fn new() -> ECSWorld {
+        return ECSWorld::builder().build();
+    }
+
+    /// Starts configuring an ECS world with custom components, resources and systems in addition to the
+    /// built-in ones.
+    pub fn builder() -> ECSWorldBuilder {
         let mut world = World::new();
 
         world.insert(DeltaTimeResource(0.0));
         world.insert(MovementInputResource::new());
+        world.insert(PhysicsWorld::new());
+        world.insert(PhysicsBodiesResource::default());
+        world.insert(EventChannel::<CollisionEvent>::new());
 
-        world.register::<PositionComponent>();
+        world.register::<EntityHandleComponent>();
+        world.register::<TransformComponent>();
         world.register::<VelocityComponent>();
+        world.register::<PreviousTransformComponent>();
+        world.register::<RigidBodyComponent>();
+        world.register::<ColliderComponent>();
 
-        let dispatcher = DispatcherBuilder::new()
-            .with(FlyingCameraSystem, "flying_camera_system", &[])
-            .with(NewtonianExplicitIntegratorSystem, "position_integrator", &["flying_camera_system"])
-            .build();
-
-        return ECSWorld {
+        let builder = ECSWorldBuilder {
             world,
-            ecs_entities: HashMap::new(),
-            camera_handles: Vec::new(),
-            next_entiy_handle: 1,
-            dispatcher,
+            dispatcher_builders: SystemStage::ALL.iter()
+                .map(|stage| (*stage, DispatcherBuilder::new()))
+                .collect(),
+        };
+        return builder
+            .with_system(StorePreviousTransformSystem, STORE_PREVIOUS_TRANSFORM_SYSTEM, SystemStage::PreUpdate, &[])
+            .with_system(FlyingCameraSystem, FLYING_CAMERA_SYSTEM, SystemStage::Update, &[])
+            .with_system(NewtonianExplicitIntegratorSystem, POSITION_INTEGRATOR_SYSTEM, SystemStage::Update, &[FLYING_CAMERA_SYSTEM])
+            .with_system(PhysicsSystem, PHYSICS_SYSTEM, SystemStage::Update, &[POSITION_INTEGRATOR_SYSTEM]);
+    }
+
+    /// Advances the simulation as [Self::simulate()] does, then updates the render nodes of all entities to their
+    /// transforms interpolated between the last two ticks.
+    /// Used when the simulation runs on the render thread.
+    pub fn update(&mut self, delta_time: f64, movement_input: MovementInput, render_scene: &mut RenderScene) -> u32 {
+        let ticks = self.simulate(delta_time, movement_input);
+        self.snapshot().apply(self.timestep.alpha(), render_scene);
+        return ticks;
+    }
+
+    /// Advances the simulation by [delta_time] seconds of real time, in as many fixed ticks as fit into the time
+    /// carried over from previous updates plus [delta_time].
+    /// Returns the number of ticks run. The relative mouse movement of [movement_input] is only applied by the
+    /// first tick, so it must be accumulated until a tick runs.
+    pub fn simulate(&mut self, delta_time: f64, movement_input: MovementInput) -> u32 {
+        let ticks = self.timestep.advance(delta_time);
+
+        let mut movement_input = movement_input;
+        for _ in 0..ticks {
+            self.tick(movement_input.clone());
+            movement_input.delta_yaw = 0.0;
+            movement_input.delta_pitch = 0.0;
+        }
+        if ticks > 0 {
+            self.dispatch_stage(SystemStage::PreRender);
+        }
+        return ticks;
+    }
+
+    /// Copies the render-relevant state of all entities.
+    pub fn snapshot(&self) -> RenderSnapshot {
+        return RenderSnapshot {
+            tick: self.tick,
+            tick_duration: self.timestep.tick_duration(),
+            alpha: self.timestep.alpha(),
+            taken_at: Instant::now(),
+            entities: self.ecs_entities.values()
+                .filter_map(|entity| entity.snapshot(&self.world))
+                .collect(),
         };
     }
 
-    pub fn update(&mut self, delta_time: f64, movement_input: MovementInput, render_scene: &mut RenderScene) {
+    /// Runs the ECS systems once, advancing the simulation by one tick.
+    fn tick(&mut self, movement_input: MovementInput) {
         // Update delta time resource
         {
             let mut delta = self.world.write_resource::<DeltaTimeResource>();
-            *delta = DeltaTimeResource(delta_time as f32);
+            *delta = DeltaTimeResource(self.timestep.tick_duration() as f32);
         }
 
         // Update movement input resource
@@ -146,33 +602,256 @@ impl ECSWorld {
         }
         // Update ECS
         {
-            self.dispatcher.dispatch(&self.world);
-            self.world.maintain();
-        }
-        // Update scene
-        {
-            for entity in &mut self.ecs_entities.values_mut() {
-                entity.update_render_node(&self.world, render_scene);
-            }
+            self.dispatch_stage(SystemStage::PreUpdate);
+            self.dispatch_stage(SystemStage::Update);
+            self.dispatch_stage(SystemStage::PostUpdate);
         }
+        self.tick += 1;
+    }
+
+    /// Runs the systems of [stage]. Entities created or deleted by them are visible to the next stage.
+    fn dispatch_stage(&mut self, stage: SystemStage) {
+        let stage_dispatcher = self.dispatchers.iter_mut()
+            .find(|stage_dispatcher| stage_dispatcher.stage == stage)
+            .unwrap();
+        stage_dispatcher.dispatcher.dispatch(&self.world);
+        self.world.maintain();
+    }
+
+    /// The specs world holding all components and resources, eg. to add custom components to entities.
+    pub fn specs_world(&self) -> &World {
+        return &self.world;
+    }
+
+    pub fn specs_world_mut(&mut self) -> &mut World {
+        return &mut self.world;
+    }
+
+    pub fn timestep(&self) -> &FixedTimestep {
+        return &self.timestep;
+    }
+
+    /// Allows configuring the tick rate and the maximum number of ticks per update.
+    pub fn timestep_mut(&mut self) -> &mut FixedTimestep {
+        return &mut self.timestep;
     }
 
     pub fn add_entity<T: ECSEntity + 'static>(&mut self, entity: Box<T>) -> ECSEntityHandle {
+        let entity_handle = self.entity_handle_allocator.allocate();
         // if camera, add to camera list
         if TypeId::of::<T>() == TypeId::of::<CameraEntity>() {
-            self.camera_handles.push(self.next_entiy_handle);
+            self.camera_handles.push(entity_handle);
+        }
+        // if light, add to light list
+        if TypeId::of::<T>() == TypeId::of::<LightEntity>() {
+            self.light_handles.push(entity_handle);
         }
         // add to ecs
-        let entity_handle = self.next_entiy_handle;
+        self.world.write_storage::<EntityHandleComponent>()
+            .insert(entity.specs_entity(), EntityHandleComponent { entity_handle })
+            .unwrap();
+        self.specs_entity_handles.insert(entity.specs_entity(), entity_handle);
         self.ecs_entities.insert(entity_handle, entity);
-        self.next_entiy_handle += 1;
         return entity_handle;
     }
 
+    /// Despawns the entity, deleting its specs entity and removing its render node from [render_scene].
+    /// The entities attached to it with [Self::set_parent()] are despawned along with it, as their render nodes
+    /// are removed together with the render node of the entity.
+    /// Returns false if [entity_handle] is stale.
+    pub fn remove_entity(&mut self, entity_handle: &ECSEntityHandle, render_scene: &mut RenderScene) -> bool {
+        return self.remove_entity_with(entity_handle, &mut |world: &mut World, entity: &mut dyn ECSEntity| entity.despawn(world, render_scene));
+    }
+
+    /// Removes the entity and the entities attached to it as [Self::remove_entity()] does, but despawns them with
+    /// [despawn] instead of [ECSEntity::despawn()], so that no render scene is needed.
+    pub(crate) fn remove_entity_with<F: FnMut(&mut World, &mut dyn ECSEntity)>(&mut self, entity_handle: &ECSEntityHandle, despawn: &mut F) -> bool {
+        if !self.ecs_entities.contains_key(entity_handle) {
+            return false;
+        }
+        for child in self.get_children(entity_handle) {
+            self.remove_entity_with(&child, despawn);
+        }
+
+        let mut entity = self.ecs_entities.remove(entity_handle).unwrap();
+        self.specs_entity_handles.remove(&entity.specs_entity());
+        despawn(&mut self.world, entity.as_mut());
+        self.camera_handles.retain(|camera_handle| camera_handle != entity_handle);
+        self.light_handles.retain(|light_handle| light_handle != entity_handle);
+        self.entity_handle_allocator.free(entity_handle);
+        return true;
+    }
+
+    /// Returns whether [entity_handle] refers to an entity that has not been removed.
+    pub fn is_alive(&self, entity_handle: &ECSEntityHandle) -> bool {
+        return self.entity_handle_allocator.is_alive(entity_handle);
+    }
+
+    /// The handles of all entities, in ascending order.
+    pub fn entity_handles(&self) -> Vec<ECSEntityHandle> {
+        let mut entity_handles: Vec<ECSEntityHandle> = self.ecs_entities.keys().copied().collect();
+        entity_handles.sort();
+        return entity_handles;
+    }
+
     pub fn get_entity(&self, entity_handle: &ECSEntityHandle) -> Option<&Box<dyn ECSEntity>> {
         return self.ecs_entities.get(entity_handle);
     }
 
+    pub fn get_entity_mut(&mut self, entity_handle: &ECSEntityHandle) -> Option<&mut Box<dyn ECSEntity>> {
+        return self.ecs_entities.get_mut(entity_handle);
+    }
+
+    /// A copy of the entity's [C] component. None if [entity_handle] is stale or the entity has no such component.
+    pub fn get_component<C: Component + Clone>(&self, entity_handle: &ECSEntityHandle) -> Option<C> {
+        return self.with_component(entity_handle, |component: &C| component.clone());
+    }
+
+    /// Calls [f] with the entity's [C] component and returns its result.
+    /// None if [entity_handle] is stale or the entity has no such component.
+    pub fn with_component<C: Component, R, F: FnOnce(&C) -> R>(&self, entity_handle: &ECSEntityHandle, f: F) -> Option<R> {
+        let entity = self.ecs_entities.get(entity_handle)?.specs_entity();
+        if !self.world.has_value::<MaskedStorage<C>>() {
+            return None;
+        }
+        return self.world.read_storage::<C>().get(entity).map(f);
+    }
+
+    /// Calls [f] with the entity's [C] component, which it may modify, and returns its result.
+    /// None if [entity_handle] is stale or the entity has no such component.
+    pub fn with_component_mut<C: Component, R, F: FnOnce(&mut C) -> R>(&mut self, entity_handle: &ECSEntityHandle, f: F) -> Option<R> {
+        let entity = self.ecs_entities.get(entity_handle)?.specs_entity();
+        if !self.world.has_value::<MaskedStorage<C>>() {
+            return None;
+        }
+        return self.world.write_storage::<C>().get_mut(entity).map(f);
+    }
+
+    pub fn has_component<C: Component>(&self, entity_handle: &ECSEntityHandle) -> bool {
+        return self.with_component(entity_handle, |_: &C| ()).is_some();
+    }
+
+    /// Adds [component] to the entity, replacing its previous [C] component.
+    /// Registers the storage of [C] if necessary.
+    /// Returns false if [entity_handle] is stale.
+    pub fn insert_component<C: Component>(&mut self, entity_handle: &ECSEntityHandle, component: C) -> bool where C::Storage: Default {
+        let entity = match self.ecs_entities.get(entity_handle) {
+            Some(entity) => entity.specs_entity(),
+            None => return false,
+        };
+        self.world.register::<C>();
+        return self.world.write_storage::<C>().insert(entity, component).is_ok();
+    }
+
+    /// Removes the entity's [C] component and returns it.
+    /// None if [entity_handle] is stale or the entity has no such component.
+    pub fn remove_component<C: Component>(&mut self, entity_handle: &ECSEntityHandle) -> Option<C> {
+        let entity = self.ecs_entities.get(entity_handle)?.specs_entity();
+        if !self.world.has_value::<MaskedStorage<C>>() {
+            return None;
+        }
+        return self.world.write_storage::<C>().remove(entity);
+    }
+
+    /// Starts recording the [CollisionEvent]s of all following ticks for [Self::read_collision_events()].
+    /// Systems read the events from the `EventChannel<CollisionEvent>` resource instead, with a reader registered
+    /// in [System::setup()]. Systems running after the [PHYSICS_SYSTEM] see the events of the current tick.
+    pub fn register_collision_reader(&mut self) -> ReaderId<CollisionEvent> {
+        return self.world.write_resource::<EventChannel<CollisionEvent>>().register_reader();
+    }
+
+    /// The collision events recorded since the last call with [reader], in the order of their ticks.
+    pub fn read_collision_events(&self, reader: &mut ReaderId<CollisionEvent>) -> Vec<CollisionEvent> {
+        return self.world.read_resource::<EventChannel<CollisionEvent>>().read(reader).copied().collect();
+    }
+
+    /// The Rapier body of the entity's [RigidBodyComponent], eg. to exclude the entity from scene queries with
+    /// [QueryFilter::excluding()]. None until the next tick has created the body.
+    pub fn rigid_body_handle(&self, entity_handle: &ECSEntityHandle) -> Option<RigidBodyHandle> {
+        let entity = self.ecs_entities.get(entity_handle)?.specs_entity();
+        let physics_bodies = self.world.read_resource::<PhysicsBodiesResource>();
+        return physics_bodies.bodies.get(&entity).map(|body| body.object.rigid_body_handle());
+    }
+
+    /// Casts a ray from [origin] along [direction] and returns the closest entity hit within [max_distance].
+    /// Scene queries only hit entities with a [RigidBodyComponent] and see them as they were after the last tick.
+    pub fn raycast(&self, origin: Vec3A, direction: Vec3A, max_distance: f32, filter: &QueryFilter) -> Option<EntityHit> {
+        return self.query_entities(filter, |physics_world, filter| {
+            let origin = Point::new(origin.x, origin.y, origin.z);
+            let direction = Vector3::new(direction.x, direction.y, direction.z);
+            return physics_world.raycast(origin, direction, max_distance, filter);
+        });
+    }
+
+    /// Moves [collider] from [transform] along [direction] and returns the first entity it touches within
+    /// [max_distance], see [Self::raycast()]. The scale of [transform] is not applied to the collider.
+    pub fn cast_shape(&self, collider: &dyn Collider, transform: &Transform, direction: Vec3A, max_distance: f32, filter: &QueryFilter) -> Option<EntityHit> {
+        return self.query_entities(filter, |physics_world, filter| {
+            let direction = Vector3::new(direction.x, direction.y, direction.z);
+            return physics_world.cast_shape(collider, &isometry_from_transform(transform), direction, max_distance, filter);
+        });
+    }
+
+    /// The handles of all entities whose colliders intersect [collider] placed at [transform], in ascending order.
+    /// See [Self::raycast()]. The scale of [transform] is not applied to the collider.
+    pub fn intersect_shape(&self, collider: &dyn Collider, transform: &Transform, filter: &QueryFilter) -> Vec<ECSEntityHandle> {
+        let physics_world = self.world.read_resource::<PhysicsWorld>();
+        let physics_bodies = self.world.read_resource::<PhysicsBodiesResource>();
+        let is_entity_body = |rigid_body: RigidBodyHandle| self.is_entity_body(&physics_bodies, rigid_body, filter);
+        let entity_filter = QueryFilter { predicate: Some(&is_entity_body), ..*filter };
+
+        let mut entity_handles: Vec<ECSEntityHandle> = physics_world.intersect_shape(collider, &isometry_from_transform(transform), &entity_filter)
+            .iter()
+            .filter_map(|overlap| self.body_entity_handle(&physics_bodies, overlap.rigid_body?))
+            .collect();
+        entity_handles.sort();
+        entity_handles.dedup();
+        return entity_handles;
+    }
+
+    /// Runs [query] with [filter] restricted to the bodies of entities and maps its hit to the entity.
+    fn query_entities<F>(&self, filter: &QueryFilter, query: F) -> Option<EntityHit>
+        where F: FnOnce(&PhysicsWorld, &QueryFilter) -> Option<QueryHit> {
+        let physics_world = self.world.read_resource::<PhysicsWorld>();
+        let physics_bodies = self.world.read_resource::<PhysicsBodiesResource>();
+        let is_entity_body = |rigid_body: RigidBodyHandle| self.is_entity_body(&physics_bodies, rigid_body, filter);
+        let entity_filter = QueryFilter { predicate: Some(&is_entity_body), ..*filter };
+
+        let hit = query(&physics_world, &entity_filter)?;
+        return Some(EntityHit {
+            entity: self.body_entity_handle(&physics_bodies, hit.rigid_body?)?,
+            point: Vec3A::new(hit.point.x, hit.point.y, hit.point.z),
+            normal: Vec3A::new(hit.normal.x, hit.normal.y, hit.normal.z),
+            distance: hit.distance,
+        });
+    }
+
+    /// Whether [rigid_body] belongs to an entity and passes the predicate of [filter].
+    fn is_entity_body(&self, physics_bodies: &PhysicsBodiesResource, rigid_body: RigidBodyHandle, filter: &QueryFilter) -> bool {
+        return self.body_entity_handle(physics_bodies, rigid_body).is_some()
+            && filter.predicate.map_or(true, |predicate| predicate(rigid_body));
+    }
+
+    fn body_entity_handle(&self, physics_bodies: &PhysicsBodiesResource, rigid_body: RigidBodyHandle) -> Option<ECSEntityHandle> {
+        let entity = physics_bodies.entities.get(&rigid_body)?;
+        return self.specs_entity_handles.get(entity).copied();
+    }
+
+    /// The handles of all entities having all components of [S], in ascending order,
+    /// eg. `entities_with::<(TransformComponent, LightComponent)>()`.
+    pub fn entities_with<S: ComponentSet>(&self) -> Vec<ECSEntityHandle> {
+        let mask = match S::mask(&self.world) {
+            Some(mask) => mask,
+            None => return Vec::new(),
+        };
+        let mut entity_handles: Vec<ECSEntityHandle> = self.specs_entity_handles.iter()
+            .filter(|(entity, _)| mask.contains(entity.id()))
+            .map(|(_, entity_handle)| *entity_handle)
+            .collect();
+        entity_handles.sort();
+        return entity_handles;
+    }
+
     pub fn get_cameras(&self) -> &Vec<ECSEntityHandle> {
         return &self.camera_handles;
     }
@@ -180,29 +859,189 @@ impl ECSWorld {
     pub fn get_primary_camera(&self) -> Option<ECSEntityHandle> {
         return self.camera_handles.first().copied();
     }
+
+    pub fn get_lights(&self) -> &Vec<ECSEntityHandle> {
+        return &self.light_handles;
+    }
+
+    /// Attaches the render node of [child] to the render node of [parent], or makes it a root node if [parent] is
+    /// None. The transform of [child] is kept and becomes relative to [parent].
+    /// Returns false if either entity is stale or has no render node, or if [child] would become its own ancestor.
+    pub fn set_parent(&mut self, child: &ECSEntityHandle, parent: Option<&ECSEntityHandle>, render_scene: &mut RenderScene) -> bool {
+        let child_node = match self.ecs_entities.get(child).and_then(|entity| entity.get_render_node()) {
+            Some(child_node) => *child_node,
+            None => return false,
+        };
+        let parent_node = match parent {
+            Some(parent) => match self.ecs_entities.get(parent).and_then(|entity| entity.get_render_node()) {
+                Some(parent_node) => Some(*parent_node),
+                None => return false,
+            },
+            None => None,
+        };
+        if render_scene.set_parent(&child_node, parent_node.as_ref(), false).is_err() {
+            return false;
+        }
+        match parent {
+            Some(parent) => {
+                self.insert_component(child, ParentComponent { parent: *parent });
+            }
+            None => {
+                self.remove_component::<ParentComponent>(child);
+            }
+        }
+        return true;
+    }
+
+    /// The entity [entity_handle] is attached to, see [Self::set_parent()].
+    pub fn get_parent(&self, entity_handle: &ECSEntityHandle) -> Option<ECSEntityHandle> {
+        return self.with_component(entity_handle, |parent: &ParentComponent| parent.parent);
+    }
+
+    /// The entities attached to [entity_handle] with [Self::set_parent()], in ascending order.
+    pub fn get_children(&self, entity_handle: &ECSEntityHandle) -> Vec<ECSEntityHandle> {
+        if !self.world.has_value::<MaskedStorage<ParentComponent>>() {
+            return Vec::new();
+        }
+        let entity_handles = self.world.read_storage::<EntityHandleComponent>();
+        let parents = self.world.read_storage::<ParentComponent>();
+        let mut children: Vec<ECSEntityHandle> = (&entity_handles, &parents).join()
+            .filter(|(_, parent)| parent.parent == *entity_handle)
+            .map(|(child, _)| child.entity_handle)
+            .collect();
+        children.sort();
+        return children;
+    }
+
+    /// The transform of the entity. None if [entity_handle] is stale or the entity has no transform.
+    pub fn get_transform(&self, entity_handle: &ECSEntityHandle) -> Option<Transform> {
+        return self.with_component(entity_handle, |transform: &TransformComponent| transform.transform);
+    }
+
+    /// Moves the entity to [transform]. Its render node follows with the next snapshot.
+    /// Returns false if [entity_handle] is stale.
+    pub fn set_transform(&mut self, entity_handle: &ECSEntityHandle, transform: Transform) -> bool {
+        return self.insert_component(entity_handle, TransformComponent { transform });
+    }
 }
 
+/// A set of component types, implemented for tuples of up to four components, see [ECSWorld::entities_with()].
+pub trait ComponentSet {
+    /// The specs entities having all components of the set. None if a component's storage is not registered.
+    fn mask(world: &World) -> Option<BitSet>;
+}
 
-#[derive(Component, Debug)]
+macro_rules! impl_component_set {
+    ($first:ident $(, $rest:ident)*) => {
+        impl<$first: Component, $($rest: Component),*> ComponentSet for ($first, $($rest,)*) {
+            fn mask(world: &World) -> Option<BitSet> {
+                if !world.has_value::<MaskedStorage<$first>>() {
+                    return None;
+                }
+                let mut mask = world.read_storage::<$first>().mask().clone();
+                $(
+                    if !world.has_value::<MaskedStorage<$rest>>() {
+                        return None;
+                    }
+                    mask &= world.read_storage::<$rest>().mask();
+                )*
+                return Some(mask);
+            }
+        }
+    };
+}
+
+impl_component_set!(A);
+impl_component_set!(A, B);
+impl_component_set!(A, B, C);
+impl_component_set!(A, B, C, D);
+
+/// Returns the snapshot of [entity]'s transforms before and after the last tick, along with [state].
+/// [entity] must have a [TransformComponent].
+fn snapshot_entity(world: &World, entity: Entity, render_node: RenderNodeHandle, state: EntityRenderState) -> EntitySnapshot {
+    let transform_component = world.read_component::<TransformComponent>();
+    let transform = transform_component.get(entity).unwrap().transform;
+
+    // Entities added since the last tick have no previous transform
+    let previous_transform_component = world.read_component::<PreviousTransformComponent>();
+    let previous_transform = match previous_transform_component.get(entity) {
+        Some(previous) => previous.transform,
+        None => transform,
+    };
+    return EntitySnapshot { render_node, previous_transform, transform, state };
+}
+
+/// Deletes [specs_entity] from [world] and removes [render_node] along with its descendants from [render_scene].
+/// Shared by the [ECSEntity::despawn()] implementations of entities with a render node.
+fn despawn_entity(world: &mut World, specs_entity: Entity, render_node: &RenderNodeHandle, render_scene: &mut RenderScene) {
+    world.delete_entity(specs_entity).unwrap();
+    // The render node is already gone if an ancestor was removed through the render scene directly
+    if render_scene.is_alive(render_node) {
+        render_scene.remove_node(render_node).unwrap();
+    }
+}
+
+/// Returns the rotation that turns [forward_axis] into [direction].
+/// Rotates around [up_axis] if the two point in opposite directions.
+fn rotation_from_direction(direction: Vec3A, forward_axis: Vec3A, up_axis: Vec3A) -> Quat {
+    let dot = direction.dot(forward_axis);
+    if (dot - 1.0).abs() < f32::EPSILON {
+        return Quat::IDENTITY;
+    } else if (dot + 1.0).abs() < f32::EPSILON {
+        return Quat::from_axis_angle(Vec3::from(up_axis), PI);
+    } else {
+        let angle = dot.acos();
+        let rot_axis = forward_axis.cross(direction).normalize();
+        return Quat::from_axis_angle(Vec3::from(rot_axis), angle);
+    }
+}
+
+
+#[derive(Component, Copy, Clone, Debug, PartialEq)]
 #[storage(HashMapStorage)]
-struct CameraComponent {
+pub struct CameraComponent {
     // The camera's forward axis (not to be confused with the camera's foward vector)
-    forward_axis: Vec3A,
+    pub forward_axis: Vec3A,
     // The camera's up axis (not to be confused with the camera's up vector)
-    up_axis: Vec3A,
-    // The camera's vertical field of view.
-    fov: f32,
+    pub up_axis: Vec3A,
+    // The camera's vertical field of view. None for orthographic cameras.
+    // unit: radians
+    pub fov: Option<f32>,
 }
 
-#[derive(Component, Default)]
-#[storage(NullStorage)]
-pub struct FlyingCameraComponent;
+/// Makes a camera move with the [MovementInput].
+#[derive(Component, Default, Debug)]
+#[storage(HashMapStorage)]
+pub struct FlyingCameraComponent {
+    /// [yaw], [pitch] and [roll] determine the rotation of the camera
+    /// unit: radians
+    yaw: f32,
+    pitch: f32,
+    roll: f32,
+}
+
+impl FlyingCameraComponent {
+    /// Starts flying with the camera rotated by [rotation].
+    pub fn from_rotation(rotation: Quat) -> Self {
+        let (yaw, pitch, roll) = rotation.to_euler(EulerRot::YXZ);
+        return FlyingCameraComponent { yaw, pitch, roll };
+    }
+}
 
-pub trait ECSEntity {
-    fn update_render_node(&mut self, world: &World, render_scene: &mut RenderScene);
+/// Entities are owned by the [ECSWorld], which may be moved to the simulation thread.
+pub trait ECSEntity: Send {
+    /// The render-relevant state of the entity, applied to its render node by [RenderSnapshot::apply()].
+    /// None if the entity has no render node.
+    fn snapshot(&self, world: &World) -> Option<EntitySnapshot>;
 
     fn get_render_node(&self) -> Option<&RenderNodeHandle>;
 
+    /// The specs entity holding the entity's components.
+    fn specs_entity(&self) -> Entity;
+
+    /// Deletes the entity's specs entity from [world] and removes its render node, if any, from [render_scene].
+    fn despawn(&mut self, world: &mut World, render_scene: &mut RenderScene);
+
     fn as_any(&self) -> &dyn Any;
 
     fn as_any_mut(&mut self) -> &mut dyn Any;
@@ -214,28 +1053,25 @@ pub struct CameraEntity {
 }
 
 impl ECSEntity for CameraEntity {
-    fn update_render_node(&mut self, world: &World, render_scene: &mut RenderScene) {
-        let position_component = world.read_component::<PositionComponent>();
-        let position = position_component.get(self.specs_entity_handle).unwrap().position;
-
-        let rotation_component = world.read_component::<RotationComponent>();
-        let rotation = rotation_component.get(self.specs_entity_handle).unwrap().quaternion;
-
+    fn snapshot(&self, world: &World) -> Option<EntitySnapshot> {
         let camera_component = world.read_component::<CameraComponent>();
         let camera_data = camera_component.get(self.specs_entity_handle).unwrap();
 
-        let render_node = render_scene.nodes.get_mut(&self.camera_render_node_handle).unwrap().as_any_mut();
-        let camera_render_node = render_node.downcast_mut::<CameraRenderNode>().unwrap();
-
-        camera_render_node.set_position(position);
-        camera_render_node.set_rotation(Quat::from_xyzw(rotation.x as f32, rotation.y as f32, rotation.z as f32, rotation.w as f32));
-        camera_render_node.set_fov(camera_data.fov);
+        return Some(snapshot_entity(world, self.specs_entity_handle, self.camera_render_node_handle, EntityRenderState::Camera { fov: camera_data.fov }));
     }
 
     fn get_render_node(&self) -> Option<&RenderNodeHandle> {
         return Some(&self.camera_render_node_handle);
     }
 
+    fn specs_entity(&self) -> Entity {
+        return self.specs_entity_handle;
+    }
+
+    fn despawn(&mut self, world: &mut World, render_scene: &mut RenderScene) {
+        despawn_entity(world, self.specs_entity_handle, &self.camera_render_node_handle, render_scene);
+    }
+
     fn as_any(&self) -> &dyn Any {
         return self;
     }
@@ -246,6 +1082,8 @@ impl ECSEntity for CameraEntity {
 }
 
 impl CameraEntity {
+    /// Adds a perspective camera that is moved by the [MovementInput].
+    /// [fov] is the vertical field of view in degrees.
     pub fn add_flying(ecs_word: &mut ECSWorld, render_scene: &mut RenderScene,
                       position: Vec3A,
                       direction: Vec3A,
@@ -256,34 +1094,16 @@ impl CameraEntity {
                       far: Option<f32>,
                       aspect: f32) -> ECSEntityHandle {
         let world = &mut ecs_word.world;
-        world.register::<PositionComponent>();
-        world.register::<VelocityComponent>();
-        world.register::<RotationComponent>();
         world.register::<CameraComponent>();
         world.register::<FlyingCameraComponent>();
 
-        let rotation;
-        {
-            let dot = direction.dot(forward_axis);
-            if (dot - 1.0).abs() < f32::EPSILON {
-                rotation = Quat::IDENTITY;
-            } else if (dot + 1.0).abs() < f32::EPSILON {
-                rotation = Quat::from_axis_angle(Vec3::from(up_axis), PI);
-            } else {
-                let angle = dot.acos();
-                let rot_axis = forward_axis.cross(direction).normalize();
-                rotation = Quat::from_axis_angle(Vec3::from(rot_axis), angle);
-            }
-        }
-
-        let (yaw, pitch, roll) = rotation.to_euler(EulerRot::YXZ);
+        let rotation = rotation_from_direction(direction, forward_axis, up_axis);
 
         let entity = world.create_entity()
-            .with(PositionComponent { position: position })
+            .with(TransformComponent { transform: Transform::from_translation_rotation(position, rotation) })
             .with(VelocityComponent { velocity: Vec3A::ZERO })
-            .with(RotationComponent { quaternion: rotation, yaw: yaw, pitch: pitch, roll: roll })
-            .with(CameraComponent { forward_axis, up_axis, fov })
-            .with(FlyingCameraComponent)
+            .with(CameraComponent { forward_axis, up_axis, fov: Some(fov.to_radians()) })
+            .with(FlyingCameraComponent::from_rotation(rotation))
             .build();
 
         let camera = PerspectiveCamera::new(position, direction, forward_axis, up_axis, fov, near, far, aspect);
@@ -291,6 +1111,185 @@ impl CameraEntity {
         let camera_entity = CameraEntity { camera_render_node_handle: camera_node_handle, specs_entity_handle: entity };
         return ecs_word.add_entity(Box::new(camera_entity));
     }
+
+    /// Adds a stationary orthographic camera, eg. for top, front and side views.
+    /// [height] is the height of the camera's view volume in world units.
+    pub fn add_orthographic(ecs_world: &mut ECSWorld, render_scene: &mut RenderScene,
+                            position: Vec3A,
+                            direction: Vec3A,
+                            forward_axis: Vec3A,
+                            up_axis: Vec3A,
+                            height: f32,
+                            near: f32,
+                            far: f32,
+                            aspect: f32) -> ECSEntityHandle {
+        let world = &mut ecs_world.world;
+        world.register::<CameraComponent>();
+
+        let rotation = rotation_from_direction(direction, forward_axis, up_axis);
+
+        let entity = world.create_entity()
+            .with(TransformComponent { transform: Transform::from_translation_rotation(position, rotation) })
+            .with(CameraComponent { forward_axis, up_axis, fov: None })
+            .build();
+
+        let camera = OrthographicCamera::new(position, direction, forward_axis, up_axis, height, near, far, aspect);
+        let camera_node_handle = CameraRenderNode::add_new(camera, render_scene);
+        let camera_entity = CameraEntity { camera_render_node_handle: camera_node_handle, specs_entity_handle: entity };
+        return ecs_world.add_entity(Box::new(camera_entity));
+    }
+}
+
+/// The light emitted by a [LightEntity]. Changes are applied to the light's render node with the next snapshot.
+#[derive(Component, Copy, Clone, Debug, PartialEq)]
+#[storage(HashMapStorage)]
+pub struct LightComponent {
+    pub light: Light,
+}
+
+/// A directional, point or spot light. The light shines along the entity's forward (+Z) axis.
+pub struct LightEntity {
+    light_render_node_handle: RenderNodeHandle,
+    specs_entity_handle: Entity,
+}
+
+impl ECSEntity for LightEntity {
+    fn snapshot(&self, world: &World) -> Option<EntitySnapshot> {
+        let light_component = world.read_component::<LightComponent>();
+        let light = light_component.get(self.specs_entity_handle).unwrap().light;
+
+        return Some(snapshot_entity(world, self.specs_entity_handle, self.light_render_node_handle, EntityRenderState::Light(light)));
+    }
+
+    fn get_render_node(&self) -> Option<&RenderNodeHandle> {
+        return Some(&self.light_render_node_handle);
+    }
+
+    fn specs_entity(&self) -> Entity {
+        return self.specs_entity_handle;
+    }
+
+    fn despawn(&mut self, world: &mut World, render_scene: &mut RenderScene) {
+        despawn_entity(world, self.specs_entity_handle, &self.light_render_node_handle, render_scene);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        return self;
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        return self;
+    }
+}
+
+impl LightEntity {
+    /// Adds a directional light shining along [direction].
+    pub fn add_directional(ecs_world: &mut ECSWorld, render_scene: &mut RenderScene, direction: Vec3A, color: Vec3A, intensity: f32, casts_shadows: bool) -> ECSEntityHandle {
+        return Self::add(ecs_world, render_scene, Vec3A::ZERO, direction, Light::directional(color, intensity).with_shadows(casts_shadows));
+    }
+
+    /// Adds a point light at [position] that reaches up to [range].
+    pub fn add_point(ecs_world: &mut ECSWorld, render_scene: &mut RenderScene, position: Vec3A, color: Vec3A, intensity: f32, range: f32) -> ECSEntityHandle {
+        return Self::add(ecs_world, render_scene, position, Vec3A::Z, Light::point(color, intensity, range));
+    }
+
+    /// Adds a spot light at [position] shining along [direction].
+    /// [inner_cone_angle] and [outer_cone_angle] are in radians.
+    pub fn add_spot(ecs_world: &mut ECSWorld, render_scene: &mut RenderScene,
+                    position: Vec3A,
+                    direction: Vec3A,
+                    color: Vec3A,
+                    intensity: f32,
+                    range: f32,
+                    inner_cone_angle: f32,
+                    outer_cone_angle: f32,
+                    casts_shadows: bool) -> ECSEntityHandle {
+        return Self::add(ecs_world, render_scene, position, direction, Light::spot(color, intensity, range, inner_cone_angle, outer_cone_angle).with_shadows(casts_shadows));
+    }
+
+    fn add(ecs_world: &mut ECSWorld, render_scene: &mut RenderScene, position: Vec3A, direction: Vec3A, light: Light) -> ECSEntityHandle {
+        let rotation = rotation_from_direction(direction.normalize(), Vec3A::Z, Vec3A::Y);
+        return Self::add_with_transform(ecs_world, render_scene, light, Transform::from_translation_rotation(position, rotation));
+    }
+
+    /// Adds [light] placed by [transform]. The light shines along the transform's forward (+Z) axis.
+    pub fn add_with_transform(ecs_world: &mut ECSWorld, render_scene: &mut RenderScene, light: Light, transform: Transform) -> ECSEntityHandle {
+        let world = &mut ecs_world.world;
+        world.register::<LightComponent>();
+
+        let entity = world.create_entity()
+            .with(TransformComponent { transform })
+            .with(LightComponent { light })
+            .build();
+
+        let light_node_handle = LightRenderNode::add_new(light, render_scene);
+        render_scene.set_local_transform(&light_node_handle, transform);
+        let light_entity = LightEntity { light_render_node_handle: light_node_handle, specs_entity_handle: entity };
+        return ecs_world.add_entity(Box::new(light_entity));
+    }
+}
+
+/// An entity whose [TransformComponent] is synced into an arbitrary render node, eg. the root of a spawned model.
+pub struct MeshEntity {
+    render_node_handle: RenderNodeHandle,
+    specs_entity_handle: Entity,
+}
+
+impl ECSEntity for MeshEntity {
+    fn snapshot(&self, world: &World) -> Option<EntitySnapshot> {
+        return Some(snapshot_entity(world, self.specs_entity_handle, self.render_node_handle, EntityRenderState::Transform));
+    }
+
+    fn get_render_node(&self) -> Option<&RenderNodeHandle> {
+        return Some(&self.render_node_handle);
+    }
+
+    fn specs_entity(&self) -> Entity {
+        return self.specs_entity_handle;
+    }
+
+    fn despawn(&mut self, world: &mut World, render_scene: &mut RenderScene) {
+        despawn_entity(world, self.specs_entity_handle, &self.render_node_handle, render_scene);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        return self;
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        return self;
+    }
+}
+
+impl MeshEntity {
+    /// Adds an entity that moves [render_node] to [transform] and keeps it in sync with its [TransformComponent].
+    /// [render_node] should be a root node, as its local transform is overwritten with the entity's transform.
+    /// Use [ECSWorld::set_parent()] to attach it to another entity.
+    /// The render node and its children are removed when the entity is removed.
+    pub fn add(ecs_world: &mut ECSWorld, render_scene: &mut RenderScene, render_node: RenderNodeHandle, transform: Transform) -> ECSEntityHandle {
+        let entity = ecs_world.world.create_entity()
+            .with(TransformComponent { transform })
+            .build();
+
+        render_scene.set_local_transform(&render_node, transform);
+        let mesh_entity = MeshEntity { render_node_handle: render_node, specs_entity_handle: entity };
+        return ecs_world.add_entity(Box::new(mesh_entity));
+    }
+
+    /// Spawns the render nodes of [model] and adds an entity moving them, see [MeshRenderNode::spawn_model()].
+    pub fn spawn_model(ecs_world: &mut ECSWorld, render_scene: &mut RenderScene, model: &Model, transform: Transform) -> ECSEntityHandle {
+        let model_root = MeshRenderNode::spawn_model(model, render_scene);
+        return Self::add(ecs_world, render_scene, model_root, transform);
+    }
+
+    /// Loads the glTF file at [path] and spawns it as [Self::spawn_model()] does.
+    /// The path is kept in a [ModelComponent], so the entity can be saved to a scene file.
+    pub fn load_model<P: AsRef<Path>>(ecs_world: &mut ECSWorld, render_scene: &mut RenderScene, path: P, transform: Transform) -> Result<ECSEntityHandle, ModelLoadError> {
+        let model = gltf_loader::load_model_from_file(path.as_ref())?;
+        let entity_handle = Self::spawn_model(ecs_world, render_scene, &model, transform);
+        ecs_world.insert_component(&entity_handle, ModelComponent { path: path.as_ref().to_path_buf() });
+        return Ok(entity_handle);
+    }
 }
 
 #[derive(Default)]
@@ -310,34 +1309,34 @@ impl<'a> System<'a> for FlyingCameraSystem {
     type SystemData = (
         Read<'a, DeltaTimeResource>,
         Read<'a, MovementInputResource>,
-        WriteStorage<'a, RotationComponent>,
+        WriteStorage<'a, TransformComponent>,
         WriteStorage<'a, VelocityComponent>,
         WriteStorage<'a, CameraComponent>,
-        ReadStorage<'a, FlyingCameraComponent>,
+        WriteStorage<'a, FlyingCameraComponent>,
     );
 
-    fn run(&mut self, (delta_time_resource, movement_input_resource, mut rotations, mut velocities, cameras, flying_cameras): Self::SystemData) {
+    fn run(&mut self, (delta_time_resource, movement_input_resource, mut transforms, mut velocities, cameras, mut flying_cameras): Self::SystemData) {
         let movement_input = &movement_input_resource.movement_input;
         let delta_yaw = movement_input.delta_yaw;
         let delta_pitch = movement_input.delta_pitch;
-        for (rotation, velocity, camera, _) in (&mut rotations, &mut velocities, &cameras, &flying_cameras).join() {
+        for (transform, velocity, camera, flying_camera) in (&mut transforms, &mut velocities, &cameras, &mut flying_cameras).join() {
+            let rotation = &mut transform.transform.rotation;
+
             // Rotate the camera.
             {
-                let rot_quat = &mut rotation.quaternion;
-
                 if !movement_input.should_roll {
-                    rotation.yaw += delta_yaw;
-                    rotation.pitch += delta_pitch;
+                    flying_camera.yaw += delta_yaw;
+                    flying_camera.pitch += delta_pitch;
                 } else {
-                    rotation.roll += delta_yaw;
+                    flying_camera.roll += delta_yaw;
                 }
-                *rot_quat = Quat::from_euler(EulerRot::YXZ, rotation.yaw, rotation.pitch, rotation.roll);
+                *rotation = Quat::from_euler(EulerRot::YXZ, flying_camera.yaw, flying_camera.pitch, flying_camera.roll);
             }
 
             // Add movement input to velocity
             {
-                let forward = rotation.quaternion * camera.forward_axis;
-                let up = rotation.quaternion * camera.up_axis;
+                let forward = *rotation * camera.forward_axis;
+                let up = *rotation * camera.up_axis;
                 let right = up.cross(forward);
 
                 let mut move_dir = Vec3A::ZERO;
@@ -370,3 +1369,247 @@ impl<'a> System<'a> for FlyingCameraSystem {
     }
 }
 
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use newton::{CubeCollider, SphereCollider};
+    use super::*;
+
+    #[derive(Default)]
+    struct StageLog(Vec<SystemStage>);
+
+    struct LogStageSystem(SystemStage);
+
+    impl<'a> System<'a> for LogStageSystem {
+        type SystemData = specs::Write<'a, StageLog>;
+
+        fn run(&mut self, mut stage_log: Self::SystemData) {
+            stage_log.0.push(self.0);
+        }
+    }
+
+    /// An entity without a render node, so no render scene is needed.
+    pub(crate) struct BareEntity(Entity);
+
+    impl ECSEntity for BareEntity {
+        fn snapshot(&self, _world: &World) -> Option<EntitySnapshot> {
+            return None;
+        }
+
+        fn get_render_node(&self) -> Option<&RenderNodeHandle> {
+            return None;
+        }
+
+        fn specs_entity(&self) -> Entity {
+            return self.0;
+        }
+
+        fn despawn(&mut self, world: &mut World, _render_scene: &mut RenderScene) {
+            world.delete_entity(self.0).unwrap();
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            return self;
+        }
+
+        fn as_any_mut(&mut self) -> &mut dyn Any {
+            return self;
+        }
+    }
+
+    pub(crate) fn add_bare_entity(ecs_world: &mut ECSWorld) -> ECSEntityHandle {
+        let entity = ecs_world.specs_world_mut().create_entity().build();
+        return ecs_world.add_entity(Box::new(BareEntity(entity)));
+    }
+
+    #[test]
+    fn component_accessors() {
+        let mut ecs_world = ECSWorld::new();
+        let entity_handle = add_bare_entity(&mut ecs_world);
+        let velocity = VelocityComponent { velocity: Vec3A::X };
+
+        assert_eq!(ecs_world.get_component::<VelocityComponent>(&entity_handle), None);
+        assert!(ecs_world.insert_component(&entity_handle, velocity));
+        assert_eq!(ecs_world.get_component::<VelocityComponent>(&entity_handle), Some(velocity));
+
+        ecs_world.with_component_mut(&entity_handle, |velocity: &mut VelocityComponent| velocity.velocity = Vec3A::Y);
+        assert_eq!(ecs_world.get_component::<VelocityComponent>(&entity_handle).unwrap().velocity, Vec3A::Y);
+
+        assert!(ecs_world.remove_component::<VelocityComponent>(&entity_handle).is_some());
+        assert!(!ecs_world.has_component::<VelocityComponent>(&entity_handle));
+        // Unregistered components are never present
+        assert!(!ecs_world.has_component::<LightComponent>(&entity_handle));
+    }
+
+    #[test]
+    fn iterates_entities_with_components() {
+        let mut ecs_world = ECSWorld::new();
+        let moving = add_bare_entity(&mut ecs_world);
+        let still = add_bare_entity(&mut ecs_world);
+        ecs_world.set_transform(&moving, Transform::IDENTITY);
+        ecs_world.insert_component(&moving, VelocityComponent { velocity: Vec3A::X });
+        ecs_world.set_transform(&still, Transform::IDENTITY);
+
+        assert_eq!(ecs_world.entities_with::<(TransformComponent,)>(), vec![moving, still]);
+        assert_eq!(ecs_world.entities_with::<(TransformComponent, VelocityComponent)>(), vec![moving]);
+        assert_eq!(ecs_world.entities_with::<(TransformComponent, LightComponent)>(), vec![]);
+    }
+
+    #[test]
+    fn finds_children_of_entities() {
+        let mut ecs_world = ECSWorld::new();
+        let vehicle = add_bare_entity(&mut ecs_world);
+        let camera = add_bare_entity(&mut ecs_world);
+        let wheel = add_bare_entity(&mut ecs_world);
+        assert!(ecs_world.get_children(&vehicle).is_empty());
+
+        ecs_world.insert_component(&wheel, ParentComponent { parent: vehicle });
+        ecs_world.insert_component(&camera, ParentComponent { parent: vehicle });
+        assert_eq!(ecs_world.get_children(&vehicle), vec![camera, wheel]);
+        assert!(ecs_world.get_children(&camera).is_empty());
+    }
+
+    #[test]
+    fn moves_rigid_bodies_physically() {
+        let mut ecs_world = ECSWorld::new();
+        let falling = add_bare_entity(&mut ecs_world);
+        let ground = add_bare_entity(&mut ecs_world);
+        ecs_world.set_transform(&falling, Transform::from_translation(Vec3A::new(0.0, 10.0, 0.0)));
+        ecs_world.insert_component(&falling, VelocityComponent { velocity: Vec3A::ZERO });
+        ecs_world.insert_component(&falling, RigidBodyComponent::dynamic());
+        ecs_world.insert_component(&falling, ColliderComponent::new(SphereCollider { radius: 0.5 }));
+        ecs_world.set_transform(&ground, Transform::IDENTITY);
+        ecs_world.insert_component(&ground, RigidBodyComponent::fixed());
+        ecs_world.insert_component(&ground, ColliderComponent::new(CubeCollider { dimension: Vector3::new(10.0, 1.0, 10.0) }));
+        let tick_duration = ecs_world.timestep().tick_duration();
+
+        // At most [FixedTimestep::max_ticks_per_update] ticks run per update
+        for _ in 0..10 {
+            ecs_world.simulate(tick_duration, MovementInput::new());
+        }
+        let translation = ecs_world.get_transform(&falling).unwrap().translation;
+        assert!(translation.y < 10.0);
+        assert!(ecs_world.get_component::<VelocityComponent>(&falling).unwrap().velocity.y < 0.0);
+        assert_eq!(ecs_world.get_transform(&ground).unwrap(), Transform::IDENTITY);
+
+        // Setting the transform teleports the body
+        ecs_world.set_transform(&falling, Transform::from_translation(Vec3A::new(0.0, 20.0, 0.0)));
+        ecs_world.simulate(tick_duration, MovementInput::new());
+        assert!(ecs_world.get_transform(&falling).unwrap().translation.y > 19.0);
+    }
+
+    #[test]
+    fn accelerates_rigid_bodies_without_velocity() {
+        let mut ecs_world = ECSWorld::new();
+        let falling = add_bare_entity(&mut ecs_world);
+        ecs_world.set_transform(&falling, Transform::from_translation(Vec3A::new(0.0, 10.0, 0.0)));
+        ecs_world.insert_component(&falling, RigidBodyComponent::dynamic());
+        ecs_world.insert_component(&falling, ColliderComponent::new(SphereCollider { radius: 0.5 }));
+        let tick_duration = ecs_world.timestep().tick_duration();
+
+        let ticks = (0.5 / tick_duration) as u32;
+        for _ in 0..ticks {
+            ecs_world.simulate(tick_duration, MovementInput::new());
+        }
+        // Falls about g * t^2 / 2, not just g * dt per tick
+        let fallen = 10.0 - ecs_world.get_transform(&falling).unwrap().translation.y;
+        let expected = 0.5 * 9.81 * (ticks as f32 * tick_duration as f32).powi(2);
+        assert!((fallen - expected).abs() < expected * 0.1, "fell {} instead of {}", fallen, expected);
+        assert!(!ecs_world.has_component::<VelocityComponent>(&falling));
+    }
+
+    #[test]
+    fn queries_entities_by_collider() {
+        let mut ecs_world = ECSWorld::new();
+        let ground = add_bare_entity(&mut ecs_world);
+        let ball = add_bare_entity(&mut ecs_world);
+        ecs_world.set_transform(&ground, Transform::IDENTITY);
+        ecs_world.insert_component(&ground, RigidBodyComponent::fixed());
+        ecs_world.insert_component(&ground, ColliderComponent::new(CubeCollider { dimension: Vector3::new(10.0, 1.0, 10.0) }));
+        ecs_world.set_transform(&ball, Transform::from_translation(Vec3A::new(3.0, 5.0, 0.0)));
+        ecs_world.insert_component(&ball, RigidBodyComponent::fixed());
+        ecs_world.insert_component(&ball, ColliderComponent::new(SphereCollider { radius: 0.5 }));
+        // Bodies are created and become visible to queries with the next tick
+        assert!(ecs_world.raycast(Vec3A::new(0.0, 10.0, 0.0), -Vec3A::Y, 100.0, &QueryFilter::new()).is_none());
+        ecs_world.simulate(ecs_world.timestep().tick_duration(), MovementInput::new());
+
+        let hit = ecs_world.raycast(Vec3A::new(0.0, 10.0, 0.0), -Vec3A::Y, 100.0, &QueryFilter::new()).unwrap();
+        assert_eq!(hit.entity, ground);
+        assert!((hit.distance - 9.5).abs() < 1e-4);
+        assert!(hit.normal.abs_diff_eq(Vec3A::Y, 1e-4));
+
+        let ground_body = ecs_world.rigid_body_handle(&ground).unwrap();
+        let from_above_ball = Vec3A::new(3.0, 10.0, 0.0);
+        assert_eq!(ecs_world.raycast(from_above_ball, -Vec3A::Y, 100.0, &QueryFilter::new()).unwrap().entity, ball);
+        let only_ground = |rigid_body: RigidBodyHandle| rigid_body == ground_body;
+        assert_eq!(ecs_world.raycast(from_above_ball, -Vec3A::Y, 100.0, &QueryFilter::new().with_predicate(&only_ground)).unwrap().entity, ground);
+        assert!(ecs_world.raycast(Vec3A::new(0.0, 10.0, 0.0), -Vec3A::Y, 100.0, &QueryFilter::new().excluding(ground_body)).is_none());
+
+        let probe = SphereCollider { radius: 3.0 };
+        assert_eq!(ecs_world.intersect_shape(&probe, &Transform::from_translation(Vec3A::new(3.0, 3.0, 0.0)), &QueryFilter::new()), vec![ground, ball]);
+        let hit = ecs_world.cast_shape(&probe, &Transform::from_translation(Vec3A::new(-3.0, 10.0, 0.0)), -Vec3A::Y, 100.0, &QueryFilter::new()).unwrap();
+        assert_eq!(hit.entity, ground);
+    }
+
+    #[test]
+    fn reports_collision_events_of_entities() {
+        let mut ecs_world = ECSWorld::new();
+        let mut reader = ecs_world.register_collision_reader();
+        let ground = add_bare_entity(&mut ecs_world);
+        let trigger = add_bare_entity(&mut ecs_world);
+        let ball = add_bare_entity(&mut ecs_world);
+        ecs_world.set_transform(&ground, Transform::IDENTITY);
+        ecs_world.insert_component(&ground, RigidBodyComponent::fixed());
+        ecs_world.insert_component(&ground, ColliderComponent::new(CubeCollider { dimension: Vector3::new(10.0, 1.0, 10.0) }));
+        ecs_world.set_transform(&trigger, Transform::from_translation(Vec3A::new(0.0, 3.0, 0.0)));
+        let mut trigger_body = RigidBodyComponent::fixed();
+        trigger_body.settings.is_sensor = true;
+        ecs_world.insert_component(&trigger, trigger_body);
+        ecs_world.insert_component(&trigger, ColliderComponent::new(CubeCollider { dimension: Vector3::new(1.0, 1.0, 1.0) }));
+        ecs_world.set_transform(&ball, Transform::from_translation(Vec3A::new(0.0, 5.0, 0.0)));
+        let mut ball_body = RigidBodyComponent::dynamic();
+        ball_body.settings.restitution = 0.0;
+        ecs_world.insert_component(&ball, ball_body);
+        ecs_world.insert_component(&ball, ColliderComponent::new(SphereCollider { radius: 0.5 }));
+
+        let tick_duration = ecs_world.timestep().tick_duration();
+        for _ in 0..(2.0 / tick_duration) as u32 {
+            ecs_world.simulate(tick_duration, MovementInput::new());
+        }
+        // The ball fell through the trigger and stays below it after hitting the ground
+        let ball_height = ecs_world.get_transform(&ball).unwrap().translation.y;
+        assert!(ball_height < 2.0, "ball is at {}", ball_height);
+        let events = ecs_world.read_collision_events(&mut reader);
+        assert!(events.contains(&CollisionEvent::SensorEntered { sensor: trigger, entity: ball }));
+        assert!(events.contains(&CollisionEvent::SensorExited { sensor: trigger, entity: ball }));
+        assert!(events.iter().any(|event| match event {
+            CollisionEvent::ContactStarted { entity1, entity2 } => [*entity1, *entity2].contains(&ground) && [*entity1, *entity2].contains(&ball),
+            _ => false,
+        }));
+        // Events are only read once
+        assert!(ecs_world.read_collision_events(&mut reader).is_empty());
+    }
+
+    #[test]
+    fn runs_stages_in_order() {
+        let mut ecs_world = ECSWorld::builder()
+            .with_resource(StageLog::default())
+            .with_system(LogStageSystem(SystemStage::PreRender), "pre_render", SystemStage::PreRender, &[])
+            .with_system(LogStageSystem(SystemStage::PostUpdate), "post_update", SystemStage::PostUpdate, &[])
+            .with_system(LogStageSystem(SystemStage::Update), "update", SystemStage::Update, &[POSITION_INTEGRATOR_SYSTEM])
+            .with_system(LogStageSystem(SystemStage::PreUpdate), "pre_update", SystemStage::PreUpdate, &[])
+            .build();
+        let tick_duration = ecs_world.timestep().tick_duration();
+
+        assert_eq!(ecs_world.simulate(tick_duration * 2.5, MovementInput::new()), 2);
+        assert_eq!(ecs_world.specs_world().read_resource::<StageLog>().0, vec![
+            SystemStage::PreUpdate, SystemStage::Update, SystemStage::PostUpdate,
+            SystemStage::PreUpdate, SystemStage::Update, SystemStage::PostUpdate,
+            SystemStage::PreRender,
+        ]);
+
+        // No pre-render systems run without a tick
+        assert_eq!(ecs_world.simulate(0.0, MovementInput::new()), 0);
+        assert_eq!(ecs_world.specs_world().read_resource::<StageLog>().0.len(), 7);
+    }
+}
@@ -0,0 +1,475 @@
+use std::fmt;
+use std::fmt::Formatter;
+use std::path::Path;
+use glam::{Mat4, Quat, Vec2, Vec3, Vec3A};
+use newton::ColliderMesh;
+use crate::material::{AlphaMode, Material, TextureData};
+use crate::mesh::{MeshData, MeshVertex};
+use crate::transform::Transform;
+
+/// A mesh of a [Model], made up of one or more primitives.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModelMesh {
+    pub name: Option<String>,
+    pub primitives: Vec<MeshData>,
+}
+
+/// A node of the node hierarchy of a [Model].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModelNode {
+    pub name: Option<String>,
+    /// The transform of the node relative to its parent.
+    pub transform: Transform,
+    /// Index into [Model::meshes].
+    pub mesh: Option<usize>,
+    /// Indices into [Model::nodes].
+    pub children: Vec<usize>,
+}
+
+/// The CPU-side contents of a glTF 2.0 file.
+/// All geometry and transforms are converted from glTF's right-handed coordinate system
+/// into the left-handed coordinate system of the engine by mirroring along the Z axis.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Model {
+    pub meshes: Vec<ModelMesh>,
+    pub materials: Vec<Material>,
+    /// The images of the file, converted to RGBA8. Referenced by the texture indices of [Model::materials].
+    pub textures: Vec<TextureData>,
+    pub nodes: Vec<ModelNode>,
+    /// Indices into [Model::nodes] of the nodes that have no parent.
+    pub root_nodes: Vec<usize>,
+}
+
+impl Model {
+    /// The triangles of all primitives of the model in model space, eg. to build a collider from, see
+    /// [newton::ConvexHullCollider::from_mesh()].
+    /// The transforms of the nodes referencing the meshes are applied to the vertices.
+    pub fn collider_mesh(&self) -> ColliderMesh {
+        let mut collider_mesh = ColliderMesh::default();
+        let mut stack: Vec<(usize, Mat4)> = self.root_nodes.iter()
+            .map(|node_index| (*node_index, Mat4::IDENTITY))
+            .collect();
+        while let Some((node_index, parent_matrix)) = stack.pop() {
+            let node = &self.nodes[node_index];
+            let matrix = parent_matrix * node.transform.to_matrix();
+            if let Some(mesh_index) = node.mesh {
+                for primitive in &self.meshes[mesh_index].primitives {
+                    let positions: Vec<[f32; 3]> = primitive.vertices.iter()
+                        .map(|vertex| matrix.transform_point3(Vec3::from(vertex.position)).to_array())
+                        .collect();
+                    collider_mesh.append(&positions, &primitive.indices);
+                }
+            }
+            for child_index in &node.children {
+                stack.push((*child_index, matrix));
+            }
+        }
+        return collider_mesh;
+    }
+}
+
+#[derive(Debug)]
+pub enum ModelLoadError {
+    /// The file could not be read or is not valid glTF.
+    Gltf(gltf::Error),
+    /// A primitive has no POSITION attribute.
+    MissingPositions { mesh: usize, primitive: usize },
+    /// A primitive is not a triangle list.
+    UnsupportedPrimitiveMode { mesh: usize, primitive: usize },
+    /// A vertex attribute of a primitive has [count] values, but the primitive has [position_count] positions.
+    AttributeCountMismatch { mesh: usize, primitive: usize, attribute: &'static str, count: usize, position_count: usize },
+    /// An index of a primitive refers to a vertex the primitive does not have.
+    IndexOutOfRange { mesh: usize, primitive: usize },
+    /// An image has a pixel format that cannot be converted to RGBA8.
+    UnsupportedImageFormat { image: usize },
+}
+
+impl fmt::Display for ModelLoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ModelLoadError::Gltf(error) => write!(f, "Failed to load glTF: {}", error),
+            ModelLoadError::MissingPositions { mesh, primitive } => write!(f, "Primitive {} of mesh {} has no positions", primitive, mesh),
+            ModelLoadError::UnsupportedPrimitiveMode { mesh, primitive } => write!(f, "Primitive {} of mesh {} is not a triangle list", primitive, mesh),
+            ModelLoadError::AttributeCountMismatch { mesh, primitive, attribute, count, position_count } => write!(
+                f, "Primitive {} of mesh {} has {} {} values, but {} positions",
+                primitive, mesh, count, attribute, position_count
+            ),
+            ModelLoadError::IndexOutOfRange { mesh, primitive } => write!(f, "Primitive {} of mesh {} has an index out of range", primitive, mesh),
+            ModelLoadError::UnsupportedImageFormat { image } => write!(f, "Image {} has an unsupported pixel format", image),
+        }
+    }
+}
+
+impl From<gltf::Error> for ModelLoadError {
+    fn from(error: gltf::Error) -> Self {
+        return ModelLoadError::Gltf(error);
+    }
+}
+
+/// Loads a .gltf or .glb file. External buffers are resolved relative to [path].
+pub fn load_model_from_file<P: AsRef<Path>>(path: P) -> Result<Model, ModelLoadError> {
+    let (document, buffers, images) = gltf::import(path)?;
+    return convert_document(&document, &buffers, &images);
+}
+
+/// Loads a .gltf or .glb file from memory. Only embedded buffers are supported.
+pub fn load_model_from_slice(bytes: &[u8]) -> Result<Model, ModelLoadError> {
+    let (document, buffers, images) = gltf::import_slice(bytes)?;
+    return convert_document(&document, &buffers, &images);
+}
+
+fn convert_document(document: &gltf::Document, buffers: &[gltf::buffer::Data], images: &[gltf::image::Data]) -> Result<Model, ModelLoadError> {
+    let mut meshes = Vec::new();
+    for mesh in document.meshes() {
+        let mut primitives = Vec::new();
+        for primitive in mesh.primitives() {
+            primitives.push(convert_primitive(&primitive, mesh.index(), buffers)?);
+        }
+        meshes.push(ModelMesh { name: mesh.name().map(String::from), primitives });
+    }
+
+    let materials = document.materials()
+        .map(|material| convert_material(&material))
+        .collect();
+
+    let mut textures = Vec::new();
+    for (image_index, image) in images.iter().enumerate() {
+        textures.push(convert_image(image, image_index)?);
+    }
+
+    let nodes = document.nodes()
+        .map(|node| {
+            let (translation, rotation, scale) = node.transform().decomposed();
+            ModelNode {
+                name: node.name().map(String::from),
+                transform: Transform::new(
+                    mirror_z(translation),
+                    // Mirroring along Z flips the sense of rotations around the X and Y axes.
+                    Quat::from_xyzw(-rotation[0], -rotation[1], rotation[2], rotation[3]),
+                    Vec3A::from(scale),
+                ),
+                mesh: node.mesh().map(|mesh| mesh.index()),
+                children: node.children().map(|child| child.index()).collect(),
+            }
+        })
+        .collect::<Vec<ModelNode>>();
+
+    let root_nodes = match document.default_scene().or_else(|| document.scenes().next()) {
+        Some(scene) => scene.nodes().map(|node| node.index()).collect(),
+        None => {
+            // Without a scene, every node that is not the child of another node is a root.
+            (0..nodes.len())
+                .filter(|index| !nodes.iter().any(|node| node.children.contains(index)))
+                .collect()
+        }
+    };
+
+    return Ok(Model { meshes, materials, textures, nodes, root_nodes });
+}
+
+fn convert_material(material: &gltf::Material) -> Material {
+    let pbr = material.pbr_metallic_roughness();
+    // Textures are referenced by the index of their image, which is the index into Model::textures
+    let image_index = |texture: gltf::Texture| texture.source().index();
+    return Material {
+        name: material.name().map(String::from),
+        base_color_factor: pbr.base_color_factor(),
+        base_color_texture: pbr.base_color_texture().map(|info| image_index(info.texture())),
+        metallic_factor: pbr.metallic_factor(),
+        roughness_factor: pbr.roughness_factor(),
+        metallic_roughness_texture: pbr.metallic_roughness_texture().map(|info| image_index(info.texture())),
+        normal_texture: material.normal_texture().map(|normal| image_index(normal.texture())),
+        normal_scale: material.normal_texture().map(|normal| normal.scale()).unwrap_or(1.0),
+        occlusion_texture: material.occlusion_texture().map(|occlusion| image_index(occlusion.texture())),
+        occlusion_strength: material.occlusion_texture().map(|occlusion| occlusion.strength()).unwrap_or(1.0),
+        emissive_factor: material.emissive_factor(),
+        emissive_texture: material.emissive_texture().map(|info| image_index(info.texture())),
+        alpha_mode: match material.alpha_mode() {
+            gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+            gltf::material::AlphaMode::Mask => AlphaMode::Mask,
+            gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+        },
+        alpha_cutoff: material.alpha_cutoff().unwrap_or(0.5),
+        double_sided: material.double_sided(),
+    };
+}
+
+fn convert_image(image: &gltf::image::Data, image_index: usize) -> Result<TextureData, ModelLoadError> {
+    use gltf::image::Format;
+    let pixel_count = (image.width * image.height) as usize;
+    let mut pixels = Vec::with_capacity(pixel_count * 4);
+    match image.format {
+        Format::R8G8B8A8 => pixels.extend_from_slice(&image.pixels),
+        Format::R8G8B8 => {
+            for rgb in image.pixels.chunks_exact(3) {
+                pixels.extend_from_slice(&[rgb[0], rgb[1], rgb[2], 255]);
+            }
+        }
+        Format::R8G8 => {
+            for rg in image.pixels.chunks_exact(2) {
+                pixels.extend_from_slice(&[rg[0], rg[1], 0, 255]);
+            }
+        }
+        Format::R8 => {
+            for r in &image.pixels {
+                pixels.extend_from_slice(&[*r, *r, *r, 255]);
+            }
+        }
+        Format::R16G16B16A16 => {
+            // Keep the most significant byte of each little endian 16 bit channel
+            for rgba in image.pixels.chunks_exact(8) {
+                pixels.extend_from_slice(&[rgba[1], rgba[3], rgba[5], rgba[7]]);
+            }
+        }
+        Format::R16G16B16 => {
+            for rgb in image.pixels.chunks_exact(6) {
+                pixels.extend_from_slice(&[rgb[1], rgb[3], rgb[5], 255]);
+            }
+        }
+        _ => return Err(ModelLoadError::UnsupportedImageFormat { image: image_index }),
+    }
+    return Ok(TextureData { width: image.width, height: image.height, pixels });
+}
+
+fn convert_primitive(primitive: &gltf::Primitive, mesh_index: usize, buffers: &[gltf::buffer::Data]) -> Result<MeshData, ModelLoadError> {
+    if primitive.mode() != gltf::mesh::Mode::Triangles {
+        return Err(ModelLoadError::UnsupportedPrimitiveMode { mesh: mesh_index, primitive: primitive.index() });
+    }
+    let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| &data.0[..]));
+
+    let positions: Vec<Vec3A> = match reader.read_positions() {
+        Some(positions) => positions.map(mirror_z).collect(),
+        None => return Err(ModelLoadError::MissingPositions { mesh: mesh_index, primitive: primitive.index() }),
+    };
+
+    // Mirroring flips the handedness together with the winding order, so with the left-handed view and projection
+    // the front faces stay counter-clockwise on screen and the indices are kept as they are.
+    let indices: Vec<u32> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..positions.len() as u32).collect(),
+    };
+    if indices.iter().any(|index| *index as usize >= positions.len()) {
+        return Err(ModelLoadError::IndexOutOfRange { mesh: mesh_index, primitive: primitive.index() });
+    }
+    // All attributes must have a value for every vertex, which is not guaranteed by the glTF loader
+    let check_count = |attribute: &'static str, count: usize| {
+        if count != positions.len() {
+            return Err(ModelLoadError::AttributeCountMismatch { mesh: mesh_index, primitive: primitive.index(), attribute, count, position_count: positions.len() });
+        }
+        return Ok(());
+    };
+
+    let normals: Vec<Vec3A> = match reader.read_normals() {
+        Some(normals) => normals.map(mirror_z).collect(),
+        None => compute_vertex_normals(&positions, &indices),
+    };
+    check_count("normal", normals.len())?;
+
+    let tex_coords: Vec<[f32; 2]> = match reader.read_tex_coords(0) {
+        Some(tex_coords) => tex_coords.into_f32().collect(),
+        None => vec![[0.0, 0.0]; positions.len()],
+    };
+    check_count("texture coordinate", tex_coords.len())?;
+
+    let tangents: Vec<[f32; 4]> = match reader.read_tangents() {
+        // Mirroring flips the handedness of the tangent frame, thus the bitangent sign is flipped as well
+        Some(tangents) => tangents.map(|tangent| [tangent[0], tangent[1], -tangent[2], -tangent[3]]).collect(),
+        None => compute_vertex_tangents(&positions, &normals, &tex_coords, &indices),
+    };
+    check_count("tangent", tangents.len())?;
+
+    let vertices = positions.iter()
+        .zip(normals.iter())
+        .zip(tex_coords.iter())
+        .zip(tangents.iter())
+        .map(|(((position, normal), tex_coords), tangent)| MeshVertex {
+            position: position.to_array(),
+            normal: normal.to_array(),
+            tex_coords: *tex_coords,
+            tangent: *tangent,
+        })
+        .collect();
+
+    return Ok(MeshData { vertices, indices, material: primitive.material().index() });
+}
+
+/// Converts a vector from glTF's right-handed coordinate system into the engine's left-handed coordinate system.
+fn mirror_z(vector: [f32; 3]) -> Vec3A {
+    return Vec3A::new(vector[0], vector[1], -vector[2]);
+}
+
+/// Computes smooth vertex normals by accumulating the area weighted face normals of all triangles sharing a vertex.
+/// In the left-handed coordinate system, the front face of a counter-clockwise triangle a, b, c points along
+/// -cross(b - a, c - a).
+fn compute_vertex_normals(positions: &[Vec3A], indices: &[u32]) -> Vec<Vec3A> {
+    let mut normals = vec![Vec3A::ZERO; positions.len()];
+    for triangle in indices.chunks_exact(3) {
+        let (a, b, c) = (triangle[0] as usize, triangle[1] as usize, triangle[2] as usize);
+        let face_normal = -(positions[b] - positions[a]).cross(positions[c] - positions[a]);
+        normals[a] += face_normal;
+        normals[b] += face_normal;
+        normals[c] += face_normal;
+    }
+    return normals.into_iter()
+        .map(|normal| normal.normalize_or_zero())
+        .collect();
+}
+
+/// Computes per vertex tangents from the texture coordinate gradients of the triangles sharing a vertex.
+/// The tangent is orthogonalized against the vertex normal. The bitangent sign is chosen such that
+/// cross(normal, tangent) * sign points along increasing V. Neither depends on the winding order of the triangles.
+fn compute_vertex_tangents(positions: &[Vec3A], normals: &[Vec3A], tex_coords: &[[f32; 2]], indices: &[u32]) -> Vec<[f32; 4]> {
+    let mut tangents = vec![Vec3A::ZERO; positions.len()];
+    let mut bitangents = vec![Vec3A::ZERO; positions.len()];
+    for triangle in indices.chunks_exact(3) {
+        let (a, b, c) = (triangle[0] as usize, triangle[1] as usize, triangle[2] as usize);
+        let edge1 = positions[b] - positions[a];
+        let edge2 = positions[c] - positions[a];
+        let delta_uv1 = Vec2::from(tex_coords[b]) - Vec2::from(tex_coords[a]);
+        let delta_uv2 = Vec2::from(tex_coords[c]) - Vec2::from(tex_coords[a]);
+        let determinant = delta_uv1.x * delta_uv2.y - delta_uv2.x * delta_uv1.y;
+        if determinant.abs() < f32::EPSILON {
+            // Degenerate texture mapping, the triangle does not contribute
+            continue;
+        }
+        let tangent = (edge1 * delta_uv2.y - edge2 * delta_uv1.y) / determinant;
+        let bitangent = (edge2 * delta_uv1.x - edge1 * delta_uv2.x) / determinant;
+        for vertex in [a, b, c] {
+            tangents[vertex] += tangent;
+            bitangents[vertex] += bitangent;
+        }
+    }
+    return (0..positions.len())
+        .map(|vertex| {
+            let normal = normals[vertex];
+            let tangent = (tangents[vertex] - normal * normal.dot(tangents[vertex])).normalize_or_zero();
+            let sign = if normal.cross(tangent).dot(bitangents[vertex]) < 0.0 { -1.0 } else { 1.0 };
+            [tangent.x, tangent.y, tangent.z, sign]
+        })
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Quat, Vec3A};
+    use super::*;
+
+    /// A single triangle (0, 0, 0), (1, 0, 0), (0, 1, 0) without normals, referenced by the child of a translated
+    /// root node.
+    const TRIANGLE_GLTF: &str = r#"{
+        "asset": { "version": "2.0" },
+        "scene": 0,
+        "scenes": [ { "nodes": [ 0 ] } ],
+        "nodes": [
+            { "name": "root", "translation": [ 1.0, 2.0, 3.0 ], "children": [ 1 ] },
+            { "name": "triangle", "mesh": 0 }
+        ],
+        "meshes": [ { "name": "triangle", "primitives": [ { "attributes": { "POSITION": 0 }, "indices": 1, "material": 0 } ] } ],
+        "materials": [
+            {
+                "name": "red",
+                "pbrMetallicRoughness": { "baseColorFactor": [ 1.0, 0.0, 0.0, 1.0 ], "metallicFactor": 0.0 },
+                "alphaMode": "MASK",
+                "doubleSided": true
+            }
+        ],
+        "buffers": [ { "byteLength": 44, "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAABAAIAAAA=" } ],
+        "bufferViews": [
+            { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+            { "buffer": 0, "byteOffset": 36, "byteLength": 6 }
+        ],
+        "accessors": [
+            { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [ 0.0, 0.0, 0.0 ], "max": [ 1.0, 1.0, 0.0 ] },
+            { "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }
+        ]
+    }"#;
+
+    #[test]
+    fn loads_node_hierarchy() {
+        let model = load_model_from_slice(TRIANGLE_GLTF.as_bytes()).unwrap();
+
+        assert_eq!(model.root_nodes, vec![0]);
+        assert_eq!(model.nodes.len(), 2);
+        assert_eq!(model.nodes[0].name.as_deref(), Some("root"));
+        assert_eq!(model.nodes[0].children, vec![1]);
+        assert_eq!(model.nodes[0].mesh, None);
+        assert_eq!(model.nodes[1].mesh, Some(0));
+        assert_eq!(model.nodes[0].transform.translation, Vec3A::new(1.0, 2.0, -3.0));
+        assert_eq!(model.nodes[0].transform.rotation, Quat::IDENTITY);
+    }
+
+    #[test]
+    fn loads_accessors_into_left_handed_triangle_list() {
+        let model = load_model_from_slice(TRIANGLE_GLTF.as_bytes()).unwrap();
+
+        assert_eq!(model.meshes.len(), 1);
+        let primitive = &model.meshes[0].primitives[0];
+        let positions: Vec<[f32; 3]> = primitive.vertices.iter().map(|vertex| vertex.position).collect();
+        assert_eq!(positions, vec![[0.0, 0.0, -0.0], [1.0, 0.0, -0.0], [0.0, 1.0, -0.0]]);
+        // The triangle is counter-clockwise seen from -Z, where it faces in the engine, and thus stays a front face
+        assert_eq!(primitive.indices, vec![0, 1, 2]);
+        // The missing normals are computed from the faces. The triangle faces +Z in glTF and thus -Z in the engine.
+        for vertex in &primitive.vertices {
+            assert_eq!(vertex.normal, [0.0, 0.0, -1.0]);
+        }
+    }
+
+    #[test]
+    fn loads_materials() {
+        let model = load_model_from_slice(TRIANGLE_GLTF.as_bytes()).unwrap();
+
+        assert_eq!(model.meshes[0].primitives[0].material, Some(0));
+        assert_eq!(model.materials.len(), 1);
+        assert!(model.textures.is_empty());
+        let material = &model.materials[0];
+        assert_eq!(material.name.as_deref(), Some("red"));
+        assert_eq!(material.base_color_factor, [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(material.metallic_factor, 0.0);
+        assert_eq!(material.roughness_factor, 1.0);
+        assert_eq!(material.alpha_mode, AlphaMode::Mask);
+        assert_eq!(material.alpha_cutoff, 0.5);
+        assert!(material.double_sided);
+        assert_eq!(material.base_color_texture, None);
+    }
+
+    #[test]
+    fn builds_collider_mesh_in_model_space() {
+        let model = load_model_from_slice(TRIANGLE_GLTF.as_bytes()).unwrap();
+
+        let collider_mesh = model.collider_mesh();
+        let vertices: Vec<[f32; 3]> = collider_mesh.vertices.iter().map(|vertex| [vertex.x, vertex.y, vertex.z]).collect();
+        assert_eq!(vertices, vec![[1.0, 2.0, -3.0], [2.0, 2.0, -3.0], [1.0, 3.0, -3.0]]);
+        assert_eq!(collider_mesh.indices, vec![[0, 1, 2]]);
+    }
+
+    #[test]
+    fn rejects_attributes_of_differing_count() {
+        // Only two normals for the three positions
+        let gltf = TRIANGLE_GLTF
+            .replace(r#""POSITION": 0"#, r#""POSITION": 0, "NORMAL": 2"#)
+            .replace(
+                r#"{ "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }"#,
+                r#"{ "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" },
+                   { "bufferView": 0, "componentType": 5126, "count": 2, "type": "VEC3" }"#,
+            );
+        assert!(matches!(
+            load_model_from_slice(gltf.as_bytes()),
+            Err(ModelLoadError::AttributeCountMismatch { attribute: "normal", count: 2, position_count: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_indices_out_of_range() {
+        // Only two positions for the indices 0, 1, 2
+        let gltf = TRIANGLE_GLTF.replace(r#""count": 3, "type": "VEC3""#, r#""count": 2, "type": "VEC3""#);
+        assert!(matches!(
+            load_model_from_slice(gltf.as_bytes()),
+            Err(ModelLoadError::IndexOutOfRange { mesh: 0, primitive: 0 })
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_gltf() {
+        assert!(load_model_from_slice(b"not a gltf file").is_err());
+    }
+}
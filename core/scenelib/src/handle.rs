@@ -0,0 +1,141 @@
+/// A handle to an object stored in a slot that may be reused once the object is removed.
+/// The handle remembers the generation of the slot at the time it was issued. Removing the object increments the
+/// generation of its slot, so a handle that outlived its object is detected as stale instead of silently referring
+/// to the next object occupying the same slot.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GenerationalHandle {
+    index: u32,
+    generation: u32,
+}
+
+impl GenerationalHandle {
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Packs the handle into a single integer, eg. to store it in foreign user data fields.
+    pub fn to_bits(&self) -> u64 {
+        return ((self.generation as u64) << 32) | self.index as u64;
+    }
+
+    /// Inverse of [Self::to_bits()].
+    pub fn from_bits(bits: u64) -> Self {
+        return GenerationalHandle {
+            index: bits as u32,
+            generation: (bits >> 32) as u32,
+        };
+    }
+}
+
+/// Issues [GenerationalHandle]s and keeps track of which of them are still alive.
+pub(crate) struct HandleAllocator {
+    /// The current generation of each slot.
+    generations: Vec<u32>,
+    /// Whether each slot is currently occupied.
+    occupied: Vec<bool>,
+    free_indices: Vec<u32>,
+}
+
+impl HandleAllocator {
+    pub(crate) fn new() -> Self {
+        return HandleAllocator {
+            generations: Vec::new(),
+            occupied: Vec::new(),
+            free_indices: Vec::new(),
+        };
+    }
+
+    pub(crate) fn allocate(&mut self) -> GenerationalHandle {
+        return match self.free_indices.pop() {
+            Some(index) => {
+                self.occupied[index as usize] = true;
+                GenerationalHandle { index, generation: self.generations[index as usize] }
+            }
+            None => {
+                let index = self.generations.len() as u32;
+                self.generations.push(0);
+                self.occupied.push(true);
+                GenerationalHandle { index, generation: 0 }
+            }
+        };
+    }
+
+    /// Frees the slot referenced by [handle]. Returns false if [handle] was already stale.
+    pub(crate) fn free(&mut self, handle: &GenerationalHandle) -> bool {
+        if !self.is_alive(handle) {
+            return false;
+        }
+        let index = handle.index as usize;
+        self.occupied[index] = false;
+        self.generations[index] = self.generations[index].wrapping_add(1);
+        self.free_indices.push(handle.index);
+        return true;
+    }
+
+    pub(crate) fn is_alive(&self, handle: &GenerationalHandle) -> bool {
+        let index = handle.index as usize;
+        return index < self.generations.len()
+            && self.occupied[index]
+            && self.generations[index] == handle.generation;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocates_distinct_live_handles() {
+        let mut allocator = HandleAllocator::new();
+        let first = allocator.allocate();
+        let second = allocator.allocate();
+
+        assert_ne!(first, second);
+        assert!(allocator.is_alive(&first));
+        assert!(allocator.is_alive(&second));
+        assert!(!allocator.is_alive(&GenerationalHandle::from_bits(2)));
+    }
+
+    #[test]
+    fn reused_slots_get_a_new_generation() {
+        let mut allocator = HandleAllocator::new();
+        let freed = allocator.allocate();
+        let kept = allocator.allocate();
+        assert!(allocator.free(&freed));
+        assert!(!allocator.is_alive(&freed));
+        assert!(allocator.is_alive(&kept));
+
+        let reused = allocator.allocate();
+        assert_eq!(reused.index(), freed.index());
+        assert_eq!(reused.generation(), freed.generation() + 1);
+        assert!(allocator.is_alive(&reused));
+        // The stale handle does not refer to the new occupant of its slot
+        assert!(!allocator.is_alive(&freed));
+    }
+
+    #[test]
+    fn double_free_is_rejected() {
+        let mut allocator = HandleAllocator::new();
+        let handle = allocator.allocate();
+        assert!(allocator.free(&handle));
+        assert!(!allocator.free(&handle));
+
+        // The slot is only handed out once
+        let first = allocator.allocate();
+        let second = allocator.allocate();
+        assert_ne!(first.index(), second.index());
+    }
+
+    #[test]
+    fn round_trips_through_bits() {
+        let mut allocator = HandleAllocator::new();
+        let handle = allocator.allocate();
+        allocator.free(&handle);
+        let handle = allocator.allocate();
+        assert_eq!(GenerationalHandle::from_bits(handle.to_bits()), handle);
+    }
+}
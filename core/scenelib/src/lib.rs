@@ -4,4 +4,19 @@
  */
 pub mod scene;
 pub mod camera;
-pub mod ecs;
\ No newline at end of file
+pub mod culling;
+pub mod ecs;
+pub mod gltf_loader;
+pub mod handle;
+pub mod light;
+pub mod material;
+pub mod mesh;
+pub mod pipeline;
+pub mod prefab;
+pub mod render_phase;
+pub mod scene_file;
+pub mod scene_graph;
+pub mod shadow;
+pub mod snapshot;
+pub mod timestep;
+pub mod transform;
\ No newline at end of file
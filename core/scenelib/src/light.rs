@@ -0,0 +1,362 @@
+use std::any::Any;
+use bytemuck::Zeroable;
+use glam::{Mat4, Vec3, Vec3A};
+use wgpu::util::DeviceExt;
+use wgpu::{Device, Queue};
+use crate::culling::Frustum;
+use crate::render_phase::RenderPhase;
+use crate::scene::{RenderCallState, RenderNode, RenderNodeHandle, RenderScene, StaticRenderState};
+use crate::shadow::{ShadowMapAllocation, ShadowRenderState, MAX_SHADOW_MAPS};
+
+/// The maximum number of lights that contribute to a frame. Excess lights are culled by importance.
+pub const MAX_LIGHTS: usize = 64;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LightKind {
+    /// Infinitely far away light (eg. the sun) shining along the node's forward (+Z) axis.
+    Directional,
+    /// Light emitted uniformly in all directions from the node's position.
+    /// [range] is the distance at which the light's contribution reaches zero.
+    Point { range: f32 },
+    /// Light emitted from the node's position in a cone around the node's forward (+Z) axis.
+    /// The intensity falls off from the full intensity at [inner_cone_angle] to zero at [outer_cone_angle].
+    /// unit: radians
+    Spot { range: f32, inner_cone_angle: f32, outer_cone_angle: f32 },
+}
+
+impl LightKind {
+    /// The light type id used by the shader.
+    fn shader_type(&self) -> f32 {
+        return match self {
+            LightKind::Directional => 0.0,
+            LightKind::Point { .. } => 1.0,
+            LightKind::Spot { .. } => 2.0,
+        };
+    }
+}
+
+/// A punctual light, following the glTF KHR_lights_punctual light model.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Light {
+    pub kind: LightKind,
+    /// Linear RGB color of the light.
+    pub color: Vec3A,
+    /// Illuminance in lux for directional lights, luminous intensity in candela for point and spot lights.
+    pub intensity: f32,
+    /// Whether the light is occluded by the scene geometry. Only supported by directional and spot lights.
+    pub casts_shadows: bool,
+}
+
+impl Light {
+    pub fn directional(color: Vec3A, intensity: f32) -> Self {
+        return Light { kind: LightKind::Directional, color, intensity, casts_shadows: false };
+    }
+
+    pub fn point(color: Vec3A, intensity: f32, range: f32) -> Self {
+        return Light { kind: LightKind::Point { range }, color, intensity, casts_shadows: false };
+    }
+
+    pub fn spot(color: Vec3A, intensity: f32, range: f32, inner_cone_angle: f32, outer_cone_angle: f32) -> Self {
+        return Light { kind: LightKind::Spot { range, inner_cone_angle, outer_cone_angle }, color, intensity, casts_shadows: false };
+    }
+
+    pub fn with_shadows(mut self, casts_shadows: bool) -> Self {
+        self.casts_shadows = casts_shadows;
+        return self;
+    }
+
+    /// The distance at which the light's contribution reaches zero. None for directional lights.
+    pub fn range(&self) -> Option<f32> {
+        return match self.kind {
+            LightKind::Directional => None,
+            LightKind::Point { range } => Some(range),
+            LightKind::Spot { range, .. } => Some(range),
+        };
+    }
+}
+
+// We need this for Rust to store our data correctly for the shaders
+#[repr(C)]
+// This is so we can store this in a buffer
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightShaderState {
+    // xyz: world space position, w: range
+    position: [f32; 4],
+    // xyz: world space direction the light shines in, w: light type
+    direction: [f32; 4],
+    // rgb: color multiplied with intensity, a is unused
+    color: [f32; 4],
+    // x: cosine of the outer cone angle, y: cosine of the inner cone angle, zw are unused
+    cone: [f32; 4],
+    // x: first shadow map layer (negative if the light casts no shadows), y: number of shadow map layers, zw are unused
+    shadow: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightsShaderState {
+    count: u32,
+    // Aligns the matrices to 16 bytes
+    _padding: [u32; 3],
+    // The light space view projection matrix of every shadow map layer
+    shadow_view_projs: [[[f32; 4]; 4]; MAX_SHADOW_MAPS],
+    lights: [LightShaderState; MAX_LIGHTS],
+}
+
+/// A light in the scene graph. Its position and direction are derived from the node's world transform.
+pub struct LightRenderNode {
+    light: Light,
+    position: Vec3A,
+    direction: Vec3A,
+}
+
+impl RenderNode for LightRenderNode {
+    fn is_dirty(&self) -> bool {
+        // The light buffer is rebuilt by the scene every frame
+        return false;
+    }
+
+    fn render<'a, 'b: 'a>(&'b mut self, _static_render_state: &'b StaticRenderState, _render_call_state: &mut RenderCallState<'_, 'b>) {}
+
+    fn render_phase(&self) -> RenderPhase {
+        return RenderPhase::Setup;
+    }
+
+    fn resolve_dirty_state(&mut self, _static_render_state: &mut StaticRenderState) {}
+
+    fn set_world_transform(&mut self, world_transform: &Mat4) {
+        self.position = Vec3A::from(world_transform.transform_point3(Vec3::ZERO));
+        self.direction = Vec3A::from(world_transform.transform_vector3(Vec3::Z)).normalize_or_zero();
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl LightRenderNode {
+    pub fn add_new(light: Light, scene: &mut RenderScene) -> RenderNodeHandle {
+        let light_node = LightRenderNode {
+            light,
+            position: Vec3A::ZERO,
+            direction: Vec3A::Z,
+        };
+        return scene.add_node(Box::new(light_node));
+    }
+
+    pub fn light(&self) -> &Light {
+        return &self.light;
+    }
+
+    pub fn set_light(&mut self, light: Light) {
+        self.light = light;
+    }
+
+    pub fn position(&self) -> Vec3A {
+        return self.position;
+    }
+
+    pub fn direction(&self) -> Vec3A {
+        return self.direction;
+    }
+
+    /// How much the light contributes to what a camera at [camera_position] sees, used to rank lights when
+    /// there are more than [MAX_LIGHTS]. Returns None if the light cannot reach anything inside of
+    /// [camera_frustum].
+    fn importance(&self, camera_position: Vec3A, camera_frustum: &Frustum) -> Option<f32> {
+        let range = match self.light.range() {
+            Some(range) => range,
+            // Directional lights affect everything
+            None => return Some(f32::INFINITY),
+        };
+        // Spot lights are tested with the sphere around their cone
+        if !camera_frustum.intersects_sphere(self.position, range) {
+            return None;
+        }
+        let distance = self.position.distance(camera_position);
+        let brightness = self.light.intensity * self.light.color.max_element();
+        return Some(brightness / (distance * distance).max(1.0));
+    }
+
+    fn shader_state(&self, shadow_layers: Option<(usize, usize)>) -> LightShaderState {
+        let (range, inner_cone_angle, outer_cone_angle) = match self.light.kind {
+            LightKind::Directional => (0.0, 0.0, 0.0),
+            LightKind::Point { range } => (range, 0.0, 0.0),
+            LightKind::Spot { range, inner_cone_angle, outer_cone_angle } => (range, inner_cone_angle, outer_cone_angle),
+        };
+        let color = self.light.color * self.light.intensity;
+        return LightShaderState {
+            position: self.position.extend(range).to_array(),
+            direction: self.direction.extend(self.light.kind.shader_type()).to_array(),
+            color: color.extend(0.0).to_array(),
+            cone: [outer_cone_angle.cos(), inner_cone_angle.cos(), 0.0, 0.0],
+            shadow: match shadow_layers {
+                Some((first_layer, layer_count)) => [first_layer as f32, layer_count as f32, 0.0, 0.0],
+                None => [-1.0, 0.0, 0.0, 0.0],
+            },
+        };
+    }
+}
+
+/// Selects the lights that are uploaded for a camera at [camera_position] that sees [camera_frustum].
+/// Point and spot lights whose range lies outside of the frustum are culled. If more than [MAX_LIGHTS] lights
+/// remain, the most important ones are kept; directional lights are always kept first.
+pub(crate) fn cull_lights<'a, I: Iterator<Item=&'a LightRenderNode>>(lights: I, camera_position: Vec3A, camera_frustum: &Frustum) -> Vec<&'a LightRenderNode> {
+    let mut ranked_lights: Vec<(f32, &LightRenderNode)> = lights
+        .filter_map(|light| light.importance(camera_position, camera_frustum).map(|importance| (importance, light)))
+        .collect();
+    // Stable sort, lights of equal importance keep their scene order
+    ranked_lights.sort_by(|(a, _), (b, _)| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+    ranked_lights.truncate(MAX_LIGHTS);
+    return ranked_lights.into_iter()
+        .map(|(_, light)| light)
+        .collect();
+}
+
+/// The GPU-side list of lights of the frame and their shadow maps. Bound at [crate::scene::LIGHT_BIND_GROUP].
+pub struct LightRenderState {
+    light_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl LightRenderState {
+    pub(crate) fn new(device: &Device, layout: &wgpu::BindGroupLayout, shadow_render_state: &ShadowRenderState) -> Self {
+        let light_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("LightBuffer"),
+            contents: bytemuck::cast_slice(&[LightsShaderState::zeroed()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: light_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(shadow_render_state.array_view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(shadow_render_state.sampler()),
+                },
+            ],
+            label: Some("light_bind_group"),
+        });
+        return LightRenderState { light_buffer, bind_group };
+    }
+
+    pub(crate) fn create_bind_group_layout(device: &Device) -> wgpu::BindGroupLayout {
+        return device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                    count: None,
+                },
+            ],
+            label: Some("light_bind_group_layout"),
+        });
+    }
+
+    /// Uploads [lights], which must not contain more than [MAX_LIGHTS] lights, along with the shadow maps
+    /// [shadow_maps] assigns to them.
+    pub(crate) fn upload(&self, queue: &Queue, lights: &[&LightRenderNode], shadow_maps: &ShadowMapAllocation) {
+        let mut shader_state = LightsShaderState::zeroed();
+        shader_state.count = lights.len() as u32;
+        for (layer, view_proj) in shadow_maps.view_projs.iter().enumerate() {
+            shader_state.shadow_view_projs[layer] = view_proj.to_cols_array_2d();
+        }
+        for (slot, light) in lights.iter().enumerate() {
+            shader_state.lights[slot] = light.shader_state(shadow_maps.light_layers[slot]);
+        }
+        queue.write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&[shader_state]));
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        return &self.bind_group;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The frustum of a camera at the origin looking along +Z with an infinite far plane.
+    fn camera_frustum() -> Frustum {
+        let view = Mat4::look_at_lh(Vec3::ZERO, Vec3::Z, Vec3::Y);
+        let projection = Mat4::perspective_infinite_reverse_lh(90.0_f32.to_radians(), 1.0, 0.1);
+        return Frustum::from_view_proj(&(projection * view));
+    }
+
+    fn light_node(light: Light, position: Vec3A) -> LightRenderNode {
+        return LightRenderNode { light, position, direction: Vec3A::Z };
+    }
+
+    fn positions(lights: &[&LightRenderNode]) -> Vec<Vec3A> {
+        return lights.iter().map(|light| light.position()).collect();
+    }
+
+    #[test]
+    fn culls_lights_out_of_view() {
+        let lights = vec![
+            light_node(Light::point(Vec3A::ONE, 10.0, 5.0), Vec3A::new(0.0, 0.0, -10.0)),
+            // Behind the camera, but reaches into the view
+            light_node(Light::point(Vec3A::ONE, 10.0, 5.0), Vec3A::new(0.0, 0.0, -3.0)),
+            light_node(Light::spot(Vec3A::ONE, 10.0, 5.0, 0.2, 0.4), Vec3A::new(0.0, 0.0, 1000.0)),
+            light_node(Light::point(Vec3A::ONE, 10.0, 5.0), Vec3A::new(-100.0, 0.0, 10.0)),
+            light_node(Light::directional(Vec3A::ONE, 1.0), Vec3A::new(0.0, 0.0, -100.0)),
+        ];
+        let culled = cull_lights(lights.iter(), Vec3A::ZERO, &camera_frustum());
+        assert_eq!(positions(&culled), vec![Vec3A::new(0.0, 0.0, -100.0), Vec3A::new(0.0, 0.0, -3.0), Vec3A::new(0.0, 0.0, 1000.0)]);
+    }
+
+    #[test]
+    fn ranks_directional_lights_first_then_by_brightness_and_distance() {
+        let lights = vec![
+            light_node(Light::point(Vec3A::ONE, 100.0, 50.0), Vec3A::new(0.0, 0.0, 20.0)),
+            light_node(Light::point(Vec3A::ONE, 100.0, 50.0), Vec3A::new(0.0, 0.0, 10.0)),
+            light_node(Light::point(Vec3A::ONE, 1000.0, 50.0), Vec3A::new(0.0, 0.0, 30.0)),
+            light_node(Light::directional(Vec3A::ONE, 0.1), Vec3A::ZERO),
+        ];
+        let culled = cull_lights(lights.iter(), Vec3A::ZERO, &camera_frustum());
+        assert_eq!(positions(&culled), vec![Vec3A::ZERO, Vec3A::new(0.0, 0.0, 30.0), Vec3A::new(0.0, 0.0, 10.0), Vec3A::new(0.0, 0.0, 20.0)]);
+    }
+
+    #[test]
+    fn keeps_the_most_important_lights_up_to_the_maximum() {
+        let lights: Vec<LightRenderNode> = (0..MAX_LIGHTS + 10).rev()
+            .map(|index| light_node(Light::point(Vec3A::ONE, 10.0, 5.0), Vec3A::new(0.0, 0.0, 2.0 + index as f32)))
+            .collect();
+        let culled = cull_lights(lights.iter(), Vec3A::ZERO, &camera_frustum());
+        assert_eq!(culled.len(), MAX_LIGHTS);
+        let expected: Vec<Vec3A> = (0..MAX_LIGHTS).map(|index| Vec3A::new(0.0, 0.0, 2.0 + index as f32)).collect();
+        assert_eq!(positions(&culled), expected);
+    }
+}
@@ -0,0 +1,357 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use wgpu::util::DeviceExt;
+use wgpu::Device;
+use crate::scene::StaticRenderState;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AlphaMode {
+    /// The alpha value is ignored and the rendered output is fully opaque.
+    Opaque,
+    /// Fragments with an alpha value below [Material::alpha_cutoff] are discarded, all others are opaque.
+    Mask,
+    /// The rendered output is blended with the background.
+    Blend,
+}
+
+/// An RGBA8 image used as a material texture.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextureData {
+    pub width: u32,
+    pub height: u32,
+    /// Tightly packed rows of RGBA8 pixels.
+    pub pixels: Vec<u8>,
+}
+
+/// A metallic-roughness PBR material, following the glTF 2.0 material model.
+/// Texture references are indices into the list of [TextureData]s the material is created with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Material {
+    pub name: Option<String>,
+    /// Linear RGBA base color, multiplied with the base color texture (sRGB).
+    pub base_color_factor: [f32; 4],
+    pub base_color_texture: Option<usize>,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    /// Roughness is sampled from the green channel, metalness from the blue channel.
+    pub metallic_roughness_texture: Option<usize>,
+    /// Tangent space normal map.
+    pub normal_texture: Option<usize>,
+    pub normal_scale: f32,
+    /// Ambient occlusion is sampled from the red channel.
+    pub occlusion_texture: Option<usize>,
+    pub occlusion_strength: f32,
+    /// Linear RGB emitted light, multiplied with the emissive texture (sRGB).
+    pub emissive_factor: [f32; 3],
+    pub emissive_texture: Option<usize>,
+    pub alpha_mode: AlphaMode,
+    pub alpha_cutoff: f32,
+    /// Whether back faces are rendered (with flipped normals) instead of culled.
+    pub double_sided: bool,
+}
+
+impl Default for Material {
+    /// The glTF default material: opaque, white, fully metallic and fully rough.
+    fn default() -> Self {
+        return Material {
+            name: None,
+            base_color_factor: [1.0, 1.0, 1.0, 1.0],
+            base_color_texture: None,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            normal_scale: 1.0,
+            occlusion_texture: None,
+            occlusion_strength: 1.0,
+            emissive_factor: [0.0, 0.0, 0.0],
+            emissive_texture: None,
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+            double_sided: false,
+        };
+    }
+}
+
+impl Material {
+    pub fn features(&self) -> MaterialFeatures {
+        return MaterialFeatures {
+            base_color_texture: self.base_color_texture.is_some(),
+            metallic_roughness_texture: self.metallic_roughness_texture.is_some(),
+            normal_texture: self.normal_texture.is_some(),
+            occlusion_texture: self.occlusion_texture.is_some(),
+            emissive_texture: self.emissive_texture.is_some(),
+            alpha_mode: self.alpha_mode,
+            double_sided: self.double_sided,
+        };
+    }
+}
+
+/// The properties of a material that require a dedicated render pipeline.
+/// Used as the key of the [crate::pipeline::PipelineCache].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MaterialFeatures {
+    pub base_color_texture: bool,
+    pub metallic_roughness_texture: bool,
+    pub normal_texture: bool,
+    pub occlusion_texture: bool,
+    pub emissive_texture: bool,
+    pub alpha_mode: AlphaMode,
+    pub double_sided: bool,
+}
+
+impl MaterialFeatures {
+    /// The features as a bit set, matching the FEATURE_* constants of the PBR shader.
+    pub fn shader_flags(&self) -> u32 {
+        let mut flags = 0;
+        if self.base_color_texture {
+            flags |= 1 << 0;
+        }
+        if self.metallic_roughness_texture {
+            flags |= 1 << 1;
+        }
+        if self.normal_texture {
+            flags |= 1 << 2;
+        }
+        if self.occlusion_texture {
+            flags |= 1 << 3;
+        }
+        if self.emissive_texture {
+            flags |= 1 << 4;
+        }
+        if self.alpha_mode == AlphaMode::Mask {
+            flags |= 1 << 5;
+        }
+        if self.double_sided {
+            flags |= 1 << 6;
+        }
+        return flags;
+    }
+}
+
+// We need this for Rust to store our data correctly for the shaders
+#[repr(C)]
+// This is so we can store this in a buffer
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct MaterialShaderState {
+    base_color_factor: [f32; 4],
+    // w is unused
+    emissive_factor: [f32; 4],
+    metallic_factor: f32,
+    roughness_factor: f32,
+    normal_scale: f32,
+    occlusion_strength: f32,
+    alpha_cutoff: f32,
+    // Uniform structs are padded to a multiple of 16 bytes
+    _padding: [f32; 3],
+}
+
+/// Source of [MaterialRenderState::id()]s.
+static NEXT_MATERIAL_ID: AtomicU32 = AtomicU32::new(0);
+
+/// The GPU resources of a [Material]. Shared between all mesh render nodes using the material.
+pub struct MaterialRenderState {
+    id: u32,
+    features: MaterialFeatures,
+    uniform_buffer: wgpu::Buffer,
+    textures: Vec<wgpu::Texture>,
+    bind_group: wgpu::BindGroup,
+}
+
+impl MaterialRenderState {
+    /// Number of textures bound per material, in binding order:
+    /// base color, metallic-roughness, normal, occlusion, emissive.
+    const TEXTURE_COUNT: u32 = 5;
+
+    /// Uploads [material] and the textures it references out of [textures].
+    /// Textures the material does not have, or whose index is out of range of [textures], are substituted with
+    /// [StaticRenderState::default_texture_view].
+    pub fn new(material: &Material, textures: &[TextureData], static_render_state: &StaticRenderState) -> Self {
+        let device = &static_render_state.device;
+        let shader_state = MaterialShaderState {
+            base_color_factor: material.base_color_factor,
+            emissive_factor: [material.emissive_factor[0], material.emissive_factor[1], material.emissive_factor[2], 0.0],
+            metallic_factor: material.metallic_factor,
+            roughness_factor: material.roughness_factor,
+            normal_scale: material.normal_scale,
+            occlusion_strength: material.occlusion_strength,
+            alpha_cutoff: material.alpha_cutoff,
+            _padding: [0.0; 3],
+        };
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("MaterialBuffer"),
+            contents: bytemuck::cast_slice(&[shader_state]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let texture_slots = [
+            (material.base_color_texture, wgpu::TextureFormat::Rgba8UnormSrgb),
+            (material.metallic_roughness_texture, wgpu::TextureFormat::Rgba8Unorm),
+            (material.normal_texture, wgpu::TextureFormat::Rgba8Unorm),
+            (material.occlusion_texture, wgpu::TextureFormat::Rgba8Unorm),
+            (material.emissive_texture, wgpu::TextureFormat::Rgba8UnormSrgb),
+        ];
+        let mut owned_textures = Vec::new();
+        let mut texture_views = Vec::new();
+        for (texture_index, format) in texture_slots {
+            match texture_index.and_then(|index| textures.get(index)) {
+                Some(texture_data) => {
+                    let texture = Self::upload_texture(static_render_state, texture_data, format);
+                    texture_views.push(Some(texture.create_view(&wgpu::TextureViewDescriptor::default())));
+                    owned_textures.push(texture);
+                }
+                None => texture_views.push(None),
+            }
+        }
+
+        let mut entries = vec![
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&static_render_state.default_sampler),
+            },
+        ];
+        for (slot, texture_view) in texture_views.iter().enumerate() {
+            entries.push(wgpu::BindGroupEntry {
+                binding: 2 + slot as u32,
+                resource: wgpu::BindingResource::TextureView(texture_view.as_ref().unwrap_or(&static_render_state.default_texture_view)),
+            });
+        }
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: static_render_state.material_bind_group_layout(),
+            entries: &entries,
+            label: Some("material_bind_group"),
+        });
+
+        return MaterialRenderState {
+            id: NEXT_MATERIAL_ID.fetch_add(1, Ordering::Relaxed),
+            features: material.features(),
+            uniform_buffer,
+            textures: owned_textures,
+            bind_group,
+        };
+    }
+
+    fn upload_texture(static_render_state: &StaticRenderState, texture_data: &TextureData, format: wgpu::TextureFormat) -> wgpu::Texture {
+        return static_render_state.device.create_texture_with_data(
+            &static_render_state.queue,
+            &wgpu::TextureDescriptor {
+                label: Some("MaterialTexture"),
+                size: wgpu::Extent3d {
+                    width: texture_data.width,
+                    height: texture_data.height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            },
+            &texture_data.pixels,
+        );
+    }
+
+    pub(crate) fn create_bind_group_layout(device: &Device) -> wgpu::BindGroupLayout {
+        let mut entries = vec![
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ];
+        for slot in 0..Self::TEXTURE_COUNT {
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding: 2 + slot,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            });
+        }
+        return device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &entries,
+            label: Some("material_bind_group_layout"),
+        });
+    }
+
+    /// Uniquely identifies the material render state among all material render states created by the process.
+    pub fn id(&self) -> u32 {
+        return self.id;
+    }
+
+    pub fn features(&self) -> MaterialFeatures {
+        return self.features;
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        return &self.bind_group;
+    }
+}
+
+impl Drop for MaterialRenderState {
+    fn drop(&mut self) {
+        self.uniform_buffer.destroy();
+        for texture in &self.textures {
+            texture.destroy();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_material_has_no_shader_features() {
+        assert_eq!(Material::default().features().shader_flags(), 0);
+    }
+
+    #[test]
+    fn shader_flags_match_the_features() {
+        let material = Material {
+            base_color_texture: Some(0),
+            normal_texture: Some(1),
+            emissive_texture: Some(0),
+            double_sided: true,
+            ..Material::default()
+        };
+        assert_eq!(material.features().shader_flags(), 1 << 0 | 1 << 2 | 1 << 4 | 1 << 6);
+
+        let features = MaterialFeatures {
+            base_color_texture: false,
+            metallic_roughness_texture: true,
+            normal_texture: false,
+            occlusion_texture: true,
+            emissive_texture: false,
+            alpha_mode: AlphaMode::Mask,
+            double_sided: false,
+        };
+        assert_eq!(features.shader_flags(), 1 << 1 | 1 << 3 | 1 << 5);
+    }
+
+    #[test]
+    fn only_masked_alpha_has_a_shader_flag() {
+        let flags = |alpha_mode| Material { alpha_mode, ..Material::default() }.features().shader_flags();
+        assert_eq!(flags(AlphaMode::Opaque), 0);
+        assert_eq!(flags(AlphaMode::Mask), 1 << 5);
+        // Blending is pipeline state, the shader is the same as for opaque materials
+        assert_eq!(flags(AlphaMode::Blend), 0);
+    }
+}
@@ -0,0 +1,245 @@
+use std::any::Any;
+use std::rc::Rc;
+use glam::{Mat4, Vec3A};
+use wgpu::util::DeviceExt;
+use crate::culling::Aabb;
+use crate::gltf_loader::Model;
+use crate::material::{AlphaMode, Material, MaterialRenderState};
+use crate::render_phase::RenderPhase;
+use crate::scene::{EmptyRenderNode, RenderCallState, RenderNode, RenderNodeHandle, RenderScene, StaticRenderState, MATERIAL_BIND_GROUP, MODEL_BIND_GROUP};
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MeshVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub tex_coords: [f32; 2],
+    /// xyz is the tangent, w the sign of the bitangent (bitangent = cross(normal, tangent) * w).
+    pub tangent: [f32; 4],
+}
+
+impl MeshVertex {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![
+        0 => Float32x3,
+        1 => Float32x3,
+        2 => Float32x2,
+        3 => Float32x4,
+    ];
+
+    /// The layout of the vertex buffers of [MeshRenderNode]s, to be used in the vertex state of mesh pipelines.
+    pub fn buffer_layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        return wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<MeshVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        };
+    }
+}
+
+/// CPU-side geometry of a single mesh primitive. Always an indexed triangle list.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MeshData {
+    pub vertices: Vec<MeshVertex>,
+    pub indices: Vec<u32>,
+    /// Index into the materials of the [Model] the mesh belongs to. None for the default material.
+    pub material: Option<usize>,
+}
+
+// We need this for Rust to store our data correctly for the shaders
+#[repr(C)]
+// This is so we can store this in a buffer
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ModelShaderState {
+    // Transforms vertex positions from model space into world space
+    model: [[f32; 4]; 4],
+    // Transforms vertex normals from model space into world space (inverse transpose of the model matrix)
+    normal: [[f32; 4]; 4],
+}
+
+impl ModelShaderState {
+    fn from_world_transform(world_transform: &Mat4) -> Self {
+        return ModelShaderState {
+            model: world_transform.to_cols_array_2d(),
+            normal: world_transform.inverse().transpose().to_cols_array_2d(),
+        };
+    }
+}
+
+pub struct MeshRenderNode {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    index_count: u32,
+    model_buffer: wgpu::Buffer,
+    model_bind_group: wgpu::BindGroup,
+    model_shader_state: ModelShaderState,
+    material: Rc<MaterialRenderState>,
+    /// The bounding box of the vertices in model space. None if the mesh has no vertices.
+    local_bounding_box: Option<Aabb>,
+    world_bounding_box: Option<Aabb>,
+    dirty: bool,
+}
+
+impl RenderNode for MeshRenderNode {
+    fn is_dirty(&self) -> bool {
+        return self.dirty;
+    }
+
+    #[profiling::function]
+    fn render<'a, 'b: 'a>(&'b mut self, static_render_state: &'b StaticRenderState, render_call_state: &mut RenderCallState<'_, 'b>) {
+        let pipeline = static_render_state.pipeline_cache.get(&self.material.features()).unwrap();
+        let render_pass = &mut render_call_state.render_pass;
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(MODEL_BIND_GROUP, &self.model_bind_group, &[]);
+        render_pass.set_bind_group(MATERIAL_BIND_GROUP, self.material.bind_group(), &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.draw_indexed(0..self.index_count, 0, 0..1);
+    }
+
+    fn render_phase(&self) -> RenderPhase {
+        return match self.material.features().alpha_mode {
+            AlphaMode::Opaque => RenderPhase::Opaque,
+            AlphaMode::Mask => RenderPhase::AlphaTested,
+            AlphaMode::Blend => RenderPhase::Transparent,
+        };
+    }
+
+    fn state_sort_key(&self) -> u64 {
+        return ((self.material.features().shader_flags() as u64) << 32) | self.material.id() as u64;
+    }
+
+    fn render_shadow<'a>(&'a self, render_call_state: &mut RenderCallState<'_, 'a>) {
+        // Translucent surfaces let light through
+        if self.material.features().alpha_mode == AlphaMode::Blend {
+            return;
+        }
+        let render_pass = &mut render_call_state.render_pass;
+        render_pass.set_bind_group(MODEL_BIND_GROUP, &self.model_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.draw_indexed(0..self.index_count, 0, 0..1);
+    }
+
+    fn resolve_dirty_state(&mut self, static_render_state: &mut StaticRenderState) {
+        static_render_state.queue.write_buffer(&self.model_buffer, 0, bytemuck::cast_slice(&[self.model_shader_state]));
+        self.dirty = false;
+    }
+
+    fn set_world_transform(&mut self, world_transform: &Mat4) {
+        self.model_shader_state = ModelShaderState::from_world_transform(world_transform);
+        self.world_bounding_box = self.local_bounding_box.map(|bounding_box| bounding_box.transformed(world_transform));
+        self.dirty = true;
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        return self.world_bounding_box;
+    }
+
+    fn release_resources(&mut self, _static_render_state: &mut StaticRenderState) {
+        self.vertex_buffer.destroy();
+        self.index_buffer.destroy();
+        self.model_buffer.destroy();
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl MeshRenderNode {
+    /// Uploads [mesh_data] to the GPU and adds a render node drawing it with [material] to [scene].
+    pub fn add_new(mesh_data: &MeshData, material: Rc<MaterialRenderState>, scene: &mut RenderScene) -> RenderNodeHandle {
+        scene.static_render_state.ensure_pipeline(material.features());
+        let render_context = &scene.static_render_state;
+        let vertex_buffer = render_context.device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("MeshVertexBuffer"),
+                contents: bytemuck::cast_slice(&mesh_data.vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            }
+        );
+        let index_buffer = render_context.device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("MeshIndexBuffer"),
+                contents: bytemuck::cast_slice(&mesh_data.indices),
+                usage: wgpu::BufferUsages::INDEX,
+            }
+        );
+        let model_shader_state = ModelShaderState::from_world_transform(&Mat4::IDENTITY);
+        let model_buffer = render_context.device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("ModelBuffer"),
+                contents: bytemuck::cast_slice(&[model_shader_state]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+        let model_bind_group = render_context.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: render_context.model_bind_group_layout(),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: model_buffer.as_entire_binding(),
+                }
+            ],
+            label: Some("model_bind_group"),
+        });
+        let local_bounding_box = Aabb::from_points(mesh_data.vertices.iter().map(|vertex| Vec3A::from(vertex.position)));
+        let mesh_node = MeshRenderNode {
+            vertex_buffer,
+            index_buffer,
+            index_count: mesh_data.indices.len() as u32,
+            model_buffer,
+            model_bind_group,
+            model_shader_state,
+            material,
+            local_bounding_box,
+            world_bounding_box: local_bounding_box,
+            dirty: false,
+        };
+        return scene.add_node(Box::new(mesh_node));
+    }
+
+    /// Adds the node hierarchy of [model] to [scene].
+    /// Every model node becomes a render node with the node's transform as its local transform. Each primitive of
+    /// the node's mesh becomes a [MeshRenderNode] child of it.
+    /// Returns the handle of a new root node that all root nodes of the model are attached to.
+    pub fn spawn_model(model: &Model, scene: &mut RenderScene) -> RenderNodeHandle {
+        let materials: Vec<Rc<MaterialRenderState>> = model.materials.iter()
+            .map(|material| Rc::new(MaterialRenderState::new(material, &model.textures, &scene.static_render_state)))
+            .collect();
+        let mut default_material: Option<Rc<MaterialRenderState>> = None;
+
+        let model_root = scene.add_node(Box::new(EmptyRenderNode));
+
+        let mut stack: Vec<(usize, RenderNodeHandle)> = model.root_nodes.iter()
+            .map(|node_index| (*node_index, model_root))
+            .collect();
+        while let Some((node_index, parent_handle)) = stack.pop() {
+            let model_node = &model.nodes[node_index];
+            let node_handle = scene.add_node(Box::new(EmptyRenderNode));
+            scene.set_parent(&node_handle, Some(&parent_handle), false).unwrap();
+            scene.set_local_transform(&node_handle, model_node.transform);
+
+            if let Some(mesh_index) = model_node.mesh {
+                for primitive in &model.meshes[mesh_index].primitives {
+                    let material = match primitive.material {
+                        Some(material_index) => materials[material_index].clone(),
+                        None => default_material
+                            .get_or_insert_with(|| Rc::new(MaterialRenderState::new(&Material::default(), &[], &scene.static_render_state)))
+                            .clone(),
+                    };
+                    let primitive_handle = MeshRenderNode::add_new(primitive, material, scene);
+                    scene.set_parent(&primitive_handle, Some(&node_handle), false).unwrap();
+                }
+            }
+            for child_index in &model_node.children {
+                stack.push((*child_index, node_handle));
+            }
+        }
+        return model_root;
+    }
+}
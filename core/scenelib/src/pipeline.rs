@@ -0,0 +1,93 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use wgpu::Device;
+use crate::material::{AlphaMode, MaterialFeatures};
+use crate::mesh::MeshVertex;
+
+/// The formats of the attachments the scene is rendered into. All pipelines must be compatible with them.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RenderTargetState {
+    pub color_format: wgpu::TextureFormat,
+    pub depth_format: wgpu::TextureFormat,
+    /// The depth comparison that makes a fragment pass the depth test.
+    pub depth_compare: wgpu::CompareFunction,
+    pub sample_count: u32,
+}
+
+/// Lazily builds and caches one PBR render pipeline per distinct set of [MaterialFeatures].
+pub struct PipelineCache {
+    pipelines: HashMap<MaterialFeatures, wgpu::RenderPipeline>,
+}
+
+impl PipelineCache {
+    pub(crate) fn new() -> Self {
+        return PipelineCache { pipelines: HashMap::new() };
+    }
+
+    /// Returns the pipeline for [features]. The pipeline must have been created with [Self::get_or_create()] before.
+    pub fn get(&self, features: &MaterialFeatures) -> Option<&wgpu::RenderPipeline> {
+        return self.pipelines.get(features);
+    }
+
+    /// Returns the pipeline for [features], building it from [bind_group_layouts] if it does not exist yet.
+    pub fn get_or_create(&mut self, device: &Device, bind_group_layouts: &[wgpu::BindGroupLayout], render_target: &RenderTargetState, features: MaterialFeatures) -> &wgpu::RenderPipeline {
+        return self.pipelines.entry(features)
+            .or_insert_with(|| Self::create_pipeline(device, bind_group_layouts, render_target, &features));
+    }
+
+    #[profiling::function]
+    fn create_pipeline(device: &Device, bind_group_layouts: &[wgpu::BindGroupLayout], render_target: &RenderTargetState, features: &MaterialFeatures) -> wgpu::RenderPipeline {
+        // WGSL has no preprocessor. The variant is selected by a constant that the shader branches on.
+        let shader_source = format!("let MATERIAL_FEATURES: u32 = {}u;\n{}", features.shader_flags(), include_str!("../../cres/shaders/pbr.wgsl"));
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("PbrShader"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Owned(shader_source)),
+        });
+
+        let layouts: Vec<&wgpu::BindGroupLayout> = bind_group_layouts.iter().collect();
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("PbrPipelineLayout"),
+            bind_group_layouts: &layouts,
+            push_constant_ranges: &[],
+        });
+
+        let is_blended = features.alpha_mode == AlphaMode::Blend;
+        return device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("PbrPipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[MeshVertex::buffer_layout()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: render_target.color_format,
+                    blend: if is_blended { Some(wgpu::BlendState::ALPHA_BLENDING) } else { None },
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: if features.double_sided { None } else { Some(wgpu::Face::Back) },
+                ..wgpu::PrimitiveState::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: render_target.depth_format,
+                // Blended surfaces must not occlude what is rendered behind them afterwards
+                depth_write_enabled: !is_blended,
+                depth_compare: render_target.depth_compare,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: render_target.sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        });
+    }
+}
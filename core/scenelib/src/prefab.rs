@@ -0,0 +1,532 @@
+use std::collections::HashMap;
+use std::fs;
+use std::mem;
+use std::path::{Path, PathBuf};
+use glam::Vec3A;
+use serde::{Deserialize, Serialize};
+use specs::{Component, HashMapStorage};
+use crate::camera::Camera;
+use crate::ecs::{CameraComponent, ECSEntityHandle, ECSWorld, FlyingCameraComponent, LightComponent, VelocityComponent};
+use crate::light::Light;
+use crate::scene_file::{resolve_model_paths, spawn_entities, SceneEntity, SceneEntityKind, SceneEntitySpawner, SceneFileError, SceneLight, SceneTransform};
+use crate::transform::Transform;
+
+/// The version of the prefab file format written by [Prefab::save()].
+pub const PREFAB_FILE_VERSION: u32 = 1;
+
+/// A reusable template of entities, read from a RON file and instantiated any number of times.
+/// The first entity is the root of every instance, all other entities must be attached to an entity before them.
+/// Relative model paths are relative to the prefab file.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Prefab {
+    pub version: u32,
+    pub entities: Vec<SceneEntity>,
+}
+
+/// A single component field of an instance that differs from its prefab.
+/// Overrides that do not apply to the kind of the entity are ignored. Angles are in degrees.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum FieldOverride {
+    Translation([f32; 3]),
+    /// Quaternion in x, y, z, w order.
+    Rotation([f32; 4]),
+    Scale([f32; 3]),
+    Velocity(Option<[f32; 3]>),
+    LightColor([f32; 3]),
+    LightIntensity(f32),
+    /// Point and spot lights only.
+    LightRange(f32),
+    /// Spot lights only.
+    LightConeAngles { inner: f32, outer: f32 },
+    LightCastsShadows(bool),
+    CameraFov(f32),
+    CameraNear(f32),
+    CameraFar(Option<f32>),
+    /// Orthographic cameras only.
+    CameraHeight(f32),
+    CameraFlying(bool),
+}
+
+/// Overrides a field of the entity at index [entity] of the prefab.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PrefabOverride {
+    #[serde(default)]
+    pub entity: usize,
+    pub field: FieldOverride,
+}
+
+impl PrefabOverride {
+    /// Overrides a field of the root entity.
+    pub fn root(field: FieldOverride) -> Self {
+        return PrefabOverride { entity: 0, field };
+    }
+
+    /// Whether both override the same field of the same entity.
+    fn overrides_same_field(&self, other: &PrefabOverride) -> bool {
+        return self.entity == other.entity && mem::discriminant(&self.field) == mem::discriminant(&other.field);
+    }
+}
+
+impl FieldOverride {
+    pub fn apply(&self, entity: &mut SceneEntity) {
+        match (self, &mut entity.kind) {
+            (FieldOverride::Translation(translation), _) => entity.transform.translation = *translation,
+            (FieldOverride::Rotation(rotation), _) => entity.transform.rotation = *rotation,
+            (FieldOverride::Scale(scale), _) => entity.transform.scale = *scale,
+            (FieldOverride::Velocity(velocity), _) => entity.velocity = *velocity,
+            (FieldOverride::LightColor(new_color), SceneEntityKind::Light(light)) => match light {
+                SceneLight::Directional { color, .. } | SceneLight::Point { color, .. } | SceneLight::Spot { color, .. } => *color = *new_color,
+            },
+            (FieldOverride::LightIntensity(new_intensity), SceneEntityKind::Light(light)) => match light {
+                SceneLight::Directional { intensity, .. } | SceneLight::Point { intensity, .. } | SceneLight::Spot { intensity, .. } => *intensity = *new_intensity,
+            },
+            (FieldOverride::LightRange(new_range), SceneEntityKind::Light(light)) => match light {
+                SceneLight::Point { range, .. } | SceneLight::Spot { range, .. } => *range = *new_range,
+                SceneLight::Directional { .. } => {}
+            },
+            (FieldOverride::LightConeAngles { inner, outer }, SceneEntityKind::Light(SceneLight::Spot { inner_cone_angle, outer_cone_angle, .. })) => {
+                *inner_cone_angle = *inner;
+                *outer_cone_angle = *outer;
+            }
+            (FieldOverride::LightCastsShadows(new_casts_shadows), SceneEntityKind::Light(light)) => match light {
+                SceneLight::Directional { casts_shadows, .. } | SceneLight::Spot { casts_shadows, .. } => *casts_shadows = *new_casts_shadows,
+                SceneLight::Point { .. } => {}
+            },
+            (FieldOverride::CameraFov(new_fov), SceneEntityKind::PerspectiveCamera { fov, .. }) => *fov = *new_fov,
+            (FieldOverride::CameraNear(new_near), SceneEntityKind::PerspectiveCamera { near, .. }) => *near = *new_near,
+            (FieldOverride::CameraNear(new_near), SceneEntityKind::OrthographicCamera { near, .. }) => *near = *new_near,
+            (FieldOverride::CameraFar(new_far), SceneEntityKind::PerspectiveCamera { far, .. }) => *far = *new_far,
+            (FieldOverride::CameraFar(Some(new_far)), SceneEntityKind::OrthographicCamera { far, .. }) => *far = *new_far,
+            (FieldOverride::CameraHeight(new_height), SceneEntityKind::OrthographicCamera { height, .. }) => *height = *new_height,
+            (FieldOverride::CameraFlying(new_flying), SceneEntityKind::PerspectiveCamera { flying, .. }) => *flying = *new_flying,
+            _ => {}
+        }
+    }
+}
+
+impl Prefab {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SceneFileError> {
+        return Prefab::from_ron(&fs::read_to_string(path)?);
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SceneFileError> {
+        fs::write(path, self.to_ron()?)?;
+        return Ok(());
+    }
+
+    pub fn from_ron(ron: &str) -> Result<Self, SceneFileError> {
+        let prefab: Prefab = ron::from_str(ron).map_err(SceneFileError::Parse)?;
+        if prefab.version > PREFAB_FILE_VERSION {
+            return Err(SceneFileError::UnsupportedVersion(prefab.version));
+        }
+        if prefab.entities.is_empty() {
+            return Err(SceneFileError::EmptyPrefab);
+        }
+        for (entity_index, entity) in prefab.entities.iter().enumerate() {
+            let has_valid_parent = match entity.parent {
+                Some(parent) => parent < entity_index,
+                None => entity_index == 0,
+            };
+            if !has_valid_parent {
+                return Err(SceneFileError::InvalidParent { entity: entity_index, parent: entity.parent.unwrap_or(entity_index) });
+            }
+        }
+        return Ok(Prefab { version: PREFAB_FILE_VERSION, ..prefab });
+    }
+
+    pub fn to_ron(&self) -> Result<String, SceneFileError> {
+        return ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()).map_err(SceneFileError::Serialize);
+    }
+
+    /// The entities of an instance placed by [transform], with [overrides] applied.
+    /// The root entity's transform is the placement, transform overrides of the root entity are ignored.
+    pub fn instance_entities(&self, transform: SceneTransform, overrides: &[PrefabOverride]) -> Vec<SceneEntity> {
+        let mut entities = self.entities.clone();
+        for prefab_override in overrides {
+            if let Some(entity) = entities.get_mut(prefab_override.entity) {
+                prefab_override.field.apply(entity);
+            }
+        }
+        entities[0].transform = transform;
+        return entities;
+    }
+}
+
+/// Links the root entity of a prefab instance to its prefab.
+#[derive(Component, Clone, Debug, PartialEq)]
+#[storage(HashMapStorage)]
+pub struct PrefabInstanceComponent {
+    /// The path the prefab was loaded from, see [PrefabLibrary].
+    pub prefab: PathBuf,
+    pub overrides: Vec<PrefabOverride>,
+    /// The entities of the instance, in the order of the prefab's entities. The first one is the root.
+    pub members: Vec<ECSEntityHandle>,
+    /// The entities as they were spawned, with model paths resolved.
+    spawned: Vec<SceneEntity>,
+}
+
+impl PrefabInstanceComponent {
+    pub(crate) fn new(prefab: PathBuf, overrides: Vec<PrefabOverride>, members: Vec<ECSEntityHandle>, spawned: Vec<SceneEntity>) -> Self {
+        return PrefabInstanceComponent { prefab, overrides, members, spawned };
+    }
+}
+
+/// Loads prefabs once and keeps their instances in sync with them.
+/// Prefabs are identified by the path they were loaded from.
+pub struct PrefabLibrary {
+    prefabs: HashMap<PathBuf, Prefab>,
+}
+
+impl PrefabLibrary {
+    pub fn new() -> Self {
+        return PrefabLibrary { prefabs: HashMap::new() };
+    }
+
+    /// The prefab at [path], loaded from the file the first time it is requested.
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> Result<&Prefab, SceneFileError> {
+        let path = path.as_ref();
+        if !self.prefabs.contains_key(path) {
+            let prefab = Prefab::load(path)?;
+            self.prefabs.insert(path.to_path_buf(), prefab);
+        }
+        return Ok(&self.prefabs[path]);
+    }
+
+    /// Adds an instance of the prefab at [path], placed by [transform], and returns its root entity.
+    /// Cameras are created with the given [aspect] ratio.
+    pub fn instantiate<P: AsRef<Path>, S: SceneEntitySpawner>(&mut self, ecs_world: &mut ECSWorld, render_scene: &mut S,
+                                                               path: P,
+                                                               transform: Transform,
+                                                               overrides: Vec<PrefabOverride>,
+                                                               aspect: f32) -> Result<ECSEntityHandle, SceneFileError> {
+        let path = path.as_ref();
+        let mut entities = self.load(path)?.instance_entities(SceneTransform::from(transform), &overrides);
+        resolve_model_paths(&mut entities, prefab_dir(path));
+        let members = spawn_entities(&entities, ecs_world, render_scene, aspect)?;
+        let root = members[0];
+        ecs_world.insert_component(&root, PrefabInstanceComponent::new(path.to_path_buf(), overrides, members, entities));
+        return Ok(root);
+    }
+
+    /// Replaces the prefab at [path] and updates all of its instances, see [Self::update_instance()].
+    pub fn set_prefab<P: AsRef<Path>, S: SceneEntitySpawner>(&mut self, ecs_world: &mut ECSWorld, render_scene: &mut S, path: P, prefab: Prefab) -> Result<(), SceneFileError> {
+        let path = path.as_ref();
+        self.prefabs.insert(path.to_path_buf(), prefab);
+        for root in ecs_world.entities_with::<(PrefabInstanceComponent,)>() {
+            let instance_path = ecs_world.with_component(&root, |instance: &PrefabInstanceComponent| instance.prefab.clone()).unwrap();
+            if instance_path == path {
+                self.update_instance(ecs_world, render_scene, &root)?;
+            }
+        }
+        return Ok(());
+    }
+
+    /// Reads the prefab at [path] from its file again and updates all of its instances.
+    pub fn reload<P: AsRef<Path>, S: SceneEntitySpawner>(&mut self, ecs_world: &mut ECSWorld, render_scene: &mut S, path: P) -> Result<(), SceneFileError> {
+        let prefab = Prefab::load(path.as_ref())?;
+        return self.set_prefab(ecs_world, render_scene, path, prefab);
+    }
+
+    /// Overrides a field of the instance with root entity [root], replacing an earlier override of the same field.
+    /// Returns false if [root] is not the root of a prefab instance.
+    pub fn set_override<S: SceneEntitySpawner>(&mut self, ecs_world: &mut ECSWorld, render_scene: &mut S, root: &ECSEntityHandle, prefab_override: PrefabOverride) -> Result<bool, SceneFileError> {
+        let is_instance = ecs_world.with_component_mut(root, |instance: &mut PrefabInstanceComponent| {
+            instance.overrides.retain(|existing| !existing.overrides_same_field(&prefab_override));
+            instance.overrides.push(prefab_override);
+        }).is_some();
+        if !is_instance {
+            return Ok(false);
+        }
+        self.update_instance(ecs_world, render_scene, root)?;
+        return Ok(true);
+    }
+
+    /// Removes the override of [field]'s field of entity [entity], so the field follows the prefab again.
+    /// Returns false if [root] is not the root of a prefab instance.
+    pub fn clear_override<S: SceneEntitySpawner>(&mut self, ecs_world: &mut ECSWorld, render_scene: &mut S, root: &ECSEntityHandle, entity: usize, field: &FieldOverride) -> Result<bool, SceneFileError> {
+        let cleared = PrefabOverride { entity, field: field.clone() };
+        let is_instance = ecs_world.with_component_mut(root, |instance: &mut PrefabInstanceComponent| {
+            instance.overrides.retain(|existing| !existing.overrides_same_field(&cleared));
+        }).is_some();
+        if !is_instance {
+            return Ok(false);
+        }
+        self.update_instance(ecs_world, render_scene, root)?;
+        return Ok(true);
+    }
+
+    /// Brings the instance with root entity [root] up to date with its prefab and overrides.
+    /// The root entity keeps its current transform. Fields are updated in place if the prefab still has the same
+    /// entities of the same kinds and models, otherwise the members of the instance are despawned and spawned
+    /// again, which gives all of them new handles. Entities attached to the instance from outside stay attached.
+    /// Returns the handle of the root entity, which differs from [root] if the instance was spawned again.
+    pub fn update_instance<S: SceneEntitySpawner>(&mut self, ecs_world: &mut ECSWorld, render_scene: &mut S, root: &ECSEntityHandle) -> Result<ECSEntityHandle, SceneFileError> {
+        let instance = match ecs_world.get_component::<PrefabInstanceComponent>(root) {
+            Some(instance) => instance,
+            None => return Ok(*root),
+        };
+        let root_transform = ecs_world.get_transform(root).unwrap_or(Transform::IDENTITY);
+        let mut entities = self.load(&instance.prefab)?.instance_entities(SceneTransform::from(root_transform), &instance.overrides);
+        resolve_model_paths(&mut entities, prefab_dir(&instance.prefab));
+
+        if has_same_structure(&instance.spawned, &entities) {
+            for (member, entity) in instance.members.iter().zip(&entities) {
+                update_member(ecs_world, render_scene, member, entity);
+            }
+            ecs_world.with_component_mut(root, |instance: &mut PrefabInstanceComponent| instance.spawned = entities);
+            return Ok(*root);
+        }
+
+        // Cameras keep their aspect ratio
+        let aspect = instance.members.iter()
+            .find_map(|member| render_scene.camera(ecs_world, member).map(|camera| camera.aspect()))
+            .unwrap_or(1.0);
+        let parent = ecs_world.get_parent(root);
+        let members = spawn_entities(&entities, ecs_world, render_scene, aspect)?;
+        // Entities attached to the instance that are not part of it would be despawned with their parent.
+        // They are moved to the new member at the same index, or to the new root if there is none.
+        for (index, old_member) in instance.members.iter().enumerate() {
+            let new_member = members.get(index).unwrap_or(&members[0]);
+            for child in ecs_world.get_children(old_member) {
+                if !instance.members.contains(&child) {
+                    render_scene.set_parent(ecs_world, &child, new_member);
+                }
+            }
+        }
+        for member in instance.members.iter().rev() {
+            render_scene.remove_entity(ecs_world, member);
+        }
+        let new_root = members[0];
+        if let Some(parent) = parent {
+            render_scene.set_parent(ecs_world, &new_root, &parent);
+        }
+        ecs_world.insert_component(&new_root, PrefabInstanceComponent::new(instance.prefab, instance.overrides, members, entities));
+        return Ok(new_root);
+    }
+}
+
+/// The directory that relative model paths of the prefab at [path] are resolved against.
+pub(crate) fn prefab_dir(path: &Path) -> &Path {
+    return path.parent().unwrap_or_else(|| Path::new(""));
+}
+
+/// Whether [new] can be applied to the entities spawned from [old] without spawning them again.
+fn has_same_structure(old: &[SceneEntity], new: &[SceneEntity]) -> bool {
+    return old.len() == new.len() && old.iter().zip(new).all(|(old_entity, new_entity)| {
+        let same_kind = match (&old_entity.kind, &new_entity.kind) {
+            (SceneEntityKind::Model { path: old_path }, SceneEntityKind::Model { path: new_path }) => old_path == new_path,
+            (old_kind, new_kind) => mem::discriminant(old_kind) == mem::discriminant(new_kind),
+        };
+        return same_kind && old_entity.parent == new_entity.parent;
+    });
+}
+
+/// Updates the components of [member] to [entity], which is of the same kind as the entity [member] was spawned
+/// from. The root entity's transform is kept by [PrefabLibrary::update_instance()] through [entity].
+fn update_member<S: SceneEntitySpawner>(ecs_world: &mut ECSWorld, render_scene: &mut S, member: &ECSEntityHandle, entity: &SceneEntity) {
+    let transform = Transform::from(entity.transform);
+    ecs_world.set_transform(member, transform);
+    match entity.velocity {
+        Some(velocity) => {
+            ecs_world.insert_component(member, VelocityComponent { velocity: Vec3A::from(velocity) });
+        }
+        None => {
+            ecs_world.with_component_mut(member, |velocity: &mut VelocityComponent| velocity.velocity = Vec3A::ZERO);
+        }
+    }
+
+    match &entity.kind {
+        SceneEntityKind::PerspectiveCamera { fov, near, far, flying, .. } => {
+            ecs_world.with_component_mut(member, |camera: &mut CameraComponent| camera.fov = Some(fov.to_radians()));
+            if *flying {
+                if !ecs_world.has_component::<FlyingCameraComponent>(member) {
+                    ecs_world.insert_component(member, FlyingCameraComponent::from_rotation(transform.rotation));
+                    ecs_world.insert_component(member, VelocityComponent { velocity: Vec3A::ZERO });
+                }
+            } else {
+                ecs_world.remove_component::<FlyingCameraComponent>(member);
+            }
+            if let Some(camera) = render_scene.camera_mut(ecs_world, member) {
+                camera.set_near(*near);
+                if let Some(far) = far {
+                    camera.set_far(*far);
+                }
+            }
+        }
+        SceneEntityKind::OrthographicCamera { height, near, far, .. } => {
+            if let Some(camera) = render_scene.camera_mut(ecs_world, member) {
+                if let Camera::Orthographic(orthographic_camera) = &mut *camera {
+                    orthographic_camera.set_height(*height);
+                }
+                camera.set_near(*near);
+                camera.set_far(*far);
+            }
+        }
+        SceneEntityKind::Light(light) => {
+            ecs_world.insert_component(member, LightComponent { light: Light::from(*light) });
+        }
+        SceneEntityKind::Model { .. } => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ecs::tests::add_bare_entity;
+    use crate::scene_file::tests::BareSpawner;
+    use super::*;
+
+    fn lamp_prefab() -> Prefab {
+        return Prefab::from_ron(r#"(
+            version: 1,
+            entities: [
+                (kind: Model(path: "lamp.gltf")),
+                (parent: Some(0), transform: (translation: (0.0, 2.0, 0.0), rotation: (0.0, 0.0, 0.0, 1.0), scale: (1.0, 1.0, 1.0)),
+                 kind: Light(Point(color: (1.0, 0.9, 0.8), intensity: 100.0, range: 10.0))),
+            ],
+        )"#).unwrap();
+    }
+
+    /// A point light with a directional light attached, neither needs a model file or a GPU.
+    fn rig_prefab() -> Prefab {
+        return Prefab::from_ron(r#"(
+            version: 1,
+            entities: [
+                (kind: Light(Point(color: (1.0, 1.0, 1.0), intensity: 10.0, range: 5.0))),
+                (parent: Some(0), kind: Light(Directional(color: (1.0, 0.9, 0.8), intensity: 100.0, casts_shadows: false))),
+            ],
+        )"#).unwrap();
+    }
+
+    fn members_of(ecs_world: &ECSWorld, root: &ECSEntityHandle) -> Vec<ECSEntityHandle> {
+        return ecs_world.with_component(root, |instance: &PrefabInstanceComponent| instance.members.clone()).unwrap();
+    }
+
+    fn light_of(ecs_world: &ECSWorld, entity_handle: &ECSEntityHandle) -> Light {
+        return ecs_world.get_component::<LightComponent>(entity_handle).unwrap().light;
+    }
+
+    #[test]
+    fn prefab_edits_reach_non_overridden_fields_of_instances() {
+        let mut ecs_world = ECSWorld::new();
+        let mut spawner = BareSpawner::default();
+        let mut library = PrefabLibrary::new();
+        library.set_prefab(&mut ecs_world, &mut spawner, "rig.ron", rig_prefab()).unwrap();
+        let plain = library.instantiate(&mut ecs_world, &mut spawner, "rig.ron", Transform::IDENTITY, Vec::new(), 1.0).unwrap();
+        let overrides = vec![PrefabOverride { entity: 1, field: FieldOverride::LightIntensity(50.0) }];
+        let overridden = library.instantiate(&mut ecs_world, &mut spawner, "rig.ron", Transform::from_translation(Vec3A::X), overrides, 1.0).unwrap();
+        let (plain_members, overridden_members) = (members_of(&ecs_world, &plain), members_of(&ecs_world, &overridden));
+
+        let mut edited = rig_prefab();
+        edited.entities[1].kind = SceneEntityKind::Light(SceneLight::Directional { color: [0.0, 0.0, 1.0], intensity: 200.0, casts_shadows: true });
+        library.set_prefab(&mut ecs_world, &mut spawner, "rig.ron", edited).unwrap();
+
+        // Updated in place, the members keep their handles
+        assert_eq!(members_of(&ecs_world, &plain), plain_members);
+        assert_eq!(members_of(&ecs_world, &overridden), overridden_members);
+        let plain_light = light_of(&ecs_world, &plain_members[1]);
+        assert_eq!((plain_light.color, plain_light.intensity, plain_light.casts_shadows), (Vec3A::Z, 200.0, true));
+        let overridden_light = light_of(&ecs_world, &overridden_members[1]);
+        assert_eq!((overridden_light.color, overridden_light.intensity, overridden_light.casts_shadows), (Vec3A::Z, 50.0, true));
+        // The placement of an instance is not part of the prefab
+        assert_eq!(ecs_world.get_transform(&overridden), Some(Transform::from_translation(Vec3A::X)));
+    }
+
+    #[test]
+    fn overrides_are_kept_until_cleared() {
+        let mut ecs_world = ECSWorld::new();
+        let mut spawner = BareSpawner::default();
+        let mut library = PrefabLibrary::new();
+        library.set_prefab(&mut ecs_world, &mut spawner, "rig.ron", rig_prefab()).unwrap();
+        let root = library.instantiate(&mut ecs_world, &mut spawner, "rig.ron", Transform::IDENTITY, Vec::new(), 1.0).unwrap();
+        let light = members_of(&ecs_world, &root)[1];
+
+        let intensity_override = PrefabOverride { entity: 1, field: FieldOverride::LightIntensity(50.0) };
+        assert!(library.set_override(&mut ecs_world, &mut spawner, &root, intensity_override.clone()).unwrap());
+        assert_eq!(light_of(&ecs_world, &light).intensity, 50.0);
+
+        let mut edited = rig_prefab();
+        edited.entities[1].kind = SceneEntityKind::Light(SceneLight::Directional { color: [1.0, 0.9, 0.8], intensity: 200.0, casts_shadows: false });
+        library.set_prefab(&mut ecs_world, &mut spawner, "rig.ron", edited).unwrap();
+        assert_eq!(light_of(&ecs_world, &light).intensity, 50.0);
+
+        assert!(library.clear_override(&mut ecs_world, &mut spawner, &root, 1, &FieldOverride::LightIntensity(0.0)).unwrap());
+        assert_eq!(light_of(&ecs_world, &light).intensity, 200.0);
+        // Only the root of an instance takes overrides
+        assert!(!library.set_override(&mut ecs_world, &mut spawner, &light, intensity_override).unwrap());
+    }
+
+    #[test]
+    fn structural_changes_respawn_instances_and_keep_their_parent() {
+        let mut ecs_world = ECSWorld::new();
+        let mut spawner = BareSpawner::default();
+        let mut library = PrefabLibrary::new();
+        library.set_prefab(&mut ecs_world, &mut spawner, "rig.ron", rig_prefab()).unwrap();
+        let vehicle = add_bare_entity(&mut ecs_world);
+        let root = library.instantiate(&mut ecs_world, &mut spawner, "rig.ron", Transform::IDENTITY, Vec::new(), 1.0).unwrap();
+        spawner.set_parent(&mut ecs_world, &root, &vehicle);
+        let old_members = members_of(&ecs_world, &root);
+        // Attached to the instance, but not part of it
+        let attached = add_bare_entity(&mut ecs_world);
+        spawner.set_parent(&mut ecs_world, &attached, &old_members[1]);
+
+        let mut edited = rig_prefab();
+        edited.entities.push(SceneEntity {
+            parent: Some(0),
+            transform: SceneTransform::default(),
+            velocity: None,
+            kind: SceneEntityKind::Light(SceneLight::Point { color: [1.0, 0.0, 0.0], intensity: 5.0, range: 2.0 }),
+        });
+        library.set_prefab(&mut ecs_world, &mut spawner, "rig.ron", edited).unwrap();
+
+        let roots = ecs_world.entities_with::<(PrefabInstanceComponent,)>();
+        assert_eq!(roots.len(), 1);
+        let new_root = roots[0];
+        let new_members = members_of(&ecs_world, &new_root);
+        assert_eq!(new_members.len(), 3);
+        assert!(old_members.iter().all(|member| !ecs_world.is_alive(member)));
+        assert_eq!(ecs_world.get_parent(&new_root), Some(vehicle));
+        assert_eq!(ecs_world.get_parent(&new_members[2]), Some(new_root));
+        assert!(ecs_world.is_alive(&attached));
+        assert_eq!(ecs_world.get_parent(&attached), Some(new_members[1]));
+    }
+
+    #[test]
+    fn instances_apply_overrides() {
+        let prefab = lamp_prefab();
+        let placement = SceneTransform::from(Transform::from_translation(Vec3A::X));
+        let overrides = vec![PrefabOverride { entity: 1, field: FieldOverride::LightIntensity(50.0) }];
+        let entities = prefab.instance_entities(placement, &overrides);
+
+        assert_eq!(entities[0].transform, placement);
+        assert_eq!(entities[1].kind, SceneEntityKind::Light(SceneLight::Point { color: [1.0, 0.9, 0.8], intensity: 50.0, range: 10.0 }));
+    }
+
+    #[test]
+    fn prefab_changes_reach_non_overridden_fields() {
+        let mut prefab = lamp_prefab();
+        let overrides = vec![PrefabOverride { entity: 1, field: FieldOverride::LightIntensity(50.0) }];
+        prefab.entities[1].kind = SceneEntityKind::Light(SceneLight::Point { color: [0.0, 0.0, 1.0], intensity: 200.0, range: 20.0 });
+        let entities = prefab.instance_entities(SceneTransform::default(), &overrides);
+
+        assert_eq!(entities[1].kind, SceneEntityKind::Light(SceneLight::Point { color: [0.0, 0.0, 1.0], intensity: 50.0, range: 20.0 }));
+    }
+
+    #[test]
+    fn overrides_of_other_kinds_are_ignored() {
+        let prefab = lamp_prefab();
+        let overrides = vec![PrefabOverride::root(FieldOverride::LightIntensity(50.0)), PrefabOverride { entity: 5, field: FieldOverride::Scale([2.0; 3]) }];
+        assert_eq!(prefab.instance_entities(SceneTransform::default(), &overrides), prefab.instance_entities(SceneTransform::default(), &[]));
+    }
+
+    #[test]
+    fn rejects_entities_without_parent() {
+        let result = Prefab::from_ron("(version: 1, entities: [(kind: Model(path: \"a.gltf\")), (kind: Model(path: \"b.gltf\"))])");
+        assert!(matches!(result, Err(SceneFileError::InvalidParent { entity: 1, .. })));
+    }
+
+    #[test]
+    fn round_trips_through_ron() {
+        let prefab = lamp_prefab();
+        assert_eq!(Prefab::from_ron(&prefab.to_ron().unwrap()).unwrap(), prefab);
+    }
+}
@@ -0,0 +1,125 @@
+use crate::scene::RenderNodeHandle;
+
+/// The phases a frame is rendered in, in render order.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RenderPhase {
+    /// Binds state that all later phases draw with, eg. the active camera. Draws nothing.
+    Setup,
+    /// Fully opaque geometry, sorted by render state and then front-to-back to reduce overdraw.
+    Opaque,
+    /// Opaque geometry with discarded fragments. Rendered after opaque geometry, as discarding fragments
+    /// defeats early depth testing.
+    AlphaTested,
+    /// Blended geometry, sorted back-to-front so that blending composes correctly.
+    Transparent,
+    /// Drawn on top of everything else, in scene order.
+    Overlay,
+}
+
+/// The key the render nodes of a frame are sorted by. Ordered by phase first. Within a phase, nodes are ordered
+/// by render state and view depth as appropriate for the phase. Ties are broken by the node's handle, so that the
+/// order is the same every frame.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DrawSortKey {
+    phase: RenderPhase,
+    state: u64,
+    depth: u32,
+    handle: RenderNodeHandle,
+}
+
+impl DrawSortKey {
+    /// [state] identifies the pipeline and material the node binds, see [crate::scene::RenderNode::state_sort_key()].
+    /// [view_depth] is the distance of the node from the camera along the camera's view direction.
+    pub fn new(phase: RenderPhase, state: u64, view_depth: f32, handle: RenderNodeHandle) -> Self {
+        // The bit pattern of non-negative floats orders the same as the floats themselves
+        let depth = view_depth.max(0.0).to_bits();
+        return match phase {
+            RenderPhase::Opaque | RenderPhase::AlphaTested => DrawSortKey { phase, state, depth, handle },
+            // Back-to-front, the render state must not take precedence over the depth
+            RenderPhase::Transparent => DrawSortKey { phase, state: 0, depth: u32::MAX - depth, handle },
+            RenderPhase::Setup | RenderPhase::Overlay => DrawSortKey { phase, state: 0, depth: 0, handle },
+        };
+    }
+
+    pub fn phase(&self) -> RenderPhase {
+        return self.phase;
+    }
+
+    pub fn handle(&self) -> RenderNodeHandle {
+        return self.handle;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handle::GenerationalHandle;
+    use super::*;
+
+    fn handle(index: u64) -> RenderNodeHandle {
+        return GenerationalHandle::from_bits(index);
+    }
+
+    fn sorted(mut keys: Vec<DrawSortKey>) -> Vec<RenderNodeHandle> {
+        keys.sort();
+        return keys.iter().map(|key| key.handle()).collect();
+    }
+
+    #[test]
+    fn orders_by_phase_first() {
+        let keys = vec![
+            DrawSortKey::new(RenderPhase::Overlay, 0, 0.0, handle(0)),
+            DrawSortKey::new(RenderPhase::Transparent, 0, 1.0, handle(1)),
+            DrawSortKey::new(RenderPhase::AlphaTested, 0, 1.0, handle(2)),
+            DrawSortKey::new(RenderPhase::Opaque, u64::MAX, 100.0, handle(3)),
+            DrawSortKey::new(RenderPhase::Setup, 0, 0.0, handle(4)),
+        ];
+        assert_eq!(sorted(keys), vec![handle(4), handle(3), handle(2), handle(1), handle(0)]);
+    }
+
+    #[test]
+    fn orders_overlay_in_scene_order() {
+        let keys = vec![
+            DrawSortKey::new(RenderPhase::Overlay, 2, 1.0, handle(1)),
+            DrawSortKey::new(RenderPhase::Overlay, 1, 10.0, handle(0)),
+            DrawSortKey::new(RenderPhase::Overlay, 3, 5.0, handle(2)),
+        ];
+        assert_eq!(sorted(keys), vec![handle(0), handle(1), handle(2)]);
+    }
+
+    #[test]
+    fn orders_opaque_by_state_then_front_to_back() {
+        let keys = vec![
+            DrawSortKey::new(RenderPhase::Opaque, 2, 1.0, handle(0)),
+            DrawSortKey::new(RenderPhase::Opaque, 1, 10.0, handle(1)),
+            DrawSortKey::new(RenderPhase::Opaque, 1, 5.0, handle(2)),
+            // Behind the camera counts as at the camera
+            DrawSortKey::new(RenderPhase::Opaque, 1, -3.0, handle(3)),
+        ];
+        assert_eq!(sorted(keys), vec![handle(3), handle(2), handle(1), handle(0)]);
+    }
+
+    #[test]
+    fn orders_transparent_back_to_front_regardless_of_state() {
+        let keys = vec![
+            DrawSortKey::new(RenderPhase::Transparent, 1, 1.0, handle(0)),
+            DrawSortKey::new(RenderPhase::Transparent, 2, 10.0, handle(1)),
+            DrawSortKey::new(RenderPhase::Transparent, 1, 5.0, handle(2)),
+        ];
+        assert_eq!(sorted(keys), vec![handle(1), handle(2), handle(0)]);
+    }
+
+    #[test]
+    fn breaks_ties_by_handle() {
+        let keys = vec![
+            DrawSortKey::new(RenderPhase::Opaque, 1, 5.0, handle(2)),
+            DrawSortKey::new(RenderPhase::Opaque, 1, 5.0, handle(0)),
+            DrawSortKey::new(RenderPhase::Opaque, 1, 5.0, handle(1)),
+        ];
+        let order = sorted(keys.clone());
+        assert_eq!(order, vec![handle(0), handle(1), handle(2)]);
+        // The same regardless of the order the nodes are visited in
+        let mut reversed = keys;
+        reversed.reverse();
+        assert_eq!(sorted(reversed), order);
+    }
+}
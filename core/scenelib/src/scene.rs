@@ -1,10 +1,20 @@
 use std::any::{Any, TypeId};
 use std::collections::HashMap;
-use std::rc::Rc;
+use std::sync::Arc;
 
-use wgpu::{Device, Queue, RenderPass};
+use glam::{Mat4, Vec3A};
+use wgpu::{CommandEncoder, Device, Queue, RenderPass};
+use wgpu::util::DeviceExt;
 use crate::camera::CameraRenderNode;
-use crate::ecs::CameraEntity;
+use crate::culling::{cull_nodes, Aabb, CullingStats, Frustum};
+use crate::handle::{GenerationalHandle, HandleAllocator};
+use crate::light::{cull_lights, LightRenderNode, LightRenderState};
+use crate::material::{MaterialFeatures, MaterialRenderState};
+use crate::pipeline::{PipelineCache, RenderTargetState};
+use crate::render_phase::{DrawSortKey, RenderPhase};
+use crate::scene_graph::{SceneGraph, SceneGraphError};
+use crate::shadow::{allocate_shadow_maps, ShadowMapAllocation, ShadowRenderState};
+use crate::transform::Transform;
 
 pub trait RenderNode {
     /// Returns whether this node is currently "dirty" and needs to be updated.
@@ -18,17 +28,51 @@ pub trait RenderNode {
     fn is_dirty(&self) -> bool;
 
     /// Called to render the node. Performs the un-avoidable operations to render the node to the screen.
-    /// [static_render_state] contains static render state
+    /// [static_render_state] contains static render state. Resources (eg. pipelines) must be created in
+    /// [Self::resolve_dirty_state()], as the static render state cannot be mutated while rendering.
     /// [render_call_state] contains render state specific to this render call/frame
-    fn render<'a, 'b: 'a>(&'b mut self, static_render_state: &mut StaticRenderState, render_call_state: &mut RenderCallState<'_, 'b>);
+    fn render<'a, 'b: 'a>(&'b mut self, static_render_state: &'b StaticRenderState, render_call_state: &mut RenderCallState<'_, 'b>);
+
+    /// Called to render the node into a shadow map. The depth-only pipeline and the light space camera are
+    /// already bound; the node only binds its model state and geometry and draws.
+    /// Nodes that do not occlude light ignore this.
+    fn render_shadow<'a>(&'a self, _render_call_state: &mut RenderCallState<'_, 'a>) {}
 
     /// Gets the render node out of the dirty state.
     /// Potentially expensive operation that rebuilds the resources affected by changed state of the node.
     /// This is called when the node is marked as dirty BUT this does NOT mean
     /// the render node's dirty state is garanteed to be resolved in the next frame.
-    /// Eg. the dirty state is not resolved when the not is not visible. (TODO: this is not implemented yet)
+    /// Eg. the dirty state is not resolved when the node is neither visible nor casts a visible shadow.
+    /// Camera nodes are always resolved.
     fn resolve_dirty_state(&mut self, static_render_state: &mut StaticRenderState);
 
+    /// Called when the world transform of the node has changed, either because its own local transform
+    /// or the local transform of one of its ancestors in the scene graph has changed.
+    /// Nodes without a spatial representation can ignore this.
+    fn set_world_transform(&mut self, _world_transform: &Mat4) {}
+
+    /// The phase the node is rendered in. Nodes of earlier phases are rendered before nodes of later phases.
+    fn render_phase(&self) -> RenderPhase {
+        return RenderPhase::Opaque;
+    }
+
+    /// Identifies the pipeline and material state the node binds. Within the opaque and alpha-tested phases,
+    /// nodes with equal keys are rendered consecutively to avoid redundant state changes.
+    fn state_sort_key(&self) -> u64 {
+        return 0;
+    }
+
+    /// The world space bounding box of everything the node renders, used to cull nodes outside of the view.
+    /// Nodes without a bounding box (eg. cameras, lights and grouping nodes) are never culled, but are also
+    /// never rendered into shadow maps.
+    fn bounding_box(&self) -> Option<Aabb> {
+        return None;
+    }
+
+    /// Called when the node is removed from the scene.
+    /// Releases the GPU resources owned by the node right away instead of when the node is eventually dropped.
+    fn release_resources(&mut self, _static_render_state: &mut StaticRenderState) {}
+
     /// Allows downcast of the render node to a concrete implementation.
     fn as_any(&self) -> &dyn Any;
 
@@ -37,12 +81,30 @@ pub trait RenderNode {
 }
 
 /// A type used to reference a render node in the scene.
-pub type RenderNodeHandle = u64;
+/// Handles of removed nodes are stale and never refer to nodes added later.
+pub type RenderNodeHandle = GenerationalHandle;
+
+/// The bind group index of the active camera's state.
+pub const CAMERA_BIND_GROUP: u32 = 0;
+/// The bind group index of the per render node model state.
+pub const MODEL_BIND_GROUP: u32 = 1;
+/// The bind group index of the material state.
+pub const MATERIAL_BIND_GROUP: u32 = 2;
+/// The bind group index of the lights of the frame.
+pub const LIGHT_BIND_GROUP: u32 = 3;
 
 pub struct StaticRenderState {
-    pub device: Rc<Device>,
-    pub queue: Rc<Queue>,
+    pub device: Arc<Device>,
+    pub queue: Arc<Queue>,
+    /// The bind group layouts shared by all render pipelines, indexed by bind group index.
     pub bind_group_layouts: Vec<wgpu::BindGroupLayout>,
+    pub render_target: RenderTargetState,
+    pub pipeline_cache: PipelineCache,
+    /// A 1x1 white texture, bound in place of textures a material does not have.
+    pub default_texture_view: wgpu::TextureView,
+    pub default_sampler: wgpu::Sampler,
+    pub light_render_state: LightRenderState,
+    pub shadow_render_state: ShadowRenderState,
 }
 
 pub struct RenderCallState<'a, 'b: 'a> {
@@ -50,15 +112,126 @@ pub struct RenderCallState<'a, 'b: 'a> {
 }
 
 impl StaticRenderState {
-    pub(crate) fn push_bind_group_layout(&mut self, bind_group: wgpu::BindGroupLayout) {
-        self.bind_group_layouts.push(bind_group);
+    pub fn new(device: Arc<Device>, queue: Arc<Queue>, render_target: RenderTargetState) -> Self {
+        let camera_bind_group_layout = Self::create_uniform_bind_group_layout(&device, wgpu::ShaderStages::VERTEX_FRAGMENT, "camera_bind_group_layout");
+        let model_bind_group_layout = Self::create_uniform_bind_group_layout(&device, wgpu::ShaderStages::VERTEX, "model_bind_group_layout");
+        let material_bind_group_layout = MaterialRenderState::create_bind_group_layout(&device);
+        let light_bind_group_layout = LightRenderState::create_bind_group_layout(&device);
+        let shadow_render_state = ShadowRenderState::new(&device, &camera_bind_group_layout, &model_bind_group_layout);
+        let light_render_state = LightRenderState::new(&device, &light_bind_group_layout, &shadow_render_state);
+
+        let default_texture = device.create_texture_with_data(
+            &queue,
+            &wgpu::TextureDescriptor {
+                label: Some("DefaultTexture"),
+                size: wgpu::Extent3d { width: 1, height: 1, depth_or_array_layers: 1 },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            },
+            &[255, 255, 255, 255],
+        );
+        let default_texture_view = default_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let default_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("DefaultSampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        return StaticRenderState {
+            device,
+            queue,
+            bind_group_layouts: vec![camera_bind_group_layout, model_bind_group_layout, material_bind_group_layout, light_bind_group_layout],
+            render_target,
+            pipeline_cache: PipelineCache::new(),
+            default_texture_view,
+            default_sampler,
+            light_render_state,
+            shadow_render_state,
+        };
+    }
+
+    fn create_uniform_bind_group_layout(device: &Device, visibility: wgpu::ShaderStages, label: &str) -> wgpu::BindGroupLayout {
+        return device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }
+            ],
+            label: Some(label),
+        });
+    }
+
+    pub fn camera_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        return &self.bind_group_layouts[CAMERA_BIND_GROUP as usize];
+    }
+
+    pub fn model_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        return &self.bind_group_layouts[MODEL_BIND_GROUP as usize];
+    }
+
+    pub fn material_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        return &self.bind_group_layouts[MATERIAL_BIND_GROUP as usize];
+    }
+
+    pub fn light_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        return &self.bind_group_layouts[LIGHT_BIND_GROUP as usize];
+    }
+
+    /// Builds the render pipeline for materials with [features], unless it is already cached.
+    pub fn ensure_pipeline(&mut self, features: MaterialFeatures) {
+        self.pipeline_cache.get_or_create(&self.device, &self.bind_group_layouts, &self.render_target, features);
+    }
+}
+
+/// A render node that draws nothing. Used to group other nodes in the scene graph.
+pub struct EmptyRenderNode;
+
+impl RenderNode for EmptyRenderNode {
+    fn is_dirty(&self) -> bool {
+        return false;
+    }
+
+    fn render<'a, 'b: 'a>(&'b mut self, _static_render_state: &'b StaticRenderState, _render_call_state: &mut RenderCallState<'_, 'b>) {}
+
+    fn render_phase(&self) -> RenderPhase {
+        return RenderPhase::Setup;
+    }
+
+    fn resolve_dirty_state(&mut self, _static_render_state: &mut StaticRenderState) {}
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
     }
 }
 
 pub struct RenderScene {
-    next_handle: RenderNodeHandle,
+    handle_allocator: HandleAllocator,
     pub nodes: HashMap<RenderNodeHandle, Box<dyn RenderNode>>,
+    graph: SceneGraph,
     cameras: Vec<RenderNodeHandle>,
+    lights: Vec<RenderNodeHandle>,
+    /// The nodes visible from the active camera in render order, as of the last [RenderScene::pre_render()].
+    draw_list: Vec<RenderNodeHandle>,
+    culling_stats: CullingStats,
     pub static_render_state: StaticRenderState,
 }
 
@@ -73,41 +246,232 @@ impl RenderScene {
 
 impl RenderScene {
     pub fn new(static_render_state: StaticRenderState) -> Self {
-        RenderScene { next_handle: 1, nodes: HashMap::new(), cameras: Vec::new(), static_render_state }
+        RenderScene {
+            handle_allocator: HandleAllocator::new(),
+            nodes: HashMap::new(),
+            graph: SceneGraph::new(),
+            cameras: Vec::new(),
+            lights: Vec::new(),
+            draw_list: Vec::new(),
+            culling_stats: CullingStats::default(),
+            static_render_state,
+        }
     }
 
+    /// Adds [node] to the scene as a root node with an identity local transform.
     pub(crate) fn add_node<T: RenderNode + 'static>(&mut self, node: Box<T>) -> RenderNodeHandle {
-        if TypeId::of::<T>() != TypeId::of::<CameraRenderNode>() {
-            self.cameras.push(self.next_handle);
+        let handle = self.handle_allocator.allocate();
+        if TypeId::of::<T>() == TypeId::of::<CameraRenderNode>() {
+            self.cameras.push(handle);
+        }
+        if TypeId::of::<T>() == TypeId::of::<LightRenderNode>() {
+            self.lights.push(handle);
         }
-        let handle = self.next_handle;
         self.nodes.insert(handle, node);
-        self.next_handle += 1;
+        self.graph.add_node(handle);
         return handle;
     }
 
+    /// Removes the node and all of its descendants from the scene and releases their GPU resources.
+    /// All handles to the removed nodes become stale.
+    pub fn remove_node(&mut self, node_handle: &RenderNodeHandle) -> Result<(), SceneGraphError> {
+        if !self.is_alive(node_handle) {
+            return Err(SceneGraphError::NodeNotFound(*node_handle));
+        }
+
+        let removed_handles = self.graph.remove_subtree(node_handle)?;
+        for handle in &removed_handles {
+            self.cameras.retain(|camera_handle| camera_handle != handle);
+            self.lights.retain(|light_handle| light_handle != handle);
+            if let Some(mut node) = self.nodes.remove(handle) {
+                node.release_resources(&mut self.static_render_state);
+            }
+            self.handle_allocator.free(handle);
+        }
+        return Ok(());
+    }
+
+    /// Returns whether [node_handle] refers to a node that is still part of the scene.
+    pub fn is_alive(&self, node_handle: &RenderNodeHandle) -> bool {
+        return self.handle_allocator.is_alive(node_handle);
+    }
+
+    /// See [SceneGraph::set_parent()].
+    pub fn set_parent(&mut self, child: &RenderNodeHandle, parent: Option<&RenderNodeHandle>, keep_world_transform: bool) -> Result<(), SceneGraphError> {
+        return self.graph.set_parent(child, parent, keep_world_transform);
+    }
+
+    pub fn parent(&self, node_handle: &RenderNodeHandle) -> Option<RenderNodeHandle> {
+        return self.graph.parent(node_handle);
+    }
+
+    /// Returns the direct children of the node in the order they were attached.
+    pub fn children(&self, node_handle: &RenderNodeHandle) -> &[RenderNodeHandle] {
+        return self.graph.children(node_handle);
+    }
+
+    pub fn root_nodes(&self) -> &[RenderNodeHandle] {
+        return self.graph.root_nodes();
+    }
+
+    pub fn local_transform(&self, node_handle: &RenderNodeHandle) -> Option<&Transform> {
+        return self.graph.local_transform(node_handle);
+    }
+
+    /// Returns false if [node_handle] is stale.
+    pub fn set_local_transform(&mut self, node_handle: &RenderNodeHandle, transform: Transform) -> bool {
+        return self.graph.set_local_transform(node_handle, transform);
+    }
+
+    /// See [SceneGraph::world_transform()].
+    pub fn world_transform(&self, node_handle: &RenderNodeHandle) -> Option<Mat4> {
+        return self.graph.world_transform(node_handle);
+    }
+
+    /// See [SceneGraph::traverse_depth_first()].
+    pub fn traverse_depth_first<F: FnMut(&RenderNodeHandle, usize)>(&self, root: &RenderNodeHandle, visitor: F) {
+        self.graph.traverse_depth_first(root, visitor);
+    }
+
+    /// Returns all descendants of the node in depth-first pre-order, not including the node itself.
+    pub fn descendants(&self, node_handle: &RenderNodeHandle) -> Vec<RenderNodeHandle> {
+        return self.graph.descendants(node_handle);
+    }
+
+    /// Recomputes the world matrices of all nodes whose local transform or whose ancestors' local transforms
+    /// have changed, and passes the new world transforms on to the affected render nodes.
     #[profiling::function]
-    pub fn render<'a, 'b: 'a>(&'b mut self, render_call_state: &mut RenderCallState<'_, 'b>) {
-        for (_, node) in &mut self.nodes {
-            if node.is_dirty() {
+    pub fn update_world_transforms(&mut self) {
+        let nodes = &mut self.nodes;
+        self.graph.update_world_transforms(|handle, world_matrix| {
+            nodes.get_mut(handle).unwrap().set_world_transform(world_matrix);
+        });
+    }
+
+    /// Prepares the scene for rendering the frame: updates world transforms, culls the nodes outside of the
+    /// active camera's view, resolves the dirty state of the remaining nodes, uploads the lights that affect
+    /// what the active camera sees and renders their shadow maps.
+    /// Must be called every frame before the main render pass is begun with [command_encoder].
+    #[profiling::function]
+    pub fn pre_render(&mut self, command_encoder: &mut CommandEncoder) {
+        self.update_world_transforms();
+
+        // Culling depends on the view of the active camera, so cameras are always resolved first
+        for camera_handle in &self.cameras {
+            let camera = self.nodes.get_mut(camera_handle).unwrap();
+            if camera.is_dirty() {
+                camera.resolve_dirty_state(&mut self.static_render_state);
+            }
+        }
+
+        // Upload the lights that affect what the active camera sees
+        let camera_frustum;
+        let camera_view: Option<(Vec3A, Vec3A)>;
+        let shadow_maps;
+        {
+            let static_render_state = &self.static_render_state;
+            let active_camera = self.cameras.iter()
+                .filter_map(|handle| self.nodes.get(handle))
+                .filter_map(|node| node.as_any().downcast_ref::<CameraRenderNode>())
+                .find(|camera| camera.is_active());
+            match active_camera {
+                Some(camera) => {
+                    let lights = self.lights.iter()
+                        .filter_map(|handle| self.nodes.get(handle))
+                        .filter_map(|node| node.as_any().downcast_ref::<LightRenderNode>());
+                    let frustum = Frustum::from_view_proj(&camera.camera().shader_state().view_proj());
+                    let culled_lights = cull_lights(lights, camera.position(), &frustum);
+                    shadow_maps = allocate_shadow_maps(&culled_lights, camera);
+                    static_render_state.light_render_state.upload(&static_render_state.queue, &culled_lights, &shadow_maps);
+                    camera_frustum = Some(frustum);
+                    camera_view = Some((camera.position(), camera.direction()));
+                }
+                None => {
+                    shadow_maps = ShadowMapAllocation { view_projs: Vec::new(), light_layers: Vec::new() };
+                    static_render_state.light_render_state.upload(&static_render_state.queue, &[], &shadow_maps);
+                    // Without a camera there is no view to cull against
+                    camera_frustum = None;
+                    camera_view = None;
+                }
+            }
+        }
+
+        // Cull against the camera's view and the views of the shadow maps
+        let shadow_frusta: Vec<Frustum> = shadow_maps.view_projs.iter()
+            .map(Frustum::from_view_proj)
+            .collect();
+        let culling = cull_nodes(
+            self.nodes.iter().map(|(handle, node)| (*handle, node.bounding_box())),
+            camera_frustum.as_ref(),
+            &shadow_frusta,
+        );
+
+        for (handle, node) in &mut self.nodes {
+            if culling.is_rendered(handle) && node.is_dirty() {
                 node.resolve_dirty_state(&mut self.static_render_state);
             }
-            node.render(&mut self.static_render_state, render_call_state);
+        }
+
+        let static_render_state = &self.static_render_state;
+        static_render_state.shadow_render_state.render_shadow_maps(&static_render_state.queue, command_encoder, &shadow_maps, &culling.shadow_casters, &self.nodes);
+
+        self.culling_stats = culling.stats;
+
+        // Sort the visible nodes into render order
+        let mut draw_keys: Vec<DrawSortKey> = culling.visible_nodes.iter()
+            .map(|handle| {
+                let node = &self.nodes[handle];
+                let view_depth = match (&camera_view, node.bounding_box()) {
+                    (Some((camera_position, camera_direction)), Some(bounding_box)) => camera_direction.dot(bounding_box.center() - *camera_position),
+                    _ => 0.0,
+                };
+                DrawSortKey::new(node.render_phase(), node.state_sort_key(), view_depth, *handle)
+            })
+            .collect();
+        draw_keys.sort();
+        self.draw_list = draw_keys.iter()
+            .map(|key| key.handle())
+            .collect();
+    }
+
+    /// Renders the nodes visible from the active camera into the main render pass.
+    /// [Self::pre_render()] must have been called before.
+    #[profiling::function]
+    pub fn render<'a, 'b: 'a>(&'b mut self, render_call_state: &mut RenderCallState<'_, 'b>) {
+        let static_render_state = &self.static_render_state;
+        render_call_state.render_pass.set_bind_group(LIGHT_BIND_GROUP, static_render_state.light_render_state.bind_group(), &[]);
+
+        // The render pass keeps borrows of all rendered nodes, so all of them are borrowed up front and taken
+        // out in draw order
+        let mut nodes: HashMap<&RenderNodeHandle, &mut Box<dyn RenderNode>> = self.nodes.iter_mut().collect();
+        for handle in &self.draw_list {
+            // Nodes removed since the last pre_render are skipped
+            if let Some(node) = nodes.remove(handle) {
+                node.render(static_render_state, render_call_state);
+            }
         }
     }
 
-    pub fn set_active_camera(&mut self, camera_handle: &RenderNodeHandle) {
-        {
-            let camera: &mut CameraRenderNode = self.get_node_by_id(camera_handle).unwrap();
-            camera.set_active();
+    /// Statistics of the culling performed by the last [Self::pre_render()].
+    pub fn culling_stats(&self) -> &CullingStats {
+        return &self.culling_stats;
+    }
+
+    /// Renders from [camera_handle] and deactivates all other cameras.
+    /// Returns false and leaves the cameras unchanged if [camera_handle] is stale or not a camera.
+    pub fn set_active_camera(&mut self, camera_handle: &RenderNodeHandle) -> bool {
+        match self.get_node_by_id::<CameraRenderNode>(camera_handle) {
+            Some(camera) => camera.set_active(),
+            None => return false,
         }
 
         let cameras = self.cameras.clone();
-        for camera_handle in &cameras {
-            if camera_handle != camera_handle {
-                let camera: &mut CameraRenderNode = self.get_node_by_id(camera_handle).unwrap();
+        for other_camera_handle in &cameras {
+            if other_camera_handle != camera_handle {
+                let camera: &mut CameraRenderNode = self.get_node_by_id(other_camera_handle).unwrap();
                 camera.set_inactive();
             }
         }
+        return true;
     }
 }
\ No newline at end of file
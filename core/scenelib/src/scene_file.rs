@@ -0,0 +1,682 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fmt::Formatter;
+use std::fs;
+use std::path::{Path, PathBuf};
+use glam::{Quat, Vec3A};
+use serde::{Deserialize, Serialize};
+use crate::camera::{Camera, CameraRenderNode};
+use crate::ecs::{CameraComponent, CameraEntity, ECSEntityHandle, ECSWorld, FlyingCameraComponent, LightComponent, LightEntity, MeshEntity, ModelComponent, TransformComponent, VelocityComponent};
+use crate::gltf_loader::{self, Model, ModelLoadError};
+use crate::light::{Light, LightKind};
+use crate::prefab::{prefab_dir, PrefabInstanceComponent, PrefabLibrary, PrefabOverride};
+use crate::scene::RenderScene;
+use crate::transform::Transform;
+
+/// The version of the scene file format written by [SceneFile::save()].
+/// Files of older versions are upgraded when loaded, files of newer versions are rejected.
+/// Version 2 added prefab instances.
+pub const SCENE_FILE_VERSION: u32 = 2;
+
+#[derive(Debug)]
+pub enum SceneFileError {
+    Io(std::io::Error),
+    /// The file is not a valid [SceneFile].
+    Parse(ron::Error),
+    Serialize(ron::Error),
+    /// The file was written by a newer version of the engine.
+    UnsupportedVersion(u32),
+    /// The parent of the entity at index [entity] does not come before it in the file.
+    InvalidParent { entity: usize, parent: usize },
+    Model { path: PathBuf, error: ModelLoadError },
+    /// A prefab has no root entity.
+    EmptyPrefab,
+    /// The engine has not been started, so there is no scene to save or load into.
+    NotStarted,
+    /// The scene has no camera, neither as an entity nor in a prefab instance, so it cannot be rendered.
+    NoCamera,
+}
+
+impl fmt::Display for SceneFileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneFileError::Io(error) => write!(f, "I/O error: {}", error),
+            SceneFileError::Parse(error) => write!(f, "Invalid scene file: {}", error),
+            SceneFileError::Serialize(error) => write!(f, "Failed to serialize scene: {}", error),
+            SceneFileError::UnsupportedVersion(version) => write!(f, "Unsupported scene file version {}, the latest supported version is {}", version, SCENE_FILE_VERSION),
+            SceneFileError::InvalidParent { entity, parent } => write!(f, "Entity {} has parent {}, which does not come before it", entity, parent),
+            SceneFileError::Model { path, error } => write!(f, "Failed to load model {}: {}", path.display(), error),
+            SceneFileError::EmptyPrefab => write!(f, "The prefab has no entities"),
+            SceneFileError::NotStarted => write!(f, "The engine has not been started"),
+            SceneFileError::NoCamera => write!(f, "The scene has no camera"),
+        }
+    }
+}
+
+impl From<std::io::Error> for SceneFileError {
+    fn from(error: std::io::Error) -> Self {
+        return SceneFileError::Io(error);
+    }
+}
+
+/// A scene saved to a RON file: the entities of an [ECSWorld] along with the state of their render nodes.
+/// Models are referenced by the path of their glTF file, which also holds their meshes and materials.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SceneFile {
+    pub version: u32,
+    #[serde(default)]
+    pub entities: Vec<SceneEntity>,
+    #[serde(default)]
+    pub prefab_instances: Vec<ScenePrefabInstance>,
+}
+
+/// An instance of a [Prefab], see [PrefabLibrary::instantiate()].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ScenePrefabInstance {
+    /// The path of the prefab file. Relative paths are relative to the scene file.
+    pub prefab: PathBuf,
+    /// The index of the entity in [SceneFile::entities] the instance is attached to.
+    #[serde(default)]
+    pub parent: Option<usize>,
+    #[serde(default)]
+    pub transform: SceneTransform,
+    #[serde(default)]
+    pub overrides: Vec<PrefabOverride>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SceneEntity {
+    /// The index of the parent entity, which must come before this entity. The transform is relative to it.
+    #[serde(default)]
+    pub parent: Option<usize>,
+    #[serde(default)]
+    pub transform: SceneTransform,
+    /// unit: world units per second
+    #[serde(default)]
+    pub velocity: Option<[f32; 3]>,
+    pub kind: SceneEntityKind,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SceneTransform {
+    pub translation: [f32; 3],
+    /// Quaternion in x, y, z, w order.
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
+}
+
+impl Default for SceneTransform {
+    fn default() -> Self {
+        return SceneTransform::from(Transform::IDENTITY);
+    }
+}
+
+impl From<Transform> for SceneTransform {
+    fn from(transform: Transform) -> Self {
+        return SceneTransform {
+            translation: transform.translation.into(),
+            rotation: transform.rotation.into(),
+            scale: transform.scale.into(),
+        };
+    }
+}
+
+impl From<SceneTransform> for Transform {
+    fn from(transform: SceneTransform) -> Self {
+        return Transform::new(
+            Vec3A::from(transform.translation),
+            Quat::from_array(transform.rotation).normalize(),
+            Vec3A::from(transform.scale),
+        );
+    }
+}
+
+/// The angles of cameras and spot lights are in degrees.
+/// The aspect ratio of cameras is not saved, it is given by the viewport the scene is loaded into.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SceneEntityKind {
+    /// A perspective camera. [flying] cameras are moved by the movement input.
+    PerspectiveCamera { forward_axis: [f32; 3], up_axis: [f32; 3], fov: f32, near: f32, far: Option<f32>, flying: bool },
+    OrthographicCamera { forward_axis: [f32; 3], up_axis: [f32; 3], height: f32, near: f32, far: f32 },
+    Light(SceneLight),
+    /// A glTF model. Relative paths are relative to the scene file.
+    Model { path: PathBuf },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SceneLight {
+    Directional { color: [f32; 3], intensity: f32, casts_shadows: bool },
+    Point { color: [f32; 3], intensity: f32, range: f32 },
+    Spot { color: [f32; 3], intensity: f32, range: f32, inner_cone_angle: f32, outer_cone_angle: f32, casts_shadows: bool },
+}
+
+impl From<Light> for SceneLight {
+    fn from(light: Light) -> Self {
+        let color = light.color.into();
+        return match light.kind {
+            LightKind::Directional => SceneLight::Directional { color, intensity: light.intensity, casts_shadows: light.casts_shadows },
+            LightKind::Point { range } => SceneLight::Point { color, intensity: light.intensity, range },
+            LightKind::Spot { range, inner_cone_angle, outer_cone_angle } => SceneLight::Spot {
+                color,
+                intensity: light.intensity,
+                range,
+                inner_cone_angle: inner_cone_angle.to_degrees(),
+                outer_cone_angle: outer_cone_angle.to_degrees(),
+                casts_shadows: light.casts_shadows,
+            },
+        };
+    }
+}
+
+impl From<SceneLight> for Light {
+    fn from(light: SceneLight) -> Self {
+        return match light {
+            SceneLight::Directional { color, intensity, casts_shadows } => Light::directional(Vec3A::from(color), intensity).with_shadows(casts_shadows),
+            SceneLight::Point { color, intensity, range } => Light::point(Vec3A::from(color), intensity, range),
+            SceneLight::Spot { color, intensity, range, inner_cone_angle, outer_cone_angle, casts_shadows } => {
+                Light::spot(Vec3A::from(color), intensity, range, inner_cone_angle.to_radians(), outer_cone_angle.to_radians()).with_shadows(casts_shadows)
+            }
+        };
+    }
+}
+
+impl SceneFile {
+    pub fn new() -> Self {
+        return SceneFile { version: SCENE_FILE_VERSION, entities: Vec::new(), prefab_instances: Vec::new() };
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SceneFileError> {
+        return SceneFile::from_ron(&fs::read_to_string(path)?);
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SceneFileError> {
+        fs::write(path, self.to_ron()?)?;
+        return Ok(());
+    }
+
+    /// Parses a scene file, upgrading it to [SCENE_FILE_VERSION].
+    pub fn from_ron(ron: &str) -> Result<Self, SceneFileError> {
+        let scene_file: SceneFile = ron::from_str(ron).map_err(SceneFileError::Parse)?;
+        if scene_file.version > SCENE_FILE_VERSION {
+            return Err(SceneFileError::UnsupportedVersion(scene_file.version));
+        }
+        // Version 1 files have no prefab instances, which default to none
+        return Ok(SceneFile { version: SCENE_FILE_VERSION, ..scene_file });
+    }
+
+    pub fn to_ron(&self) -> Result<String, SceneFileError> {
+        return ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()).map_err(SceneFileError::Serialize);
+    }
+
+    /// Describes the entities of [ecs_world] whose kind is known to the scene file format: cameras, lights,
+    /// models loaded by [MeshEntity::load_model()] and prefab instances. Other entities and their children are
+    /// skipped, as are entities attached to prefab instances. Paths below [scene_dir] are made relative to it.
+    /// The state of the entities kept outside of [ecs_world] is read from [render_scene], see [SceneEntitySpawner].
+    pub fn capture<S: SceneEntitySpawner>(ecs_world: &ECSWorld, render_scene: &mut S, scene_dir: &Path) -> Self {
+        let instance_roots = ecs_world.entities_with::<(PrefabInstanceComponent,)>();
+        let instance_members: HashSet<ECSEntityHandle> = instance_roots.iter()
+            .flat_map(|root| ecs_world.get_component::<PrefabInstanceComponent>(root).unwrap().members)
+            .collect();
+        let mut entity_handles: Vec<ECSEntityHandle> = ecs_world.entities_with::<(TransformComponent,)>().into_iter()
+            .filter(|entity_handle| !instance_members.contains(entity_handle))
+            .collect();
+
+        // Parents must come before their children. Entities are added once their parent has been added.
+        let mut scene_file = SceneFile::new();
+        let mut entity_indices: HashMap<ECSEntityHandle, usize> = HashMap::new();
+        loop {
+            let entity_count = entity_indices.len();
+            let mut remaining_handles = Vec::new();
+            for entity_handle in entity_handles {
+                let parent = match ecs_world.get_parent(&entity_handle) {
+                    Some(parent_handle) => match entity_indices.get(&parent_handle) {
+                        Some(parent_index) => Some(*parent_index),
+                        None => {
+                            remaining_handles.push(entity_handle);
+                            continue;
+                        }
+                    },
+                    None => None,
+                };
+                if let Some(scene_entity) = capture_entity(ecs_world, render_scene, scene_dir, &entity_handle, parent) {
+                    entity_indices.insert(entity_handle, scene_file.entities.len());
+                    scene_file.entities.push(scene_entity);
+                }
+            }
+            // The remaining entities are attached to skipped entities
+            if entity_indices.len() == entity_count {
+                break;
+            }
+            entity_handles = remaining_handles;
+        }
+
+        for root in &instance_roots {
+            let instance = ecs_world.get_component::<PrefabInstanceComponent>(root).unwrap();
+            scene_file.prefab_instances.push(ScenePrefabInstance {
+                prefab: relative_to(instance.prefab, scene_dir),
+                parent: ecs_world.get_parent(root).and_then(|parent| entity_indices.get(&parent).copied()),
+                transform: SceneTransform::from(ecs_world.get_transform(root).unwrap_or(Transform::IDENTITY)),
+                overrides: instance.overrides,
+            });
+        }
+        return scene_file;
+    }
+
+    /// Adds the entities and prefab instances of the scene to [ecs_world] and [render_scene], see
+    /// [SceneEntitySpawner], and returns the
+    /// handles of the entities, in the order of [Self::entities], followed by the root entities of the prefab
+    /// instances. Cameras are created with the given [aspect] ratio.
+    /// Relative paths are resolved against [scene_dir], prefabs are loaded through [prefabs].
+    /// The file is validated and all models are loaded before the first entity is added, so no entities are added
+    /// if an error is returned.
+    pub fn spawn<S: SceneEntitySpawner>(&self, ecs_world: &mut ECSWorld, render_scene: &mut S, prefabs: &mut PrefabLibrary, scene_dir: &Path, aspect: f32) -> Result<Vec<ECSEntityHandle>, SceneFileError> {
+        let mut entities = self.entities.clone();
+        resolve_model_paths(&mut entities, scene_dir);
+
+        // The entities of all instances are appended to the entities of the scene, so they are spawned together
+        let mut instances = Vec::with_capacity(self.prefab_instances.len());
+        for prefab_instance in &self.prefab_instances {
+            let prefab_path = scene_dir.join(&prefab_instance.prefab);
+            let mut instance_entities = prefabs.load(&prefab_path)?.instance_entities(prefab_instance.transform, &prefab_instance.overrides);
+            resolve_model_paths(&mut instance_entities, prefab_dir(&prefab_path));
+
+            let first_member = entities.len();
+            for (entity_index, instance_entity) in instance_entities.iter().enumerate() {
+                let mut entity = instance_entity.clone();
+                entity.parent = match entity_index {
+                    0 => match prefab_instance.parent {
+                        Some(parent) if parent < self.entities.len() => Some(parent),
+                        Some(parent) => return Err(SceneFileError::InvalidParent { entity: first_member, parent }),
+                        None => None,
+                    },
+                    _ => entity.parent.map(|parent| first_member + parent),
+                };
+                entities.push(entity);
+            }
+            instances.push((prefab_path, prefab_instance.overrides.clone(), first_member, instance_entities));
+        }
+
+        let entity_handles = spawn_entities(&entities, ecs_world, render_scene, aspect)?;
+
+        let mut handles = entity_handles[..self.entities.len()].to_vec();
+        for (prefab_path, overrides, first_member, instance_entities) in instances {
+            let members = entity_handles[first_member..first_member + instance_entities.len()].to_vec();
+            let root = members[0];
+            ecs_world.insert_component(&root, PrefabInstanceComponent::new(prefab_path, overrides, members, instance_entities));
+            handles.push(root);
+        }
+        return Ok(handles);
+    }
+}
+
+/// Resolves the relative model paths of [entities] against [dir].
+pub(crate) fn resolve_model_paths(entities: &mut [SceneEntity], dir: &Path) {
+    for entity in entities {
+        if let SceneEntityKind::Model { path } = &mut entity.kind {
+            *path = dir.join(path.as_path());
+        }
+    }
+}
+
+/// [path] relative to [dir] if it is below [dir], otherwise [path] itself.
+fn relative_to(path: PathBuf, dir: &Path) -> PathBuf {
+    return match path.strip_prefix(dir) {
+        Ok(relative_path) => relative_path.to_path_buf(),
+        Err(_) => path,
+    };
+}
+
+/// Creates the entities of scene files and reads back the state they keep outside of the [ECSWorld].
+/// Implemented by [RenderScene], which gives the entities render nodes. Other implementations let scenes be
+/// spawned and captured without a GPU.
+pub trait SceneEntitySpawner {
+    /// Adds an entity of [kind] placed by [transform] and returns its handle. [model] is the loaded model of
+    /// [SceneEntityKind::Model]s. Cameras are created with the given [aspect] ratio.
+    /// The components stored in scene files, eg. the velocity, are inserted by the caller.
+    fn add_entity(&mut self, ecs_world: &mut ECSWorld, kind: &SceneEntityKind, model: Option<&Model>, transform: Transform, aspect: f32) -> ECSEntityHandle;
+
+    /// Attaches [child] to [parent]. The transform of [child] is kept and becomes relative to [parent].
+    fn set_parent(&mut self, ecs_world: &mut ECSWorld, child: &ECSEntityHandle, parent: &ECSEntityHandle);
+
+    /// Despawns [entity_handle] and the entities attached to it, see [ECSWorld::remove_entity()].
+    /// Returns false if [entity_handle] is stale.
+    fn remove_entity(&mut self, ecs_world: &mut ECSWorld, entity_handle: &ECSEntityHandle) -> bool;
+
+    /// The camera of [entity_handle]. None if the entity is no camera.
+    fn camera_mut(&mut self, ecs_world: &ECSWorld, entity_handle: &ECSEntityHandle) -> Option<&mut Camera>;
+
+    fn camera(&mut self, ecs_world: &ECSWorld, entity_handle: &ECSEntityHandle) -> Option<&Camera> {
+        return self.camera_mut(ecs_world, entity_handle).map(|camera| &*camera);
+    }
+}
+
+impl SceneEntitySpawner for RenderScene {
+    fn add_entity(&mut self, ecs_world: &mut ECSWorld, kind: &SceneEntityKind, model: Option<&Model>, transform: Transform, aspect: f32) -> ECSEntityHandle {
+        let entity_handle = match kind {
+            SceneEntityKind::PerspectiveCamera { forward_axis, up_axis, fov, near, far, .. } => {
+                let forward_axis = Vec3A::from(*forward_axis);
+                CameraEntity::add_flying(
+                    ecs_world, self,
+                    transform.translation, transform.rotation * forward_axis, forward_axis, Vec3A::from(*up_axis),
+                    *fov, *near, *far, aspect,
+                )
+            }
+            SceneEntityKind::OrthographicCamera { forward_axis, up_axis, height, near, far } => {
+                let forward_axis = Vec3A::from(*forward_axis);
+                CameraEntity::add_orthographic(
+                    ecs_world, self,
+                    transform.translation, transform.rotation * forward_axis, forward_axis, Vec3A::from(*up_axis),
+                    *height, *near, *far, aspect,
+                )
+            }
+            SceneEntityKind::Light(light) => {
+                LightEntity::add_with_transform(ecs_world, self, Light::from(*light), transform)
+            }
+            SceneEntityKind::Model { .. } => {
+                let model = model.expect("Model entities are added with their loaded model");
+                MeshEntity::spawn_model(ecs_world, self, model, transform)
+            }
+        };
+        if let Some(render_node) = ecs_world.get_entity(&entity_handle).unwrap().get_render_node() {
+            self.set_local_transform(render_node, transform);
+        }
+        return entity_handle;
+    }
+
+    fn set_parent(&mut self, ecs_world: &mut ECSWorld, child: &ECSEntityHandle, parent: &ECSEntityHandle) {
+        ecs_world.set_parent(child, Some(parent), self);
+    }
+
+    fn remove_entity(&mut self, ecs_world: &mut ECSWorld, entity_handle: &ECSEntityHandle) -> bool {
+        return ecs_world.remove_entity(entity_handle, self);
+    }
+
+    fn camera_mut(&mut self, ecs_world: &ECSWorld, entity_handle: &ECSEntityHandle) -> Option<&mut Camera> {
+        let render_node = *ecs_world.get_entity(entity_handle)?.get_render_node()?;
+        let camera_render_node: &mut CameraRenderNode = self.get_node_by_id(&render_node)?;
+        return Some(camera_render_node.camera_mut());
+    }
+}
+
+/// Spawns [entities], whose model paths must have been resolved, and returns their handles in the same order.
+/// All models are loaded before the first entity is added, so no entities are added if an error is returned.
+pub(crate) fn spawn_entities<S: SceneEntitySpawner>(entities: &[SceneEntity], ecs_world: &mut ECSWorld, render_scene: &mut S, aspect: f32) -> Result<Vec<ECSEntityHandle>, SceneFileError> {
+    let mut models: HashMap<PathBuf, Model> = HashMap::new();
+    for (entity_index, scene_entity) in entities.iter().enumerate() {
+        if let Some(parent) = scene_entity.parent {
+            if parent >= entity_index {
+                return Err(SceneFileError::InvalidParent { entity: entity_index, parent });
+            }
+        }
+        if let SceneEntityKind::Model { path } = &scene_entity.kind {
+            if !models.contains_key(path) {
+                let model = gltf_loader::load_model_from_file(path)
+                    .map_err(|error| SceneFileError::Model { path: path.clone(), error })?;
+                models.insert(path.clone(), model);
+            }
+        }
+    }
+
+    let mut entity_handles = Vec::with_capacity(entities.len());
+    for scene_entity in entities {
+        let transform = Transform::from(scene_entity.transform);
+        let model = match &scene_entity.kind {
+            SceneEntityKind::Model { path } => Some(&models[path]),
+            _ => None,
+        };
+        let entity_handle = render_scene.add_entity(ecs_world, &scene_entity.kind, model, transform, aspect);
+        match &scene_entity.kind {
+            SceneEntityKind::PerspectiveCamera { flying, .. } => {
+                // Keep the roll, which the direction does not carry
+                ecs_world.insert_component(&entity_handle, FlyingCameraComponent::from_rotation(transform.rotation));
+                if !*flying {
+                    ecs_world.remove_component::<FlyingCameraComponent>(&entity_handle);
+                    ecs_world.remove_component::<VelocityComponent>(&entity_handle);
+                }
+            }
+            SceneEntityKind::Model { path } => {
+                ecs_world.insert_component(&entity_handle, ModelComponent { path: path.clone() });
+            }
+            SceneEntityKind::OrthographicCamera { .. } | SceneEntityKind::Light(_) => {}
+        }
+        ecs_world.set_transform(&entity_handle, transform);
+        if let Some(velocity) = scene_entity.velocity {
+            ecs_world.insert_component(&entity_handle, VelocityComponent { velocity: Vec3A::from(velocity) });
+        }
+        if let Some(parent) = scene_entity.parent {
+            render_scene.set_parent(ecs_world, &entity_handle, &entity_handles[parent]);
+        }
+        entity_handles.push(entity_handle);
+    }
+    return Ok(entity_handles);
+}
+
+/// Describes a single entity. None if its kind is not known to the scene file format.
+fn capture_entity<S: SceneEntitySpawner>(ecs_world: &ECSWorld, render_scene: &mut S, scene_dir: &Path, entity_handle: &ECSEntityHandle, parent: Option<usize>) -> Option<SceneEntity> {
+    let kind;
+    if let Some(camera) = ecs_world.get_component::<CameraComponent>(entity_handle) {
+        kind = match render_scene.camera(ecs_world, entity_handle)? {
+            Camera::Perspective(perspective_camera) => SceneEntityKind::PerspectiveCamera {
+                forward_axis: camera.forward_axis.into(),
+                up_axis: camera.up_axis.into(),
+                fov: perspective_camera.fov().to_degrees(),
+                near: perspective_camera.near(),
+                far: perspective_camera.far(),
+                flying: ecs_world.has_component::<FlyingCameraComponent>(entity_handle),
+            },
+            Camera::Orthographic(orthographic_camera) => SceneEntityKind::OrthographicCamera {
+                forward_axis: camera.forward_axis.into(),
+                up_axis: camera.up_axis.into(),
+                height: orthographic_camera.height(),
+                near: orthographic_camera.near(),
+                far: orthographic_camera.far(),
+            },
+        };
+    } else if let Some(light) = ecs_world.get_component::<LightComponent>(entity_handle) {
+        kind = SceneEntityKind::Light(SceneLight::from(light.light));
+    } else if let Some(model) = ecs_world.get_component::<ModelComponent>(entity_handle) {
+        kind = SceneEntityKind::Model { path: relative_to(model.path, scene_dir) };
+    } else {
+        return None;
+    }
+
+    return Some(SceneEntity {
+        parent,
+        transform: SceneTransform::from(ecs_world.get_transform(entity_handle)?),
+        velocity: ecs_world.get_component::<VelocityComponent>(entity_handle)
+            .map(|velocity| velocity.velocity.into())
+            .filter(|velocity: &[f32; 3]| *velocity != [0.0; 3]),
+        kind,
+    });
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use specs::{World, WorldExt};
+    use crate::camera::{OrthographicCamera, PerspectiveCamera};
+    use crate::ecs::{ECSEntity, ParentComponent};
+    use crate::ecs::tests::add_bare_entity;
+    use crate::prefab::FieldOverride;
+    use super::*;
+
+    /// Spawns entities without render nodes and keeps their cameras itself, so no GPU is needed.
+    #[derive(Default)]
+    pub(crate) struct BareSpawner {
+        cameras: HashMap<ECSEntityHandle, Camera>,
+    }
+
+    impl SceneEntitySpawner for BareSpawner {
+        fn add_entity(&mut self, ecs_world: &mut ECSWorld, kind: &SceneEntityKind, _model: Option<&Model>, transform: Transform, aspect: f32) -> ECSEntityHandle {
+            let entity_handle = add_bare_entity(ecs_world);
+            match kind {
+                SceneEntityKind::PerspectiveCamera { forward_axis, up_axis, fov, near, far, .. } => {
+                    let (forward_axis, up_axis) = (Vec3A::from(*forward_axis), Vec3A::from(*up_axis));
+                    let camera = PerspectiveCamera::new(transform.translation, transform.rotation * forward_axis, forward_axis, up_axis, *fov, *near, *far, aspect);
+                    ecs_world.insert_component(&entity_handle, CameraComponent { forward_axis, up_axis, fov: Some(fov.to_radians()) });
+                    self.cameras.insert(entity_handle, Camera::from(camera));
+                }
+                SceneEntityKind::OrthographicCamera { forward_axis, up_axis, height, near, far } => {
+                    let (forward_axis, up_axis) = (Vec3A::from(*forward_axis), Vec3A::from(*up_axis));
+                    let camera = OrthographicCamera::new(transform.translation, transform.rotation * forward_axis, forward_axis, up_axis, *height, *near, *far, aspect);
+                    ecs_world.insert_component(&entity_handle, CameraComponent { forward_axis, up_axis, fov: None });
+                    self.cameras.insert(entity_handle, Camera::from(camera));
+                }
+                SceneEntityKind::Light(light) => {
+                    ecs_world.insert_component(&entity_handle, LightComponent { light: Light::from(*light) });
+                }
+                SceneEntityKind::Model { .. } => {}
+            }
+            return entity_handle;
+        }
+
+        fn set_parent(&mut self, ecs_world: &mut ECSWorld, child: &ECSEntityHandle, parent: &ECSEntityHandle) {
+            ecs_world.insert_component(child, ParentComponent { parent: *parent });
+        }
+
+        fn remove_entity(&mut self, ecs_world: &mut ECSWorld, entity_handle: &ECSEntityHandle) -> bool {
+            let removed = ecs_world.remove_entity_with(entity_handle, &mut |world: &mut World, entity: &mut dyn ECSEntity| {
+                world.delete_entity(entity.specs_entity()).unwrap();
+            });
+            self.cameras.retain(|camera_handle, _| ecs_world.is_alive(camera_handle));
+            return removed;
+        }
+
+        fn camera_mut(&mut self, _ecs_world: &ECSWorld, entity_handle: &ECSEntityHandle) -> Option<&mut Camera> {
+            return self.cameras.get_mut(entity_handle);
+        }
+    }
+
+    fn example_scene() -> SceneFile {
+        return SceneFile {
+            version: SCENE_FILE_VERSION,
+            entities: vec![
+                SceneEntity {
+                    parent: None,
+                    transform: SceneTransform::from(Transform::from_translation(Vec3A::new(0.0, 1.0, -5.0))),
+                    velocity: None,
+                    kind: SceneEntityKind::PerspectiveCamera { forward_axis: [0.0, 0.0, 1.0], up_axis: [0.0, 1.0, 0.0], fov: 70.0, near: 0.01, far: None, flying: true },
+                },
+                SceneEntity {
+                    parent: None,
+                    transform: SceneTransform::default(),
+                    velocity: Some([1.0, 0.0, 0.0]),
+                    kind: SceneEntityKind::Model { path: PathBuf::from("models/crate.gltf") },
+                },
+                SceneEntity {
+                    parent: Some(1),
+                    transform: SceneTransform::from(Transform::from_translation(Vec3A::Y)),
+                    velocity: None,
+                    kind: SceneEntityKind::Light(SceneLight::Spot { color: [1.0, 0.5, 0.25], intensity: 10.0, range: 5.0, inner_cone_angle: 15.0, outer_cone_angle: 30.0, casts_shadows: true }),
+                },
+            ],
+            prefab_instances: vec![
+                ScenePrefabInstance {
+                    prefab: PathBuf::from("prefabs/lamp.ron"),
+                    parent: Some(1),
+                    transform: SceneTransform::from(Transform::from_translation(Vec3A::Z)),
+                    overrides: vec![PrefabOverride { entity: 1, field: FieldOverride::LightIntensity(50.0) }],
+                },
+            ],
+        };
+    }
+
+    #[test]
+    fn round_trips_through_ron() {
+        let scene = example_scene();
+        let ron = scene.to_ron().unwrap();
+        assert_eq!(SceneFile::from_ron(&ron).unwrap(), scene);
+    }
+
+    #[test]
+    fn round_trips_through_ecs_world() {
+        let scene = SceneFile {
+            version: SCENE_FILE_VERSION,
+            entities: vec![
+                SceneEntity {
+                    parent: None,
+                    transform: SceneTransform::from(Transform::from_translation(Vec3A::new(0.0, 1.0, -5.0))),
+                    velocity: None,
+                    kind: SceneEntityKind::PerspectiveCamera { forward_axis: [0.0, 0.0, 1.0], up_axis: [0.0, 1.0, 0.0], fov: 90.0, near: 0.01, far: None, flying: true },
+                },
+                SceneEntity {
+                    parent: None,
+                    transform: SceneTransform::from(Transform::from_translation(Vec3A::new(0.0, 10.0, 0.0))),
+                    velocity: None,
+                    kind: SceneEntityKind::OrthographicCamera { forward_axis: [0.0, 0.0, 1.0], up_axis: [0.0, 1.0, 0.0], height: 4.0, near: 0.1, far: 100.0 },
+                },
+                SceneEntity {
+                    parent: Some(0),
+                    transform: SceneTransform::from(Transform::from_translation(Vec3A::Y)),
+                    velocity: Some([1.0, 0.0, 0.0]),
+                    kind: SceneEntityKind::Light(SceneLight::Point { color: [1.0, 0.5, 0.25], intensity: 10.0, range: 5.0 }),
+                },
+                SceneEntity {
+                    parent: None,
+                    transform: SceneTransform::default(),
+                    velocity: None,
+                    kind: SceneEntityKind::Light(SceneLight::Directional { color: [1.0, 1.0, 1.0], intensity: 2.0, casts_shadows: true }),
+                },
+            ],
+            prefab_instances: Vec::new(),
+        };
+        let mut ecs_world = ECSWorld::new();
+        let mut spawner = BareSpawner::default();
+        let scene_dir = Path::new("scenes");
+
+        let handles = scene.spawn(&mut ecs_world, &mut spawner, &mut PrefabLibrary::new(), scene_dir, 1.5).unwrap();
+        assert_eq!(handles.len(), scene.entities.len());
+        assert_eq!(ecs_world.get_parent(&handles[2]), Some(handles[0]));
+        assert_eq!(ecs_world.get_component::<VelocityComponent>(&handles[2]).unwrap().velocity, Vec3A::X);
+        assert!(ecs_world.has_component::<FlyingCameraComponent>(&handles[0]));
+
+        // Entities of unknown kind and their children are not saved
+        let unknown = add_bare_entity(&mut ecs_world);
+        ecs_world.set_transform(&unknown, Transform::IDENTITY);
+        let child_of_unknown = spawner.add_entity(&mut ecs_world, &scene.entities[3].kind, None, Transform::IDENTITY, 1.5);
+        ecs_world.set_transform(&child_of_unknown, Transform::IDENTITY);
+        spawner.set_parent(&mut ecs_world, &child_of_unknown, &unknown);
+
+        let mut captured = SceneFile::capture(&ecs_world, &mut spawner, scene_dir);
+        match &mut captured.entities[0].kind {
+            SceneEntityKind::PerspectiveCamera { fov, .. } => {
+                // Converted to radians and back
+                assert!((*fov - 90.0).abs() < 1e-4);
+                *fov = 90.0;
+            }
+            kind => panic!("Expected a perspective camera, got {:?}", kind),
+        }
+        assert_eq!(captured, scene);
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let mut scene = example_scene();
+        scene.version = SCENE_FILE_VERSION + 1;
+        let ron = scene.to_ron().unwrap();
+        assert!(matches!(SceneFile::from_ron(&ron), Err(SceneFileError::UnsupportedVersion(version)) if version == SCENE_FILE_VERSION + 1));
+    }
+
+    #[test]
+    fn upgrades_version_1() {
+        let scene = SceneFile::from_ron("(version: 1, entities: [(kind: Model(path: \"crate.gltf\"))])").unwrap();
+        assert_eq!(scene.version, SCENE_FILE_VERSION);
+        assert_eq!(scene.entities[0].parent, None);
+        assert_eq!(Transform::from(scene.entities[0].transform), Transform::IDENTITY);
+        assert!(scene.prefab_instances.is_empty());
+    }
+
+    #[test]
+    fn lights_round_trip() {
+        let light = Light::spot(Vec3A::ONE, 2.0, 4.0, 0.25, 0.5).with_shadows(true);
+        let round_tripped = Light::from(SceneLight::from(light));
+        match round_tripped.kind {
+            LightKind::Spot { range, inner_cone_angle, outer_cone_angle } => {
+                assert_eq!(range, 4.0);
+                assert!((inner_cone_angle - 0.25).abs() < 1e-6);
+                assert!((outer_cone_angle - 0.5).abs() < 1e-6);
+            }
+            kind => panic!("Expected a spot light, got {:?}", kind),
+        }
+        assert!(round_tripped.casts_shadows);
+    }
+}
@@ -0,0 +1,346 @@
+use std::collections::HashMap;
+use glam::Mat4;
+use crate::scene::RenderNodeHandle;
+use crate::transform::Transform;
+
+/// The position of a render node in the scene graph.
+struct SceneGraphNode {
+    parent: Option<RenderNodeHandle>,
+    children: Vec<RenderNodeHandle>,
+    /// The transform of the node relative to its parent (or the world, if the node has no parent).
+    local_transform: Transform,
+    /// The cached world matrix of the node. Only valid after [SceneGraph::update_world_transforms()].
+    world_matrix: Mat4,
+    /// Whether the world matrix of this node and thus of all of its descendants needs to be recomputed.
+    /// New nodes start out clean, so that render nodes keep their initial state until they are moved
+    /// or attached to a parent.
+    transform_dirty: bool,
+}
+
+impl SceneGraphNode {
+    fn new() -> Self {
+        return SceneGraphNode {
+            parent: None,
+            children: Vec::new(),
+            local_transform: Transform::IDENTITY,
+            world_matrix: Mat4::IDENTITY,
+            transform_dirty: false,
+        };
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SceneGraphError {
+    /// The referenced render node does not exist in the scene or has been removed.
+    NodeNotFound(RenderNodeHandle),
+    /// The requested parent is the node itself or one of its descendants.
+    CycleDetected { child: RenderNodeHandle, parent: RenderNodeHandle },
+}
+
+/// The hierarchy and the transforms of the render nodes of a [crate::scene::RenderScene].
+/// Holds no render state, the scene owns the render nodes and the handles referring to them.
+pub struct SceneGraph {
+    nodes: HashMap<RenderNodeHandle, SceneGraphNode>,
+    /// Nodes without a parent, in insertion order.
+    root_nodes: Vec<RenderNodeHandle>,
+}
+
+impl SceneGraph {
+    pub fn new() -> Self {
+        return SceneGraph {
+            nodes: HashMap::new(),
+            root_nodes: Vec::new(),
+        };
+    }
+
+    /// Adds [node_handle] as a root node with an identity local transform.
+    pub fn add_node(&mut self, node_handle: RenderNodeHandle) {
+        self.nodes.insert(node_handle, SceneGraphNode::new());
+        self.root_nodes.push(node_handle);
+    }
+
+    pub fn contains(&self, node_handle: &RenderNodeHandle) -> bool {
+        return self.nodes.contains_key(node_handle);
+    }
+
+    /// Removes the node and all of its descendants and returns their handles, the node's descendants in
+    /// depth-first pre-order followed by the node itself.
+    pub fn remove_subtree(&mut self, node_handle: &RenderNodeHandle) -> Result<Vec<RenderNodeHandle>, SceneGraphError> {
+        if !self.contains(node_handle) {
+            return Err(SceneGraphError::NodeNotFound(*node_handle));
+        }
+        self.detach(node_handle);
+
+        let mut removed_handles = self.descendants(node_handle);
+        removed_handles.push(*node_handle);
+        for handle in &removed_handles {
+            self.nodes.remove(handle);
+        }
+        return Ok(removed_handles);
+    }
+
+    /// Attaches [child] to [parent], or makes it a root node if [parent] is None.
+    /// If [keep_world_transform] is true, the local transform of [child] is adjusted such that its world transform
+    /// stays the same, otherwise the local transform is kept and the node moves along with its new parent.
+    pub fn set_parent(&mut self, child: &RenderNodeHandle, parent: Option<&RenderNodeHandle>, keep_world_transform: bool) -> Result<(), SceneGraphError> {
+        if !self.contains(child) {
+            return Err(SceneGraphError::NodeNotFound(*child));
+        }
+        if let Some(parent) = parent {
+            if !self.contains(parent) {
+                return Err(SceneGraphError::NodeNotFound(*parent));
+            }
+            // Walk up from the new parent. If we meet the child, the child would become its own ancestor.
+            let mut ancestor = Some(*parent);
+            while let Some(ancestor_handle) = ancestor {
+                if ancestor_handle == *child {
+                    return Err(SceneGraphError::CycleDetected { child: *child, parent: *parent });
+                }
+                ancestor = self.nodes[&ancestor_handle].parent;
+            }
+        }
+
+        let old_world_matrix = self.world_transform(child).unwrap();
+        self.detach(child);
+
+        // Attach to the new parent
+        {
+            match parent {
+                Some(parent) => {
+                    self.nodes.get_mut(parent).unwrap().children.push(*child);
+                }
+                None => {
+                    self.root_nodes.push(*child);
+                }
+            }
+            let child_node = self.nodes.get_mut(child).unwrap();
+            child_node.parent = parent.copied();
+            child_node.transform_dirty = true;
+        }
+
+        if keep_world_transform {
+            let parent_world_matrix = match parent {
+                Some(parent) => self.world_transform(parent).unwrap(),
+                None => Mat4::IDENTITY,
+            };
+            let local_matrix = parent_world_matrix.inverse() * old_world_matrix;
+            self.nodes.get_mut(child).unwrap().local_transform = Transform::from_matrix(&local_matrix);
+        }
+        return Ok(());
+    }
+
+    /// Removes the node from the children of its parent, or from the root nodes.
+    fn detach(&mut self, node_handle: &RenderNodeHandle) {
+        match self.nodes[node_handle].parent {
+            Some(parent) => {
+                self.nodes.get_mut(&parent).unwrap().children.retain(|handle| handle != node_handle);
+            }
+            None => {
+                self.root_nodes.retain(|handle| handle != node_handle);
+            }
+        }
+    }
+
+    pub fn parent(&self, node_handle: &RenderNodeHandle) -> Option<RenderNodeHandle> {
+        return self.nodes.get(node_handle).map(|node| node.parent).flatten();
+    }
+
+    /// Returns the direct children of the node in the order they were attached.
+    pub fn children(&self, node_handle: &RenderNodeHandle) -> &[RenderNodeHandle] {
+        return match self.nodes.get(node_handle) {
+            Some(node) => &node.children,
+            None => &[],
+        };
+    }
+
+    pub fn root_nodes(&self) -> &[RenderNodeHandle] {
+        return &self.root_nodes;
+    }
+
+    pub fn local_transform(&self, node_handle: &RenderNodeHandle) -> Option<&Transform> {
+        return self.nodes.get(node_handle).map(|node| &node.local_transform);
+    }
+
+    /// Returns false if [node_handle] is not part of the graph.
+    pub fn set_local_transform(&mut self, node_handle: &RenderNodeHandle, transform: Transform) -> bool {
+        let node = match self.nodes.get_mut(node_handle) {
+            Some(node) => node,
+            None => return false,
+        };
+        if node.local_transform != transform {
+            node.local_transform = transform;
+            node.transform_dirty = true;
+        }
+        return true;
+    }
+
+    /// Computes the world transform of the node from the local transforms of the node and all of its ancestors.
+    /// Unlike the cached world matrices, this is also correct before [Self::update_world_transforms()] was called.
+    pub fn world_transform(&self, node_handle: &RenderNodeHandle) -> Option<Mat4> {
+        let mut node = self.nodes.get(node_handle)?;
+        let mut world_matrix = node.local_transform.to_matrix();
+        while let Some(parent_handle) = node.parent {
+            node = &self.nodes[&parent_handle];
+            world_matrix = node.local_transform.to_matrix() * world_matrix;
+        }
+        return Some(world_matrix);
+    }
+
+    /// Visits [root] and all of its descendants in depth-first pre-order.
+    /// [visitor] receives the handle of each node and its depth relative to [root].
+    pub fn traverse_depth_first<F: FnMut(&RenderNodeHandle, usize)>(&self, root: &RenderNodeHandle, mut visitor: F) {
+        if !self.contains(root) {
+            return;
+        }
+        let mut stack = vec![(*root, 0)];
+        while let Some((handle, depth)) = stack.pop() {
+            visitor(&handle, depth);
+            // Push in reverse so that children are visited in the order they were attached
+            for child in self.nodes[&handle].children.iter().rev() {
+                stack.push((*child, depth + 1));
+            }
+        }
+    }
+
+    /// Returns all descendants of the node in depth-first pre-order, not including the node itself.
+    pub fn descendants(&self, node_handle: &RenderNodeHandle) -> Vec<RenderNodeHandle> {
+        let mut descendants = Vec::new();
+        self.traverse_depth_first(node_handle, |handle, depth| {
+            if depth > 0 {
+                descendants.push(*handle);
+            }
+        });
+        return descendants;
+    }
+
+    /// Recomputes the world matrices of all nodes whose local transform or whose ancestors' local transforms
+    /// have changed, and passes the new world matrices on to [on_changed].
+    pub fn update_world_transforms<F: FnMut(&RenderNodeHandle, &Mat4)>(&mut self, mut on_changed: F) {
+        let mut stack: Vec<(RenderNodeHandle, Mat4, bool)> = self.root_nodes.iter()
+            .rev()
+            .map(|handle| (*handle, Mat4::IDENTITY, false))
+            .collect();
+        while let Some((handle, parent_world_matrix, parent_changed)) = stack.pop() {
+            let graph_node = self.nodes.get_mut(&handle).unwrap();
+            let changed = parent_changed || graph_node.transform_dirty;
+            if changed {
+                graph_node.world_matrix = parent_world_matrix * graph_node.local_transform.to_matrix();
+                graph_node.transform_dirty = false;
+                on_changed(&handle, &graph_node.world_matrix);
+            }
+            let world_matrix = graph_node.world_matrix;
+            for child in graph_node.children.iter().rev() {
+                stack.push((*child, world_matrix, changed));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Quat, Vec3, Vec3A};
+    use crate::handle::HandleAllocator;
+    use super::*;
+
+    /// A graph of [count] root nodes.
+    fn graph_with_nodes(count: usize) -> (SceneGraph, Vec<RenderNodeHandle>) {
+        let mut allocator = HandleAllocator::new();
+        let mut graph = SceneGraph::new();
+        let handles: Vec<RenderNodeHandle> = (0..count).map(|_| allocator.allocate()).collect();
+        for handle in &handles {
+            graph.add_node(*handle);
+        }
+        return (graph, handles);
+    }
+
+    fn changed_nodes(graph: &mut SceneGraph) -> Vec<(RenderNodeHandle, Mat4)> {
+        let mut changed = Vec::new();
+        graph.update_world_transforms(|handle, world_matrix| changed.push((*handle, *world_matrix)));
+        return changed;
+    }
+
+    #[test]
+    fn rejects_cycles() {
+        let (mut graph, nodes) = graph_with_nodes(3);
+        graph.set_parent(&nodes[1], Some(&nodes[0]), false).unwrap();
+        graph.set_parent(&nodes[2], Some(&nodes[1]), false).unwrap();
+
+        assert_eq!(graph.set_parent(&nodes[0], Some(&nodes[2]), false), Err(SceneGraphError::CycleDetected { child: nodes[0], parent: nodes[2] }));
+        assert_eq!(graph.set_parent(&nodes[0], Some(&nodes[0]), false), Err(SceneGraphError::CycleDetected { child: nodes[0], parent: nodes[0] }));
+        // The graph is unchanged
+        assert_eq!(graph.root_nodes(), &[nodes[0]]);
+        assert_eq!(graph.parent(&nodes[2]), Some(nodes[1]));
+    }
+
+    #[test]
+    fn reparents_nodes() {
+        let (mut graph, nodes) = graph_with_nodes(3);
+        graph.set_parent(&nodes[1], Some(&nodes[0]), false).unwrap();
+        graph.set_parent(&nodes[2], Some(&nodes[0]), false).unwrap();
+        assert_eq!(graph.children(&nodes[0]), &[nodes[1], nodes[2]]);
+        assert_eq!(graph.root_nodes(), &[nodes[0]]);
+
+        graph.set_parent(&nodes[1], Some(&nodes[2]), false).unwrap();
+        assert_eq!(graph.children(&nodes[0]), &[nodes[2]]);
+        assert_eq!(graph.descendants(&nodes[0]), vec![nodes[2], nodes[1]]);
+
+        graph.set_parent(&nodes[2], None, false).unwrap();
+        assert_eq!(graph.root_nodes(), &[nodes[0], nodes[2]]);
+        assert!(graph.children(&nodes[0]).is_empty());
+    }
+
+    #[test]
+    fn keeps_world_transform_when_requested() {
+        let (mut graph, nodes) = graph_with_nodes(3);
+        let parent_transform = Transform::new(Vec3A::new(1.0, 2.0, 3.0), Quat::from_rotation_y(1.0), Vec3A::splat(2.0));
+        graph.set_local_transform(&nodes[0], parent_transform);
+        let child_transform = Transform::from_translation(Vec3A::X);
+        graph.set_local_transform(&nodes[1], child_transform);
+        graph.set_local_transform(&nodes[2], child_transform);
+
+        graph.set_parent(&nodes[1], Some(&nodes[0]), true).unwrap();
+        assert!(graph.world_transform(&nodes[1]).unwrap().abs_diff_eq(child_transform.to_matrix(), 1e-5));
+
+        // Otherwise the local transform is kept and the node moves with its parent
+        graph.set_parent(&nodes[2], Some(&nodes[0]), false).unwrap();
+        assert_eq!(graph.local_transform(&nodes[2]), Some(&child_transform));
+        let expected = parent_transform.to_matrix() * child_transform.to_matrix();
+        assert!(graph.world_transform(&nodes[2]).unwrap().abs_diff_eq(expected, 1e-5));
+    }
+
+    #[test]
+    fn propagates_dirty_transforms_to_descendants() {
+        let (mut graph, nodes) = graph_with_nodes(4);
+        graph.set_parent(&nodes[1], Some(&nodes[0]), false).unwrap();
+        graph.set_parent(&nodes[2], Some(&nodes[1]), false).unwrap();
+        changed_nodes(&mut graph);
+        // Nothing moved since
+        assert!(changed_nodes(&mut graph).is_empty());
+
+        graph.set_local_transform(&nodes[1], Transform::from_translation(Vec3A::Y));
+        graph.set_local_transform(&nodes[2], Transform::from_translation(Vec3A::X));
+        let changed = changed_nodes(&mut graph);
+        // The moved node and its descendants, each once, but neither its parent nor unrelated nodes
+        let changed_handles: Vec<RenderNodeHandle> = changed.iter().map(|(handle, _)| *handle).collect();
+        assert_eq!(changed_handles, vec![nodes[1], nodes[2]]);
+        assert_eq!(changed[1].1, Mat4::from_translation(Vec3::new(1.0, 1.0, 0.0)));
+
+        // Setting the same transform again does not mark the node dirty
+        graph.set_local_transform(&nodes[1], Transform::from_translation(Vec3A::Y));
+        assert!(changed_nodes(&mut graph).is_empty());
+    }
+
+    #[test]
+    fn removes_subtrees() {
+        let (mut graph, nodes) = graph_with_nodes(4);
+        graph.set_parent(&nodes[1], Some(&nodes[0]), false).unwrap();
+        graph.set_parent(&nodes[2], Some(&nodes[1]), false).unwrap();
+
+        assert_eq!(graph.remove_subtree(&nodes[1]), Ok(vec![nodes[2], nodes[1]]));
+        assert!(!graph.contains(&nodes[1]) && !graph.contains(&nodes[2]));
+        assert!(graph.children(&nodes[0]).is_empty());
+        assert_eq!(graph.remove_subtree(&nodes[1]), Err(SceneGraphError::NodeNotFound(nodes[1])));
+        assert!(!graph.set_local_transform(&nodes[2], Transform::IDENTITY));
+        assert_eq!(graph.root_nodes(), &[nodes[0], nodes[3]]);
+    }
+}
@@ -0,0 +1,415 @@
+use std::collections::HashMap;
+use glam::{Mat4, Vec3, Vec3A};
+use wgpu::util::DeviceExt;
+use wgpu::{CommandEncoder, Device, Queue};
+use crate::camera::CameraRenderNode;
+use crate::light::{LightKind, LightRenderNode};
+use crate::mesh::MeshVertex;
+use crate::scene::{RenderCallState, RenderNode, RenderNodeHandle, MODEL_BIND_GROUP};
+
+/// Width and height of every shadow map.
+pub const SHADOW_MAP_SIZE: u32 = 1024;
+/// The number of layers of the shadow map array, shared by all shadow casting lights of a frame.
+pub const MAX_SHADOW_MAPS: usize = 8;
+/// The number of cascades (and thus shadow maps) of the shadow casting directional light.
+pub const SHADOW_CASCADE_COUNT: usize = 4;
+/// How far from the camera directional light shadows are rendered. Also used for cameras with an infinite far plane.
+pub const DIRECTIONAL_SHADOW_DISTANCE: f32 = 100.0;
+/// Blend factor between the logarithmic (1.0) and uniform (0.0) cascade split scheme.
+const CASCADE_SPLIT_LAMBDA: f32 = 0.75;
+/// How far beyond the fitted cascade towards the light shadow casters are still rendered.
+const DIRECTIONAL_SHADOW_CASTER_EXTENSION: f32 = 50.0;
+
+const SHADOW_MAP_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// Computes the far distances of the cascades of a view frustum from [near] to [far] using the practical split
+/// scheme, which blends the logarithmic and the uniform split scheme by [lambda].
+/// The first cascade starts at [near]; the last distance is always [far].
+/// The logarithmic scheme is undefined for a [near] of zero or less, eg. of orthographic cameras, which thus
+/// use the uniform split scheme.
+pub fn compute_cascade_splits(near: f32, far: f32, cascade_count: usize, lambda: f32) -> Vec<f32> {
+    return (1..=cascade_count)
+        .map(|cascade| {
+            let fraction = cascade as f32 / cascade_count as f32;
+            let uniform = near + (far - near) * fraction;
+            if near <= 0.0 {
+                return uniform;
+            }
+            let logarithmic = near * (far / near).powf(fraction);
+            lambda * logarithmic + (1.0 - lambda) * uniform
+        })
+        .collect();
+}
+
+/// Returns the world space corners of the slice between [near] and [far] of a perspective view frustum.
+/// The four corners of the near plane come first.
+pub fn frustum_slice_corners(position: Vec3A, direction: Vec3A, up: Vec3A, fov: f32, aspect: f32, near: f32, far: f32) -> [Vec3A; 8] {
+    let right = up.cross(direction);
+    let tan_half_fov = (fov * 0.5).tan();
+    let mut corners = [Vec3A::ZERO; 8];
+    for (plane, distance) in [near, far].iter().enumerate() {
+        let center = position + direction * *distance;
+        let half_height = up * (tan_half_fov * distance);
+        let half_width = right * (tan_half_fov * distance * aspect);
+        corners[plane * 4] = center - half_width - half_height;
+        corners[plane * 4 + 1] = center + half_width - half_height;
+        corners[plane * 4 + 2] = center + half_width + half_height;
+        corners[plane * 4 + 3] = center - half_width + half_height;
+    }
+    return corners;
+}
+
+/// Fits the light space view projection matrix of a directional light shining along [light_direction]
+/// around [corners]. The orthographic projection bounds the sphere around the corners and is snapped to
+/// shadow map texels, so that shadows do not shimmer when the camera moves or rotates.
+/// The depth range extends [caster_extension] further towards the light to include occluders outside of the corners.
+pub fn fit_directional_light_view_proj(corners: &[Vec3A; 8], light_direction: Vec3A, shadow_map_size: u32, caster_extension: f32) -> Mat4 {
+    let center = corners.iter().fold(Vec3A::ZERO, |sum, corner| sum + *corner) / corners.len() as f32;
+    let radius = corners.iter()
+        .map(|corner| corner.distance(center))
+        .fold(0.0_f32, f32::max);
+    // Quantize the radius, the extent of the projection must not change with every rotation of the camera
+    let radius = (radius * 16.0).ceil() / 16.0;
+
+    let direction = Vec3::from(light_direction.normalize());
+    let up = if direction.y.abs() > 0.99 { Vec3::Z } else { Vec3::Y };
+    let light_view = Mat4::look_at_lh(Vec3::ZERO, direction, up);
+
+    let texel_size = 2.0 * radius / shadow_map_size as f32;
+    let mut light_space_center = light_view.transform_point3(Vec3::from(center));
+    light_space_center.x = (light_space_center.x / texel_size).floor() * texel_size;
+    light_space_center.y = (light_space_center.y / texel_size).floor() * texel_size;
+
+    let projection = Mat4::orthographic_lh(
+        light_space_center.x - radius, light_space_center.x + radius,
+        light_space_center.y - radius, light_space_center.y + radius,
+        light_space_center.z - radius - caster_extension, light_space_center.z + radius,
+    );
+    return projection * light_view;
+}
+
+/// The light space view projection matrix of a spot light at [position] shining along [direction].
+pub fn spot_light_view_proj(position: Vec3A, direction: Vec3A, outer_cone_angle: f32, range: f32) -> Mat4 {
+    let direction = Vec3::from(direction.normalize());
+    let up = if direction.y.abs() > 0.99 { Vec3::Z } else { Vec3::Y };
+    let light_view = Mat4::look_at_lh(Vec3::from(position), Vec3::from(position) + direction, up);
+    let fov = (outer_cone_angle * 2.0).min(std::f32::consts::PI * 0.99);
+    let projection = Mat4::perspective_lh(fov, 1.0, range * 0.01, range);
+    return projection * light_view;
+}
+
+/// The shadow map layers assigned to the lights of a frame.
+pub(crate) struct ShadowMapAllocation {
+    /// The light space view projection matrix of every used shadow map layer.
+    pub(crate) view_projs: Vec<Mat4>,
+    /// Per light, the first layer and the number of consecutive layers it uses. None if it casts no shadows.
+    pub(crate) light_layers: Vec<Option<(usize, usize)>>,
+}
+
+/// Assigns shadow map layers to the shadow casting lights of [lights], as seen from [camera].
+/// The first shadow casting directional light receives [SHADOW_CASCADE_COUNT] cascades, spot lights one layer each,
+/// in order of [lights] until all [MAX_SHADOW_MAPS] layers are used. Point lights do not cast shadows.
+pub(crate) fn allocate_shadow_maps(lights: &[&LightRenderNode], camera: &CameraRenderNode) -> ShadowMapAllocation {
+    let mut view_projs = Vec::new();
+    let mut light_layers = Vec::new();
+    let mut has_cascades = false;
+    for light in lights {
+        let mut layers = None;
+        if light.light().casts_shadows {
+            match light.light().kind {
+                LightKind::Directional => {
+                    if !has_cascades && view_projs.len() + SHADOW_CASCADE_COUNT <= MAX_SHADOW_MAPS {
+                        let shadow_distance = camera.far().unwrap_or(DIRECTIONAL_SHADOW_DISTANCE).min(DIRECTIONAL_SHADOW_DISTANCE);
+                        let splits = compute_cascade_splits(camera.near(), shadow_distance, SHADOW_CASCADE_COUNT, CASCADE_SPLIT_LAMBDA);
+                        layers = Some((view_projs.len(), SHADOW_CASCADE_COUNT));
+                        let mut cascade_near = camera.near();
+                        for cascade_far in splits {
+                            let corners = camera.camera().frustum_slice_corners(cascade_near, cascade_far);
+                            view_projs.push(fit_directional_light_view_proj(&corners, light.direction(), SHADOW_MAP_SIZE, DIRECTIONAL_SHADOW_CASTER_EXTENSION));
+                            cascade_near = cascade_far;
+                        }
+                        has_cascades = true;
+                    }
+                }
+                LightKind::Spot { range, outer_cone_angle, .. } => {
+                    if view_projs.len() < MAX_SHADOW_MAPS {
+                        layers = Some((view_projs.len(), 1));
+                        view_projs.push(spot_light_view_proj(light.position(), light.direction(), outer_cone_angle, range));
+                    }
+                }
+                LightKind::Point { .. } => {}
+            }
+        }
+        light_layers.push(layers);
+    }
+    return ShadowMapAllocation { view_projs, light_layers };
+}
+
+/// The shadow map array and the depth-only pipeline shadow maps are rendered with.
+pub struct ShadowRenderState {
+    texture: wgpu::Texture,
+    array_view: wgpu::TextureView,
+    layer_views: Vec<wgpu::TextureView>,
+    sampler: wgpu::Sampler,
+    pipeline: wgpu::RenderPipeline,
+    /// One light space camera per shadow map layer, bound in place of the camera bind group.
+    light_camera_buffers: Vec<wgpu::Buffer>,
+    light_camera_bind_groups: Vec<wgpu::BindGroup>,
+}
+
+impl ShadowRenderState {
+    pub(crate) fn new(device: &Device, camera_bind_group_layout: &wgpu::BindGroupLayout, model_bind_group_layout: &wgpu::BindGroupLayout) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("ShadowMaps"),
+            size: wgpu::Extent3d {
+                width: SHADOW_MAP_SIZE,
+                height: SHADOW_MAP_SIZE,
+                depth_or_array_layers: MAX_SHADOW_MAPS as u32,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: SHADOW_MAP_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+        let array_view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("ShadowMapArrayView"),
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let layer_views = (0..MAX_SHADOW_MAPS as u32)
+            .map(|layer| texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("ShadowMapLayerView"),
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_array_layer: layer,
+                array_layer_count: std::num::NonZeroU32::new(1),
+                ..Default::default()
+            }))
+            .collect();
+        // Linear filtering of a comparison sampler averages the results of the 2x2 depth comparisons (hardware PCF)
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("ShadowMapSampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        let mut light_camera_buffers = Vec::new();
+        let mut light_camera_bind_groups = Vec::new();
+        for _ in 0..MAX_SHADOW_MAPS {
+            let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("LightCameraBuffer"),
+                contents: bytemuck::cast_slice(&Mat4::IDENTITY.to_cols_array()),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+            light_camera_bind_groups.push(device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: camera_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    }
+                ],
+                label: Some("light_camera_bind_group"),
+            }));
+            light_camera_buffers.push(buffer);
+        }
+
+        let pipeline = Self::create_pipeline(device, camera_bind_group_layout, model_bind_group_layout);
+        return ShadowRenderState {
+            texture,
+            array_view,
+            layer_views,
+            sampler,
+            pipeline,
+            light_camera_buffers,
+            light_camera_bind_groups,
+        };
+    }
+
+    fn create_pipeline(device: &Device, camera_bind_group_layout: &wgpu::BindGroupLayout, model_bind_group_layout: &wgpu::BindGroupLayout) -> wgpu::RenderPipeline {
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("ShadowShader"),
+            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(include_str!("../../cres/shaders/shadow.wgsl"))),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("ShadowPipelineLayout"),
+            bind_group_layouts: &[camera_bind_group_layout, model_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        return device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("ShadowPipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[MeshVertex::buffer_layout()],
+            },
+            // Depth only
+            fragment: None,
+            primitive: wgpu::PrimitiveState {
+                // Double sided materials cast shadows from both sides
+                cull_mode: None,
+                ..wgpu::PrimitiveState::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: SHADOW_MAP_FORMAT,
+                depth_write_enabled: true,
+                // Shadow maps use a regular depth range: the light's near plane maps to 0.0
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                // Avoids shadow acne from the limited depth resolution of the shadow map
+                bias: wgpu::DepthBiasState {
+                    constant: 2,
+                    slope_scale: 2.0,
+                    clamp: 0.0,
+                },
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+    }
+
+    /// Renders one shadow map per matrix of [allocation]. [shadow_casters] holds the handles of the [nodes]
+    /// to render into each shadow map.
+    #[profiling::function]
+    pub(crate) fn render_shadow_maps(&self, queue: &Queue, command_encoder: &mut CommandEncoder, allocation: &ShadowMapAllocation, shadow_casters: &[Vec<RenderNodeHandle>], nodes: &HashMap<RenderNodeHandle, Box<dyn RenderNode>>) {
+        for (layer, view_proj) in allocation.view_projs.iter().enumerate() {
+            queue.write_buffer(&self.light_camera_buffers[layer], 0, bytemuck::cast_slice(&view_proj.to_cols_array()));
+
+            let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("ShadowRenderPass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.layer_views[layer],
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(0, &self.light_camera_bind_groups[layer], &[]);
+            let mut render_call_state = RenderCallState { render_pass: &mut render_pass };
+            for node_handle in &shadow_casters[layer] {
+                nodes[node_handle].render_shadow(&mut render_call_state);
+            }
+        }
+    }
+
+    pub fn array_view(&self) -> &wgpu::TextureView {
+        return &self.array_view;
+    }
+
+    pub fn sampler(&self) -> &wgpu::Sampler {
+        return &self.sampler;
+    }
+}
+
+impl Drop for ShadowRenderState {
+    fn drop(&mut self) {
+        self.texture.destroy();
+        for buffer in &self.light_camera_buffers {
+            buffer.destroy();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec3A, Vec4Swizzles};
+    use super::*;
+
+    fn assert_inside_ndc(view_proj: &Mat4, point: Vec3A) {
+        let clip = *view_proj * point.extend(1.0);
+        let ndc = clip.xyz() / clip.w;
+        assert!(ndc.x.abs() <= 1.0 + 1e-4 && ndc.y.abs() <= 1.0 + 1e-4, "{} is outside of the projection ({:?})", point, ndc);
+        assert!(ndc.z >= -1e-4 && ndc.z <= 1.0 + 1e-4, "{} is outside of the depth range ({})", point, ndc.z);
+    }
+
+    #[test]
+    fn cascade_splits_are_increasing_and_end_at_far() {
+        let splits = compute_cascade_splits(0.1, 100.0, 4, 0.75);
+        assert_eq!(splits.len(), 4);
+        assert!(splits[0] > 0.1);
+        for pair in splits.windows(2) {
+            assert!(pair[0] < pair[1]);
+        }
+        assert!((splits[3] - 100.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn cascade_splits_blend_uniform_and_logarithmic() {
+        let uniform = compute_cascade_splits(1.0, 16.0, 4, 0.0);
+        let logarithmic = compute_cascade_splits(1.0, 16.0, 4, 1.0);
+        for (split, expected) in uniform.iter().zip([4.75, 8.5, 12.25, 16.0]) {
+            assert!((split - expected).abs() < 1e-4);
+        }
+        for (split, expected) in logarithmic.iter().zip([2.0, 4.0, 8.0, 16.0]) {
+            assert!((split - expected).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn cascade_splits_are_uniform_without_positive_near() {
+        for near in [0.0, -10.0] {
+            let splits = compute_cascade_splits(near, 100.0, 4, 0.75);
+            for (cascade, split) in splits.iter().enumerate() {
+                let expected = near + (100.0 - near) * (cascade + 1) as f32 / 4.0;
+                assert!((split - expected).abs() < 1e-4, "Split {} is {} instead of {}", cascade, split, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn frustum_slice_corners_lie_on_the_slice_planes() {
+        let corners = frustum_slice_corners(Vec3A::ZERO, Vec3A::Z, Vec3A::Y, 90.0_f32.to_radians(), 2.0, 1.0, 10.0);
+        for corner in &corners[0..4] {
+            assert!((corner.z - 1.0).abs() < 1e-5);
+            assert!((corner.y.abs() - 1.0).abs() < 1e-5);
+            assert!((corner.x.abs() - 2.0).abs() < 1e-5);
+        }
+        for corner in &corners[4..8] {
+            assert!((corner.z - 10.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn directional_light_projection_contains_the_cascade() {
+        let corners = frustum_slice_corners(Vec3A::new(3.0, 2.0, -5.0), Vec3A::new(0.0, 0.0, 1.0), Vec3A::Y, 70.0_f32.to_radians(), 16.0 / 9.0, 0.1, 20.0);
+        for light_direction in [Vec3A::new(-0.3, -1.0, 0.5), Vec3A::new(0.0, -1.0, 0.0), Vec3A::new(1.0, 0.0, 0.0)] {
+            let view_proj = fit_directional_light_view_proj(&corners, light_direction, 1024, 50.0);
+            for corner in &corners {
+                assert_inside_ndc(&view_proj, *corner);
+            }
+        }
+    }
+
+    #[test]
+    fn directional_light_projection_is_stable_under_camera_rotation() {
+        // The bounding sphere, and thus the size of a shadow map texel, does not depend on the camera direction
+        let light_direction = Vec3A::new(-0.3, -1.0, 0.5);
+        let corners = frustum_slice_corners(Vec3A::ZERO, Vec3A::Z, Vec3A::Y, 1.0, 1.5, 0.1, 20.0);
+        let rotated_corners = frustum_slice_corners(Vec3A::ZERO, Vec3A::X, Vec3A::Y, 1.0, 1.5, 0.1, 20.0);
+        let scale = fit_directional_light_view_proj(&corners, light_direction, 1024, 50.0).x_axis.length();
+        let rotated_scale = fit_directional_light_view_proj(&rotated_corners, light_direction, 1024, 50.0).x_axis.length();
+        assert!((scale - rotated_scale).abs() < 1e-6);
+    }
+
+    #[test]
+    fn spot_light_projection_centers_its_direction() {
+        let position = Vec3A::new(1.0, 4.0, 2.0);
+        let direction = Vec3A::new(0.0, -1.0, 1.0).normalize();
+        let view_proj = spot_light_view_proj(position, direction, 0.5, 10.0);
+        let clip = view_proj * (position + direction * 5.0).extend(1.0);
+        let ndc = clip.xyz() / clip.w;
+        assert!(ndc.x.abs() < 1e-4 && ndc.y.abs() < 1e-4);
+        assert!(ndc.z > 0.0 && ndc.z < 1.0);
+    }
+}
@@ -0,0 +1,142 @@
+use std::time::Instant;
+use crate::camera::CameraRenderNode;
+use crate::light::{Light, LightRenderNode};
+use crate::scene::{RenderNodeHandle, RenderScene};
+use crate::transform::Transform;
+
+/// The render-relevant state of an entity, in addition to its transform.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EntityRenderState {
+    /// Only the transform of the render node is updated.
+    Transform,
+    /// [fov] is the vertical field of view of perspective cameras, None for orthographic cameras.
+    /// unit: radians
+    Camera { fov: Option<f32> },
+    Light(Light),
+}
+
+/// The state of an entity's render node after the last two ticks.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EntitySnapshot {
+    pub render_node: RenderNodeHandle,
+    /// The transform before the last tick.
+    pub previous_transform: Transform,
+    /// The transform after the last tick.
+    pub transform: Transform,
+    pub state: EntityRenderState,
+}
+
+impl EntitySnapshot {
+    /// The transform [alpha] of the way from [Self::previous_transform] to [Self::transform].
+    pub fn interpolated_transform(&self, alpha: f32) -> Transform {
+        return Transform::new(
+            self.previous_transform.translation.lerp(self.transform.translation, alpha),
+            self.previous_transform.rotation.slerp(self.transform.rotation, alpha),
+            self.previous_transform.scale.lerp(self.transform.scale, alpha),
+        );
+    }
+}
+
+/// An immutable copy of the render-relevant state of all entities, taken by the simulation after a tick and
+/// applied to the [RenderScene] by the render thread.
+#[derive(Clone, Debug)]
+pub struct RenderSnapshot {
+    /// The number of ticks simulated before the snapshot was taken.
+    pub tick: u64,
+    /// unit: seconds
+    pub tick_duration: f64,
+    /// How far the simulation was into the next tick when the snapshot was taken.
+    pub alpha: f32,
+    pub taken_at: Instant,
+    pub entities: Vec<EntitySnapshot>,
+}
+
+impl RenderSnapshot {
+    /// How far the simulation is into the tick after [Self::tick] at [now], clamped to 1 as the following
+    /// tick is not known yet.
+    pub fn alpha_at(&self, now: Instant) -> f32 {
+        let elapsed = now.saturating_duration_since(self.taken_at).as_secs_f64();
+        return (self.alpha + (elapsed / self.tick_duration) as f32).clamp(0.0, 1.0);
+    }
+
+    /// Updates the render nodes of all entities in [render_scene], interpolating their transforms by [alpha].
+    /// Render nodes removed since the snapshot was taken are skipped.
+    pub fn apply(&self, alpha: f32, render_scene: &mut RenderScene) {
+        for entity in &self.entities {
+            if !render_scene.is_alive(&entity.render_node) {
+                continue;
+            }
+            render_scene.set_local_transform(&entity.render_node, entity.interpolated_transform(alpha));
+            match entity.state {
+                EntityRenderState::Transform => {}
+                EntityRenderState::Camera { fov } => {
+                    if let Some(fov) = fov {
+                        let camera_render_node: &mut CameraRenderNode = render_scene.get_node_by_id(&entity.render_node).unwrap();
+                        camera_render_node.set_fov(fov);
+                    }
+                }
+                EntityRenderState::Light(light) => {
+                    let light_render_node: &mut LightRenderNode = render_scene.get_node_by_id(&entity.render_node).unwrap();
+                    light_render_node.set_light(light);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+    use std::time::Duration;
+    use glam::{Quat, Vec3A};
+    use crate::handle::GenerationalHandle;
+    use super::*;
+
+    fn snapshot(alpha: f32) -> RenderSnapshot {
+        return RenderSnapshot {
+            tick: 1,
+            tick_duration: 0.1,
+            alpha,
+            taken_at: Instant::now(),
+            entities: Vec::new(),
+        };
+    }
+
+    #[test]
+    fn interpolates_between_ticks() {
+        let entity = EntitySnapshot {
+            render_node: GenerationalHandle::from_bits(0),
+            previous_transform: Transform::IDENTITY,
+            transform: Transform::new(Vec3A::new(2.0, 0.0, 0.0), Quat::from_rotation_y(FRAC_PI_2), Vec3A::splat(3.0)),
+            state: EntityRenderState::Transform,
+        };
+
+        let is_close = |actual: Transform, expected: Transform| {
+            return actual.translation.abs_diff_eq(expected.translation, 1e-6)
+                && actual.rotation.abs_diff_eq(expected.rotation, 1e-6)
+                && actual.scale.abs_diff_eq(expected.scale, 1e-6);
+        };
+        assert!(is_close(entity.interpolated_transform(0.0), entity.previous_transform));
+        assert!(is_close(entity.interpolated_transform(1.0), entity.transform));
+        let halfway = Transform::new(Vec3A::new(1.0, 0.0, 0.0), Quat::from_rotation_y(FRAC_PI_2 / 2.0), Vec3A::splat(2.0));
+        assert!(is_close(entity.interpolated_transform(0.5), halfway));
+    }
+
+    #[test]
+    fn alpha_advances_with_elapsed_time() {
+        let snapshot = snapshot(0.25);
+
+        assert_eq!(snapshot.alpha_at(snapshot.taken_at), 0.25);
+        assert!((snapshot.alpha_at(snapshot.taken_at + Duration::from_millis(50)) - 0.75).abs() < 1e-6);
+        // Times before the snapshot was taken do not go back to the previous tick
+        assert_eq!(snapshot.alpha_at(snapshot.taken_at - Duration::from_millis(50)), 0.25);
+    }
+
+    #[test]
+    fn alpha_is_clamped_after_the_next_tick() {
+        let snapshot = snapshot(0.75);
+
+        assert_eq!(snapshot.alpha_at(snapshot.taken_at + Duration::from_millis(50)), 1.0);
+        assert_eq!(snapshot.alpha_at(snapshot.taken_at + Duration::from_secs(10)), 1.0);
+    }
+}
@@ -0,0 +1,130 @@
+/// The default number of simulation ticks per second.
+pub const DEFAULT_TICK_RATE: f64 = 60.0;
+
+/// The default maximum number of ticks run for a single frame.
+pub const DEFAULT_MAX_TICKS_PER_UPDATE: u32 = 5;
+
+/// Divides the variable time between rendered frames into simulation ticks of a fixed duration.
+/// Time that does not add up to a full tick is carried over to the next frame; how far the simulation is into
+/// the next tick is given by [FixedTimestep::alpha()], which is used to interpolate between the last two ticks.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FixedTimestep {
+    tick_duration: f64,
+    max_ticks_per_update: u32,
+    accumulator: f64,
+}
+
+impl FixedTimestep {
+    /// [tick_rate] is the number of ticks per second.
+    /// [max_ticks_per_update] limits the number of ticks run for a single frame, see [FixedTimestep::advance()].
+    /// Panics if [tick_rate] is not a positive finite number.
+    pub fn new(tick_rate: f64, max_ticks_per_update: u32) -> Self {
+        return FixedTimestep {
+            tick_duration: tick_duration_of(tick_rate),
+            max_ticks_per_update: max_ticks_per_update.max(1),
+            accumulator: 0.0,
+        };
+    }
+
+    /// The number of ticks per second.
+    pub fn tick_rate(&self) -> f64 {
+        return 1.0 / self.tick_duration;
+    }
+
+    /// Panics if [tick_rate] is not a positive finite number.
+    pub fn set_tick_rate(&mut self, tick_rate: f64) {
+        self.tick_duration = tick_duration_of(tick_rate);
+    }
+
+    /// The simulated time per tick.
+    /// unit: seconds
+    pub fn tick_duration(&self) -> f64 {
+        return self.tick_duration;
+    }
+
+    pub fn max_ticks_per_update(&self) -> u32 {
+        return self.max_ticks_per_update;
+    }
+
+    pub fn set_max_ticks_per_update(&mut self, max_ticks_per_update: u32) {
+        self.max_ticks_per_update = max_ticks_per_update.max(1);
+    }
+
+    /// Adds [delta_time] seconds of real time and returns the number of ticks to run.
+    /// If the time adds up to more than [FixedTimestep::max_ticks_per_update()] ticks, the excess ticks are
+    /// dropped. The simulation then runs slower than real time, instead of falling further behind with every
+    /// frame when ticks take longer to run than they simulate.
+    pub fn advance(&mut self, delta_time: f64) -> u32 {
+        self.accumulator += delta_time.max(0.0);
+        let ticks = (self.accumulator / self.tick_duration).floor();
+        if ticks > self.max_ticks_per_update as f64 {
+            self.accumulator %= self.tick_duration;
+            return self.max_ticks_per_update;
+        }
+        self.accumulator -= ticks * self.tick_duration;
+        return ticks as u32;
+    }
+
+    /// How far the simulation is into the next tick, in [0, 1).
+    pub fn alpha(&self) -> f32 {
+        return ((self.accumulator / self.tick_duration) as f32).clamp(0.0, 1.0);
+    }
+}
+
+/// The duration of a tick at [tick_rate] ticks per second.
+fn tick_duration_of(tick_rate: f64) -> f64 {
+    // Any other rate would make the timestep never tick or tick without bound
+    assert!(tick_rate.is_finite() && tick_rate > 0.0, "Tick rate must be positive and finite, got {}", tick_rate);
+    return 1.0 / tick_rate;
+}
+
+impl Default for FixedTimestep {
+    fn default() -> Self {
+        return FixedTimestep::new(DEFAULT_TICK_RATE, DEFAULT_MAX_TICKS_PER_UPDATE);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn carries_partial_ticks_over() {
+        let mut timestep = FixedTimestep::new(64.0, 5);
+        assert_eq!(timestep.advance(1.0 / 128.0), 0);
+        assert_eq!(timestep.alpha(), 0.5);
+        assert_eq!(timestep.advance(1.0 / 128.0), 1);
+        assert_eq!(timestep.alpha(), 0.0);
+        assert_eq!(timestep.advance(3.0 / 64.0), 3);
+    }
+
+    #[test]
+    fn drops_ticks_beyond_the_limit() {
+        let mut timestep = FixedTimestep::new(64.0, 4);
+        assert_eq!(timestep.advance(10.0 / 64.0 + 1.0 / 256.0), 4);
+        // Only the partial tick is kept
+        assert_eq!(timestep.alpha(), 0.25);
+        assert_eq!(timestep.advance(0.0), 0);
+    }
+
+    #[test]
+    fn changing_the_tick_rate() {
+        let mut timestep = FixedTimestep::default();
+        assert_eq!(timestep.tick_rate(), DEFAULT_TICK_RATE);
+        timestep.set_tick_rate(32.0);
+        assert_eq!(timestep.tick_duration(), 1.0 / 32.0);
+        assert_eq!(timestep.advance(1.0 / 16.0), 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_zero_tick_rate() {
+        FixedTimestep::new(0.0, 5);
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_negative_tick_rate() {
+        FixedTimestep::default().set_tick_rate(-60.0);
+    }
+}
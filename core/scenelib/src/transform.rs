@@ -0,0 +1,75 @@
+use glam::{Mat4, Quat, Vec3, Vec3A};
+
+/// A decomposed affine transform consisting of a translation, a rotation and a (non-uniform) scale.
+/// Applied in the order scale, rotation, translation.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vec3A,
+    pub rotation: Quat,
+    pub scale: Vec3A,
+}
+
+impl Transform {
+    pub const IDENTITY: Transform = Transform {
+        translation: Vec3A::ZERO,
+        rotation: Quat::IDENTITY,
+        scale: Vec3A::ONE,
+    };
+
+    pub fn new(translation: Vec3A, rotation: Quat, scale: Vec3A) -> Self {
+        return Transform { translation, rotation, scale };
+    }
+
+    pub fn from_translation(translation: Vec3A) -> Self {
+        return Transform { translation, ..Transform::IDENTITY };
+    }
+
+    pub fn from_translation_rotation(translation: Vec3A, rotation: Quat) -> Self {
+        return Transform { translation, rotation, ..Transform::IDENTITY };
+    }
+
+    /// Decomposes [matrix] into a transform.
+    /// Shear present in [matrix] is lost.
+    pub fn from_matrix(matrix: &Mat4) -> Self {
+        let (scale, rotation, translation) = matrix.to_scale_rotation_translation();
+        return Transform {
+            translation: Vec3A::from(translation),
+            rotation,
+            scale: Vec3A::from(scale),
+        };
+    }
+
+    pub fn to_matrix(&self) -> Mat4 {
+        return Mat4::from_scale_rotation_translation(Vec3::from(self.scale), self.rotation, Vec3::from(self.translation));
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        return Transform::IDENTITY;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::EulerRot;
+    use super::*;
+
+    #[test]
+    fn identity_is_identity_matrix() {
+        assert_eq!(Transform::IDENTITY.to_matrix(), Mat4::IDENTITY);
+    }
+
+    #[test]
+    fn round_trips_through_matrix() {
+        let transform = Transform::new(Vec3A::new(1.0, -2.0, 3.0), Quat::from_euler(EulerRot::YXZ, 0.3, -0.2, 1.1), Vec3A::new(1.0, 2.0, 0.5));
+        let round_tripped = Transform::from_matrix(&transform.to_matrix());
+        assert!(round_tripped.translation.abs_diff_eq(transform.translation, 1e-5));
+        assert!(round_tripped.scale.abs_diff_eq(transform.scale, 1e-5));
+        // q and -q are the same rotation
+        assert!(round_tripped.rotation.dot(transform.rotation).abs() > 1.0 - 1e-5);
+
+        let matrix = Mat4::from_scale_rotation_translation(Vec3::new(0.5, 3.0, 1.0), Quat::from_rotation_x(-0.7), Vec3::new(-4.0, 0.0, 2.0));
+        assert!(Transform::from_matrix(&matrix).to_matrix().abs_diff_eq(matrix, 1e-5));
+    }
+}
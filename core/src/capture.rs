@@ -0,0 +1,132 @@
+use std::fmt;
+use std::fmt::Formatter;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use wgpu::{Device, Queue};
+
+#[derive(Debug)]
+pub enum CaptureError {
+    /// The engine has not been started, there is nothing to capture.
+    NotStarted,
+    /// The viewport has no area.
+    EmptyViewport,
+    /// Reading the captured frame back from the GPU failed.
+    Readback(wgpu::BufferAsyncError),
+    /// The captured frame could not be encoded or written.
+    Save(image::ImageError),
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptureError::NotStarted => write!(f, "The engine has not been started"),
+            CaptureError::EmptyViewport => write!(f, "The viewport is empty"),
+            CaptureError::Readback(error) => write!(f, "Failed to read back frame: {}", error),
+            CaptureError::Save(error) => write!(f, "Failed to save frame: {}", error),
+        }
+    }
+}
+
+impl From<wgpu::BufferAsyncError> for CaptureError {
+    fn from(error: wgpu::BufferAsyncError) -> Self {
+        return CaptureError::Readback(error);
+    }
+}
+
+impl From<image::ImageError> for CaptureError {
+    fn from(error: image::ImageError) -> Self {
+        return CaptureError::Save(error);
+    }
+}
+
+/// A frame read back from the GPU.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameCapture {
+    pub width: u32,
+    pub height: u32,
+    /// Tightly packed RGBA8 pixels, row by row from the top.
+    pub pixels: Vec<u8>,
+}
+
+impl FrameCapture {
+    /// Writes the frame to a PNG file at [path].
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<(), CaptureError> {
+        image::save_buffer_with_format(path, &self.pixels, self.width, self.height, image::ColorType::Rgba8, image::ImageFormat::Png)?;
+        return Ok(());
+    }
+}
+
+/// A file name for a screenshot taken now, unique for screenshots taken at least a millisecond apart.
+pub fn screenshot_file_name() -> PathBuf {
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis())
+        .unwrap_or(0);
+    return PathBuf::from(format!("screenshot-{}.png", millis));
+}
+
+/// Copies the first mip level of [texture], which must have a 4 bytes per pixel format and
+/// [wgpu::TextureUsages::COPY_SRC], into tightly packed rows. Blocks until the copy has completed.
+/// BGRA pixels are reordered to RGBA.
+pub(crate) fn read_texture_rgba8(device: &Device, queue: &Queue, texture: &wgpu::Texture, format: wgpu::TextureFormat, width: u32, height: u32) -> Result<Vec<u8>, wgpu::BufferAsyncError> {
+    let unpadded_bytes_per_row = width * 4;
+    // Buffer copies require rows aligned to COPY_BYTES_PER_ROW_ALIGNMENT
+    let alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let padded_bytes_per_row = (unpadded_bytes_per_row + alignment - 1) / alignment * alignment;
+
+    let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("ReadbackBuffer"),
+        size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let mut command_encoder = device.create_command_encoder(
+        &wgpu::CommandEncoderDescriptor { label: Some("Readback") }
+    );
+    command_encoder.copy_texture_to_buffer(
+        wgpu::ImageCopyTexture {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        wgpu::ImageCopyBuffer {
+            buffer: &readback_buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(padded_bytes_per_row),
+                rows_per_image: None,
+            },
+        },
+        wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+    queue.submit(Some(command_encoder.finish()));
+
+    let mut pixels: Vec<u8>;
+    {
+        let buffer_slice = readback_buffer.slice(..);
+        let map_future = buffer_slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        pollster::block_on(map_future)?;
+
+        let padded_pixels = buffer_slice.get_mapped_range();
+        pixels = padded_pixels
+            .chunks(padded_bytes_per_row as usize)
+            .flat_map(|row| &row[..unpadded_bytes_per_row as usize])
+            .copied()
+            .collect();
+    }
+    readback_buffer.unmap();
+
+    if format == wgpu::TextureFormat::Bgra8Unorm || format == wgpu::TextureFormat::Bgra8UnormSrgb {
+        for pixel in pixels.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+        }
+    }
+    return Ok(pixels);
+}
@@ -0,0 +1,63 @@
+use wgpu::Device;
+
+/// The depth attachment of the main engine render pass.
+/// Uses reverse-Z: the near plane maps to depth 1.0 and the (possibly infinite) far plane to depth 0.0.
+/// Together with a floating point depth format this distributes depth precision evenly over the view distance.
+pub(crate) struct DepthBuffer {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    width: u32,
+    height: u32,
+    sample_count: u32,
+}
+
+impl DepthBuffer {
+    pub(crate) const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+    /// The depth value the buffer is cleared to every frame, which is the far plane when using reverse-Z.
+    pub(crate) const CLEAR_DEPTH: f32 = 0.0;
+
+    /// [sample_count] must match the sample count of the color attachment the depth buffer is used with.
+    pub(crate) fn new(device: &Device, width: u32, height: u32, sample_count: u32) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("DepthBuffer"),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        return DepthBuffer { texture, view, width, height, sample_count };
+    }
+
+    /// Recreates the depth texture if its size or sample count differ from the requested ones.
+    pub(crate) fn ensure_size(&mut self, device: &Device, width: u32, height: u32, sample_count: u32) {
+        if self.width == width && self.height == height && self.sample_count == sample_count {
+            return;
+        }
+        self.texture.destroy();
+        *self = DepthBuffer::new(device, width, height, sample_count);
+    }
+
+    pub(crate) fn view(&self) -> &wgpu::TextureView {
+        return &self.view;
+    }
+
+    /// The depth state of pipelines rendering into the depth buffer.
+    /// Closer fragments have greater depth values due to reverse-Z.
+    pub(crate) fn depth_stencil_state() -> wgpu::DepthStencilState {
+        return wgpu::DepthStencilState {
+            format: Self::FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Greater,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        };
+    }
+}
@@ -1,27 +1,37 @@
-use std::borrow::Cow;
-use std::cell::RefCell;
-use std::rc::Rc;
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
 use glam::{EulerRot, Quat, Vec3A};
-use wgpu::{ColorTargetState, MultisampleState, Queue, RenderBundle, RenderBundleDescriptor, RenderBundleEncoderDescriptor, SurfaceConfiguration};
+use wgpu::{MultisampleState, Queue, SurfaceConfiguration};
 use wgpu::{Color, CommandEncoder, Device};
 use winit::event::{DeviceId, ElementState, MouseButton, MouseScrollDelta, TouchPhase, VirtualKeyCode};
 use scenelib::camera::{CameraRenderNode};
-use scenelib::ecs::{CameraEntity, ECSEntityHandle, ECSWorld, MovementInput};
+use scenelib::culling::CullingStats;
+use scenelib::ecs::{CameraEntity, ECSEntityHandle, ECSWorld, LightEntity, MeshEntity, MovementInput};
+use scenelib::pipeline::RenderTargetState;
+use scenelib::prefab::PrefabLibrary;
 use scenelib::scene::{StaticRenderState, RenderScene, RenderCallState, RenderNodeHandle};
+use scenelib::scene_file::{SceneFile, SceneFileError};
+use scenelib::transform::Transform;
+use crate::capture::{read_texture_rgba8, CaptureError, FrameCapture};
+use crate::depth_buffer::DepthBuffer;
 use crate::input::{InputHandler};
+use crate::simulation::SimulationThread;
 
 pub struct EngineCoreState {
-    render_pipeline: wgpu::RenderPipeline,
-    triangle_render_bundle: RenderBundle,
-    render_scene: RenderScene,
-    pub ecs_world: ECSWorld,
+    depth_buffer: DepthBuffer,
+    pub render_scene: RenderScene,
+    /// Shared with the simulation thread, if it is running.
+    pub ecs_world: Arc<Mutex<ECSWorld>>,
+    /// The prefabs instantiated into [Self::ecs_world], see [PrefabLibrary::instantiate()].
+    pub prefab_library: PrefabLibrary,
     input_handler: InputHandler,
 }
 
 impl EngineCoreState {
-    pub(crate) fn get_render_node_handle_by_ecs_handle(&self, entity_handle: &ECSEntityHandle) -> Option<&RenderNodeHandle> {
-        return self.ecs_world.get_entity(entity_handle)
-            .map(|entity| entity.get_render_node())
+    pub(crate) fn get_render_node_handle_by_ecs_handle(&self, entity_handle: &ECSEntityHandle) -> Option<RenderNodeHandle> {
+        return self.ecs_world.lock().unwrap().get_entity(entity_handle)
+            .map(|entity| entity.get_render_node().copied())
             .flatten();
     }
 }
@@ -49,14 +59,15 @@ impl WindowState {
 }
 
 pub struct EngineInstance {
-    device: Rc<Device>,
-    queue: Rc<Queue>,
+    device: Arc<Device>,
+    queue: Arc<Queue>,
     pub window_state: WindowState,
-    surface_config: Rc<RefCell<SurfaceConfiguration>>,
-    color_target_state: ColorTargetState,
+    surface_config: Arc<RwLock<SurfaceConfiguration>>,
     pub multisample_state: MultisampleState,
     pub engine_core_state: Option<EngineCoreState>,
     movement_input: MovementInput,
+    /// None if the simulation runs on the render thread.
+    simulation_thread: Option<SimulationThread>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -77,18 +88,12 @@ impl ViewportRegion {
 }
 
 impl EngineInstance {
-    pub fn new(device: Rc<Device>, queue: Rc<Queue>, surface_config: Rc<RefCell<SurfaceConfiguration>>) -> EngineInstance {
-        let surface_format = surface_config.borrow().format;
+    pub fn new(device: Arc<Device>, queue: Arc<Queue>, surface_config: Arc<RwLock<SurfaceConfiguration>>) -> EngineInstance {
         EngineInstance {
             device,
             queue,
             window_state: WindowState::new(),
             surface_config,
-            color_target_state: ColorTargetState {
-                format: surface_format,
-                blend: None,
-                write_mask: Default::default(),
-            },
             multisample_state: MultisampleState {
                 count: 1,
                 mask: !0,
@@ -96,36 +101,21 @@ impl EngineInstance {
             },
             engine_core_state: None,
             movement_input: MovementInput::new(),
+            simulation_thread: None,
         }
     }
 
+    /// Starts the engine with the demo scene.
     #[profiling::function]
     pub fn start(&mut self) {
-        let triangle_render_bundle;
-        {
-            let mut triangle_render_bundle_encoder = self.device.create_render_bundle_encoder(&RenderBundleEncoderDescriptor {
-                label: Some("TriangleRenderBundleEncoder"),
-                color_formats: &[self.surface_config.borrow_mut().format],
-                depth_stencil: None,
-                sample_count: self.multisample_state.count,
-                multiview: None,
-            });
-            triangle_render_bundle_encoder.draw(0..3, 0..1);
-            triangle_render_bundle = triangle_render_bundle_encoder.finish(&RenderBundleDescriptor {
-                label: Some("TriangleRenderBundle"),
-            });
-        }
-
-        let mut ecs_world = ECSWorld::new();
-
-        let mut render_scene = RenderScene::new(StaticRenderState {
-            device: self.device.clone(),
-            queue: self.queue.clone(),
-            bind_group_layouts: Vec::new(),
-        });
+        self.start_empty();
+        let engine_state: &mut EngineCoreState = self.engine_core_state.as_mut().unwrap();
+        let mut ecs_world = engine_state.ecs_world.lock().unwrap();
+        let ecs_world = &mut *ecs_world;
+        let render_scene = &mut engine_state.render_scene;
 
         CameraEntity::add_flying(
-            &mut ecs_world, &mut render_scene,
+            ecs_world, render_scene,
             Vec3A::new(0.0, 0.0, -5.0),
             Vec3A::new(0.0, 0.0, 1.0),
             Vec3A::new(0.0, 0.0, 1.0),
@@ -136,56 +126,78 @@ impl EngineInstance {
             1.0,
         );
 
-        let shader = self.device.create_shader_module(&wgpu::ShaderModuleDescriptor {
-            label: None,
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("../cres/shaders/shader.frag.wgsl"))),
-        });
+        LightEntity::add_directional(
+            ecs_world, render_scene,
+            Vec3A::new(-0.3, -1.0, 0.5),
+            Vec3A::ONE,
+            3.0,
+            true,
+        );
+
+        // Not bundled via include_bytes!, the model is too large to embed in the binary.
+        if let Err(error) = MeshEntity::load_model(ecs_world, render_scene, concat!(env!("CARGO_MANIFEST_DIR"), "/cres/assets/bd1.glb"), Transform::IDENTITY) {
+            eprintln!("Failed to load demo model: {}", error);
+        }
+    }
 
-        let mut layouts = Vec::new();
+    /// Starts the engine with an empty scene, without any cameras or lights.
+    pub fn start_empty(&mut self) {
+        self.start_empty_with(ECSWorld::new());
+    }
 
-        for layout in &render_scene.static_render_state.bind_group_layouts {
-            layouts.push(layout);
+    /// Starts the engine with an empty scene simulated by [ecs_world], eg. one with custom systems built by
+    /// [ECSWorld::builder()].
+    #[profiling::function]
+    pub fn start_empty_with(&mut self, ecs_world: ECSWorld) {
+        let depth_buffer;
+        let render_target;
+        {
+            let surface_config = self.surface_config.read().unwrap();
+            depth_buffer = DepthBuffer::new(&self.device, surface_config.width, surface_config.height, self.multisample_state.count);
+            let depth_stencil_state = DepthBuffer::depth_stencil_state();
+            render_target = RenderTargetState {
+                color_format: surface_config.format,
+                depth_format: depth_stencil_state.format,
+                depth_compare: depth_stencil_state.depth_compare,
+                sample_count: self.multisample_state.count,
+            };
         }
 
-        let pipeline_layout = self.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: None,
-            bind_group_layouts: &layouts,
-            push_constant_ranges: &[],
-        });
+        let render_scene = RenderScene::new(StaticRenderState::new(self.device.clone(), self.queue.clone(), render_target));
 
-        let render_pipeline = self.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: None,
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[self.color_target_state.clone()],
-            }),
-            primitive: wgpu::PrimitiveState::default(),
-            depth_stencil: None,
-            multisample: self.multisample_state,
-            multiview: None,
-        });
-        self.engine_core_state = Some(EngineCoreState { render_pipeline, triangle_render_bundle, render_scene, ecs_world: ecs_world, input_handler: InputHandler::new() });
+        self.engine_core_state = Some(EngineCoreState { depth_buffer, render_scene, ecs_world: Arc::new(Mutex::new(ecs_world)), prefab_library: PrefabLibrary::new(), input_handler: InputHandler::new() });
+    }
+
+    /// Moves the ECS simulation to a thread of its own. Until then, the simulation runs on the render thread as
+    /// part of [Self::render()], which keeps it deterministic, eg. for tests.
+    /// The engine must have been started.
+    pub fn start_simulation_thread(&mut self) {
+        if self.simulation_thread.is_some() {
+            return;
+        }
+        let engine_state: &EngineCoreState = self.engine_core_state.as_ref().unwrap();
+        self.simulation_thread = Some(SimulationThread::spawn(engine_state.ecs_world.clone()));
+    }
+
+    /// The first camera entity, which is rendered from by default.
+    pub fn primary_camera(&self) -> Option<ECSEntityHandle> {
+        return self.engine_core_state.as_ref()
+            .map(|engine_state| engine_state.ecs_world.lock().unwrap().get_primary_camera())
+            .flatten();
     }
 
     /// Performs the pre-render phase of the engine.
-    /// This includes updating the ECS world.
+    /// This includes updating the ECS world, or applying the latest snapshot of the simulation thread.
     /// [render_camera_ecs_handle] is the handle of the camera ECS entity to use for the render.
     #[profiling::function]
     fn pre_render(&mut self, delta_time: f64, render_camera_ecs_handle: ECSEntityHandle) {
         let engine_state: &mut EngineCoreState = self.engine_core_state.as_mut().unwrap();
 
-        let render_camera_node_handle = engine_state.get_render_node_handle_by_ecs_handle(&render_camera_ecs_handle).unwrap().clone();
+        let render_camera_node_handle = engine_state.get_render_node_handle_by_ecs_handle(&render_camera_ecs_handle);
         let render_scene = &mut engine_state.render_scene;
 
-        // Mark render_camera as the active camera.
-        {
+        // Mark render_camera as the active camera. A removed camera leaves the active camera unchanged.
+        if let Some(render_camera_node_handle) = render_camera_node_handle {
             render_scene.set_active_camera(&render_camera_node_handle);
         }
 
@@ -204,12 +216,32 @@ impl EngineInstance {
         }
 
         // Pre-render phase
-        // TODO: MOVE OFF RENDER THREAD
-        {
-            engine_state.ecs_world.update(delta_time, self.movement_input.clone(), render_scene);
+        match &self.simulation_thread {
+            Some(simulation_thread) => {
+                simulation_thread.push_input(&self.movement_input);
+                self.movement_input.new_frame();
+                if let Some(snapshot) = simulation_thread.latest_snapshot() {
+                    snapshot.apply(snapshot.alpha_at(Instant::now()), render_scene);
+                }
+            }
+            None => {
+                let ticks = engine_state.ecs_world.lock().unwrap().update(delta_time, self.movement_input.clone(), render_scene);
+                // Mouse movement accumulates until a tick consumes it
+                if ticks > 0 {
+                    self.movement_input.new_frame();
+                }
+            }
         }
+    }
 
-        self.movement_input.new_frame();
+    /// Updates the ECS world by [delta_time] seconds without rendering, eg. when running without a window.
+    /// [render_camera_handle] is the handle of the camera ECS entity that the next frame is rendered from.
+    #[profiling::function]
+    pub fn update(&mut self, delta_time: f64, render_camera_handle: ECSEntityHandle) {
+        if self.engine_core_state.is_none() {
+            return;
+        }
+        self.pre_render(delta_time, render_camera_handle);
     }
 
     #[profiling::function]
@@ -218,35 +250,151 @@ impl EngineInstance {
             return;
         }
 
-        // Pre-render phase (TODO: MOVE OFF RENDER THREAD)
+        // Pre-render phase
         self.pre_render(delta_time, render_camera_handle);
 
         let engine_state: &mut EngineCoreState = self.engine_core_state.as_mut().unwrap();
 
-        // Begin rendering
+        // The depth buffer must match the size of the color attachment, which is the size of the surface
         {
-            let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("MainEngineRenderPass"),
-                color_attachments: &[wgpu::RenderPassColorAttachment {
-                    view: if self.multisample_state.count == 1 { &surface_texture_view } else { mutisampled_framebuffer.unwrap() },
-                    resolve_target: if self.multisample_state.count == 1 { None } else { Some(&surface_texture_view) },
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(Color::TRANSPARENT),
-                        // Storing pre-resolve MSAA data is unnecessary if it isn't used later.
-                        // On tile-based GPU, avoid store can reduce your app's memory footprint.
-                        store: if self.multisample_state.count == 1 { true } else { false },
-                    },
-                }],
-                depth_stencil_attachment: None,
+            let surface_config = self.surface_config.read().unwrap();
+            engine_state.depth_buffer.ensure_size(&self.device, surface_config.width, surface_config.height, self.multisample_state.count);
+        }
+
+        // Shadow maps are rendered in passes of their own, before the main render pass
+        {
+            engine_state.render_scene.pre_render(command_encoder);
+        }
+
+        encode_main_pass(
+            &mut engine_state.render_scene,
+            command_encoder,
+            if self.multisample_state.count == 1 { surface_texture_view } else { mutisampled_framebuffer.unwrap() },
+            if self.multisample_state.count == 1 { None } else { Some(surface_texture_view) },
+            engine_state.depth_buffer.view(),
+            viewport_region,
+        );
+    }
+
+    /// Renders the scene as seen in [viewport_region] into an offscreen texture the size of the viewport and
+    /// reads it back. The ECS world is not updated, the capture shows the state of the last rendered frame.
+    /// Blocks until the capture has completed.
+    #[profiling::function]
+    pub fn capture_viewport(&mut self, viewport_region: &ViewportRegion) -> Result<FrameCapture, CaptureError> {
+        let engine_state: &mut EngineCoreState = self.engine_core_state.as_mut().ok_or(CaptureError::NotStarted)?;
+        let width = viewport_region.width.round() as u32;
+        let height = viewport_region.height.round() as u32;
+        if width == 0 || height == 0 {
+            return Err(CaptureError::EmptyViewport);
+        }
+
+        // The pipelines are created for the surface format and sample count, so the capture must use them as well
+        let format = self.surface_config.read().unwrap().format;
+        let sample_count = self.multisample_state.count;
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let capture_texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("CaptureTexture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        });
+        let capture_view = capture_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let multisampled_view = if sample_count == 1 {
+            None
+        } else {
+            let multisampled_texture = self.device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("CaptureMultisampledTexture"),
+                size,
+                mip_level_count: 1,
+                sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             });
+            Some(multisampled_texture.create_view(&wgpu::TextureViewDescriptor::default()))
+        };
+        let depth_buffer = DepthBuffer::new(&self.device, width, height, sample_count);
 
-            render_pass.set_viewport(viewport_region.x, viewport_region.y, viewport_region.width, viewport_region.height, 0.0, 1.0);
-            render_pass.set_pipeline(&engine_state.render_pipeline);
+        let mut command_encoder = self.device.create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some("CaptureViewport") }
+        );
+        engine_state.render_scene.pre_render(&mut command_encoder);
+        encode_main_pass(
+            &mut engine_state.render_scene,
+            &mut command_encoder,
+            multisampled_view.as_ref().unwrap_or(&capture_view),
+            multisampled_view.as_ref().map(|_| &capture_view),
+            depth_buffer.view(),
+            &ViewportRegion { x: 0.0, y: 0.0, width: width as f32, height: height as f32 },
+        );
+        self.queue.submit(Some(command_encoder.finish()));
+
+        let pixels = read_texture_rgba8(&self.device, &self.queue, &capture_texture, format, width, height)?;
+        capture_texture.destroy();
+        return Ok(FrameCapture { width, height, pixels });
+    }
 
-            engine_state.render_scene.render(&mut RenderCallState { render_pass: &mut render_pass });
+    /// Captures [viewport_region] as [Self::capture_viewport()] does and writes it to a PNG file at [path].
+    pub fn save_screenshot<P: AsRef<Path>>(&mut self, viewport_region: &ViewportRegion, path: P) -> Result<(), CaptureError> {
+        return self.capture_viewport(viewport_region)?.save_png(path);
+    }
 
-            render_pass.execute_bundles(std::iter::once(&engine_state.triangle_render_bundle));
+    /// Writes the entities of the running scene to a scene file at [path], see [SceneFile::capture()].
+    /// Fails with [SceneFileError::NotStarted] if the engine has not been started.
+    pub fn save_scene<P: AsRef<Path>>(&mut self, path: P) -> Result<(), SceneFileError> {
+        let engine_state: &mut EngineCoreState = self.engine_core_state.as_mut().ok_or(SceneFileError::NotStarted)?;
+        let scene_file;
+        {
+            let ecs_world = engine_state.ecs_world.lock().unwrap();
+            scene_file = SceneFile::capture(&ecs_world, &mut engine_state.render_scene, scene_dir(path.as_ref()));
         }
+        return scene_file.save(path);
+    }
+
+    /// Replaces all entities of the running scene with the entities of the scene file at [path].
+    /// The running scene is kept if the file cannot be loaded or has no camera to render from.
+    /// Fails with [SceneFileError::NotStarted] if the engine has not been started.
+    pub fn load_scene<P: AsRef<Path>>(&mut self, path: P) -> Result<(), SceneFileError> {
+        let engine_state: &mut EngineCoreState = self.engine_core_state.as_mut().ok_or(SceneFileError::NotStarted)?;
+        let scene_file = SceneFile::load(path.as_ref())?;
+        let render_scene = &mut engine_state.render_scene;
+        let mut ecs_world = engine_state.ecs_world.lock().unwrap();
+
+        // Keep the aspect ratio of the viewport the scene is rendered to
+        let aspect = match ecs_world.get_primary_camera()
+            .and_then(|camera_handle| ecs_world.get_entity(&camera_handle))
+            .and_then(|camera_entity| camera_entity.get_render_node().copied())
+            .and_then(|camera_node_handle| render_scene.get_node_by_id::<CameraRenderNode>(&camera_node_handle)) {
+            Some(camera_node) => camera_node.aspect(),
+            None => {
+                let surface_config = self.surface_config.read().unwrap();
+                surface_config.width as f32 / surface_config.height.max(1) as f32
+            }
+        };
+
+        // Spawning adds no entities if it fails, the old entities are only removed once the new ones are in place
+        let old_entity_handles = ecs_world.entity_handles();
+        let new_entity_handles = scene_file.spawn(&mut ecs_world, render_scene, &mut engine_state.prefab_library, scene_dir(path.as_ref()), aspect)?;
+        // The scene is rendered from its first camera, which may be part of a prefab instance
+        let new_camera = ecs_world.get_cameras().iter()
+            .any(|camera_handle| !old_entity_handles.contains(camera_handle));
+        if !new_camera {
+            for entity_handle in &new_entity_handles {
+                ecs_world.remove_entity(entity_handle, render_scene);
+            }
+            return Err(SceneFileError::NoCamera);
+        }
+        for entity_handle in &old_entity_handles {
+            ecs_world.remove_entity(entity_handle, render_scene);
+        }
+        return Ok(());
     }
 
     #[profiling::function]
@@ -256,13 +404,22 @@ impl EngineInstance {
         }
 
         let engine_state: &mut EngineCoreState = self.engine_core_state.as_mut().unwrap();
-        let ecs_world = &engine_state.ecs_world;
+
+        // Recreate the depth buffer to match the resized surface
+        {
+            let surface_config = self.surface_config.read().unwrap();
+            engine_state.depth_buffer.ensure_size(&self.device, surface_config.width, surface_config.height, self.multisample_state.count);
+        }
+
+        let ecs_world = engine_state.ecs_world.lock().unwrap();
         for camera_ecs_handle in ecs_world.get_cameras() {
-            let camera_rende_node_handle = ecs_world.get_entity(camera_ecs_handle).unwrap()
-                .get_render_node()
-                .unwrap();
-            let camera_node: &mut CameraRenderNode = engine_state.render_scene.get_node_by_id(camera_rende_node_handle).unwrap();
-            camera_node.set_aspect(viewport_region.width as f32 / viewport_region.height as f32);
+            let camera_render_node_handle = match ecs_world.get_entity(camera_ecs_handle).and_then(|camera| camera.get_render_node()) {
+                Some(camera_render_node_handle) => camera_render_node_handle,
+                None => continue,
+            };
+            if let Some(camera_node) = engine_state.render_scene.get_node_by_id::<CameraRenderNode>(camera_render_node_handle) {
+                camera_node.set_aspect(viewport_region.width as f32 / viewport_region.height as f32);
+            }
         }
     }
 
@@ -292,7 +449,49 @@ impl EngineInstance {
         self.movement_input.delta_pitch += mouse_delta.1 as f32 / 1000.0;
     }
 
+    /// Statistics of the render node culling of the last rendered frame. None if the engine has not been started.
+    pub fn culling_stats(&self) -> Option<CullingStats> {
+        return self.engine_core_state.as_ref()
+            .map(|engine_state| *engine_state.render_scene.culling_stats());
+    }
+
     pub fn should_grab_cursor(&self) -> bool {
         return true;
     }
-}
\ No newline at end of file
+}
+
+/// The directory that relative paths in the scene file at [path] are resolved against.
+fn scene_dir(path: &Path) -> &Path {
+    return path.parent().unwrap_or_else(|| Path::new(""));
+}
+
+/// Records the main render pass, which renders [render_scene] into [color_view] within [viewport_region].
+/// [RenderScene::pre_render()] must have been recorded before.
+fn encode_main_pass(render_scene: &mut RenderScene, command_encoder: &mut CommandEncoder, color_view: &wgpu::TextureView, resolve_target: Option<&wgpu::TextureView>, depth_view: &wgpu::TextureView, viewport_region: &ViewportRegion) {
+    let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("MainEngineRenderPass"),
+        color_attachments: &[wgpu::RenderPassColorAttachment {
+            view: color_view,
+            resolve_target,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(Color::TRANSPARENT),
+                // Storing pre-resolve MSAA data is unnecessary if it isn't used later.
+                // On tile-based GPU, avoid store can reduce your app's memory footprint.
+                store: resolve_target.is_none(),
+            },
+        }],
+        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+            view: depth_view,
+            depth_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(DepthBuffer::CLEAR_DEPTH),
+                // The depth buffer is not read after the main render pass
+                store: false,
+            }),
+            stencil_ops: None,
+        }),
+    });
+
+    render_pass.set_viewport(viewport_region.x, viewport_region.y, viewport_region.width, viewport_region.height, 0.0, 1.0);
+
+    render_scene.render(&mut RenderCallState { render_pass: &mut render_pass });
+}
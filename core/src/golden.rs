@@ -0,0 +1,401 @@
+use std::fmt;
+use std::fmt::Formatter;
+use std::fs;
+use std::path::{Path, PathBuf};
+use glam::Vec3A;
+use serde::Deserialize;
+use scenelib::ecs::{CameraEntity, LightEntity, MeshEntity};
+use scenelib::gltf_loader::{self, ModelLoadError};
+use scenelib::transform::Transform;
+use crate::capture::{CaptureError, FrameCapture};
+use crate::headless::{HeadlessEngine, HeadlessError};
+
+/// Setting this environment variable to any value makes [run_golden_test()] write the reference images from the
+/// rendered frames instead of comparing against them, creating missing ones.
+pub const UPDATE_GOLDEN_ENV_VAR: &str = "DYNGINE_UPDATE_GOLDEN";
+
+/// A scene rendered by a golden-image test, read from a RON file.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct GoldenScene {
+    pub width: u32,
+    pub height: u32,
+    /// The number of frames rendered before the last frame is compared against the reference image.
+    pub frames: u32,
+    /// The delta time of every frame. unit: seconds
+    pub delta_time: f64,
+    #[serde(default)]
+    pub tolerance: Tolerance,
+    pub camera: GoldenCamera,
+    #[serde(default)]
+    pub lights: Vec<GoldenLight>,
+    #[serde(default)]
+    pub models: Vec<GoldenModel>,
+}
+
+/// The camera the scene is rendered from. It does not move.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub enum GoldenCamera {
+    /// [fov] is the vertical field of view in degrees.
+    Perspective { position: [f32; 3], direction: [f32; 3], fov: f32, near: f32, far: Option<f32> },
+    Orthographic { position: [f32; 3], direction: [f32; 3], height: f32, near: f32, far: f32 },
+}
+
+/// The cone angles of spot lights are in degrees.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub enum GoldenLight {
+    Directional { direction: [f32; 3], color: [f32; 3], intensity: f32, casts_shadows: bool },
+    Point { position: [f32; 3], color: [f32; 3], intensity: f32, range: f32 },
+    Spot { position: [f32; 3], direction: [f32; 3], color: [f32; 3], intensity: f32, range: f32, inner_cone_angle: f32, outer_cone_angle: f32, casts_shadows: bool },
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct GoldenModel {
+    /// Path of a glTF file, relative to the scene file.
+    pub path: String,
+    #[serde(default)]
+    pub position: [f32; 3],
+}
+
+/// How much a rendered frame may differ from its reference image.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Tolerance {
+    /// The perceptual color difference in [0, 1] above which a pixel counts as different.
+    pub pixel_threshold: f32,
+    /// The fraction of pixels that may differ.
+    pub max_different_pixels: f32,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        return Tolerance {
+            pixel_threshold: 0.1,
+            max_different_pixels: 0.001,
+        };
+    }
+}
+
+/// The result of comparing two images of the same size.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImageComparison {
+    pub different_pixels: usize,
+    pub total_pixels: usize,
+    /// The expected image faded to grey, with the different pixels marked red.
+    pub diff_image: FrameCapture,
+}
+
+impl ImageComparison {
+    pub fn different_pixel_ratio(&self) -> f32 {
+        return self.different_pixels as f32 / self.total_pixels.max(1) as f32;
+    }
+
+    pub fn is_within(&self, tolerance: &Tolerance) -> bool {
+        return self.different_pixel_ratio() <= tolerance.max_different_pixels;
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum GoldenOutcome {
+    /// The rendered frame matches the reference image within the tolerance.
+    Matched { different_pixels: usize },
+    /// An update was requested with [UPDATE_GOLDEN_ENV_VAR], the rendered frame was written as the reference.
+    ReferenceWritten,
+    /// No graphics adapter is available, nothing was rendered.
+    Skipped,
+}
+
+#[derive(Debug)]
+pub enum GoldenError {
+    Io(std::io::Error),
+    /// The scene file is not a valid [GoldenScene].
+    Scene(ron::Error),
+    Model { path: PathBuf, error: ModelLoadError },
+    Headless(HeadlessError),
+    Image(image::ImageError),
+    Capture(CaptureError),
+    SizeMismatch { expected: (u32, u32), actual: (u32, u32) },
+    /// There is no reference image at [path] and no update was requested with [UPDATE_GOLDEN_ENV_VAR].
+    MissingReference { path: PathBuf },
+    /// The rendered frame differs from the reference image by more than the tolerance.
+    /// The rendered frame and the diff image were written to [actual_path] and [diff_path].
+    Mismatch { different_pixels: usize, total_pixels: usize, actual_path: PathBuf, diff_path: PathBuf },
+}
+
+impl fmt::Display for GoldenError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            GoldenError::Io(error) => write!(f, "I/O error: {}", error),
+            GoldenError::Scene(error) => write!(f, "Invalid scene file: {}", error),
+            GoldenError::Model { path, error } => write!(f, "Failed to load model {}: {}", path.display(), error),
+            GoldenError::Headless(error) => write!(f, "{}", error),
+            GoldenError::Image(error) => write!(f, "Image error: {}", error),
+            GoldenError::Capture(error) => write!(f, "{}", error),
+            GoldenError::SizeMismatch { expected, actual } => write!(f, "Expected a {}x{} frame, rendered {}x{}", expected.0, expected.1, actual.0, actual.1),
+            GoldenError::MissingReference { path } => write!(
+                f, "Missing reference image {}, set {} to write it from the rendered frame",
+                path.display(), UPDATE_GOLDEN_ENV_VAR
+            ),
+            GoldenError::Mismatch { different_pixels, total_pixels, actual_path, diff_path } => write!(
+                f, "{} of {} pixels differ from the reference, see {} and {}",
+                different_pixels, total_pixels, actual_path.display(), diff_path.display()
+            ),
+        }
+    }
+}
+
+impl From<std::io::Error> for GoldenError {
+    fn from(error: std::io::Error) -> Self {
+        return GoldenError::Io(error);
+    }
+}
+
+impl From<ron::Error> for GoldenError {
+    fn from(error: ron::Error) -> Self {
+        return GoldenError::Scene(error);
+    }
+}
+
+impl From<HeadlessError> for GoldenError {
+    fn from(error: HeadlessError) -> Self {
+        return GoldenError::Headless(error);
+    }
+}
+
+impl From<image::ImageError> for GoldenError {
+    fn from(error: image::ImageError) -> Self {
+        return GoldenError::Image(error);
+    }
+}
+
+impl From<CaptureError> for GoldenError {
+    fn from(error: CaptureError) -> Self {
+        return GoldenError::Capture(error);
+    }
+}
+
+/// Renders the scene at [scene_path] headless and compares the last frame against the PNG at [reference_path].
+/// On a mismatch, the rendered frame and a diff image are written to [output_dir].
+/// A missing reference image is an error, unless [UPDATE_GOLDEN_ENV_VAR] is set, which writes every reference image
+/// from the rendered frame.
+pub fn run_golden_test<P: AsRef<Path>>(scene_path: P, reference_path: P, output_dir: P) -> Result<GoldenOutcome, GoldenError> {
+    let scene_path = scene_path.as_ref();
+    let reference_path = reference_path.as_ref();
+    let scene: GoldenScene = ron::from_str(&fs::read_to_string(scene_path)?)?;
+
+    let actual = match render_golden_scene(&scene, scene_path.parent().unwrap_or(Path::new("."))) {
+        Ok(frame) => frame,
+        Err(GoldenError::Headless(HeadlessError::NoAdapter)) => return Ok(GoldenOutcome::Skipped),
+        Err(error) => return Err(error),
+    };
+
+    if std::env::var_os(UPDATE_GOLDEN_ENV_VAR).is_some() {
+        if let Some(reference_dir) = reference_path.parent() {
+            fs::create_dir_all(reference_dir)?;
+        }
+        actual.save_png(reference_path)?;
+        return Ok(GoldenOutcome::ReferenceWritten);
+    }
+    if !reference_path.exists() {
+        return Err(GoldenError::MissingReference { path: reference_path.to_path_buf() });
+    }
+
+    let reference_image = image::open(reference_path)?.to_rgba8();
+    let expected = FrameCapture {
+        width: reference_image.width(),
+        height: reference_image.height(),
+        pixels: reference_image.into_raw(),
+    };
+    let comparison = compare_images(&expected, &actual, scene.tolerance.pixel_threshold)
+        .ok_or(GoldenError::SizeMismatch { expected: (expected.width, expected.height), actual: (actual.width, actual.height) })?;
+    if comparison.is_within(&scene.tolerance) {
+        return Ok(GoldenOutcome::Matched { different_pixels: comparison.different_pixels });
+    }
+
+    // Keep the evidence for inspection
+    let output_dir = output_dir.as_ref();
+    fs::create_dir_all(output_dir)?;
+    let file_stem = reference_path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    let actual_path = output_dir.join(format!("{}.actual.png", file_stem));
+    let diff_path = output_dir.join(format!("{}.diff.png", file_stem));
+    for (frame, path) in [(&actual, &actual_path), (&comparison.diff_image, &diff_path)] {
+        frame.save_png(path)?;
+    }
+    return Err(GoldenError::Mismatch {
+        different_pixels: comparison.different_pixels,
+        total_pixels: comparison.total_pixels,
+        actual_path,
+        diff_path,
+    });
+}
+
+/// Renders [scene] and returns its last frame. Model paths are resolved relative to [scene_dir].
+pub fn render_golden_scene(scene: &GoldenScene, scene_dir: &Path) -> Result<FrameCapture, GoldenError> {
+    let mut engine = HeadlessEngine::new_empty(scene.width, scene.height)?;
+
+    {
+        let engine_state = engine.engine_instance_mut().engine_core_state.as_mut().unwrap();
+        let mut ecs_world = engine_state.ecs_world.lock().unwrap();
+        let ecs_world = &mut *ecs_world;
+        let render_scene = &mut engine_state.render_scene;
+        let aspect = scene.width as f32 / scene.height.max(1) as f32;
+
+        // The camera is never moved, as the headless engine receives no movement input
+        match scene.camera {
+            GoldenCamera::Perspective { position, direction, fov, near, far } => {
+                CameraEntity::add_flying(ecs_world, render_scene, Vec3A::from(position), Vec3A::from(direction).normalize(), Vec3A::Z, Vec3A::Y, fov, near, far, aspect);
+            }
+            GoldenCamera::Orthographic { position, direction, height, near, far } => {
+                CameraEntity::add_orthographic(ecs_world, render_scene, Vec3A::from(position), Vec3A::from(direction).normalize(), Vec3A::Z, Vec3A::Y, height, near, far, aspect);
+            }
+        }
+
+        for light in &scene.lights {
+            match *light {
+                GoldenLight::Directional { direction, color, intensity, casts_shadows } => {
+                    LightEntity::add_directional(ecs_world, render_scene, Vec3A::from(direction), Vec3A::from(color), intensity, casts_shadows);
+                }
+                GoldenLight::Point { position, color, intensity, range } => {
+                    LightEntity::add_point(ecs_world, render_scene, Vec3A::from(position), Vec3A::from(color), intensity, range);
+                }
+                GoldenLight::Spot { position, direction, color, intensity, range, inner_cone_angle, outer_cone_angle, casts_shadows } => {
+                    LightEntity::add_spot(
+                        ecs_world, render_scene,
+                        Vec3A::from(position), Vec3A::from(direction), Vec3A::from(color), intensity, range,
+                        inner_cone_angle.to_radians(), outer_cone_angle.to_radians(), casts_shadows,
+                    );
+                }
+            }
+        }
+
+        for golden_model in &scene.models {
+            let path = scene_dir.join(&golden_model.path);
+            let model = gltf_loader::load_model_from_file(&path)
+                .map_err(|error| GoldenError::Model { path: path.clone(), error })?;
+            MeshEntity::spawn_model(ecs_world, render_scene, &model, Transform::from_translation(Vec3A::from(golden_model.position)));
+        }
+    }
+
+    let mut frame = None;
+    for _ in 0..scene.frames.max(1) {
+        frame = Some(engine.render_frame(scene.delta_time)?);
+    }
+    return Ok(frame.unwrap());
+}
+
+/// Compares [actual] against [expected] pixel by pixel, using the perceptual YIQ color difference of
+/// "Measuring perceived color difference using YIQ NTSC transmission color space in mobile applications"
+/// (Kotsarenko, Ramos). Pixels differing by more than [pixel_threshold] in [0, 1] count as different.
+/// Returns None if the images differ in size.
+pub fn compare_images(expected: &FrameCapture, actual: &FrameCapture, pixel_threshold: f32) -> Option<ImageComparison> {
+    if expected.width != actual.width || expected.height != actual.height {
+        return None;
+    }
+    // The largest possible difference, between black and white
+    const MAX_YIQ_DELTA: f32 = 35215.0;
+    let max_delta = MAX_YIQ_DELTA * pixel_threshold * pixel_threshold;
+
+    let mut different_pixels = 0;
+    let mut diff_pixels = Vec::with_capacity(expected.pixels.len());
+    for (expected_pixel, actual_pixel) in expected.pixels.chunks_exact(4).zip(actual.pixels.chunks_exact(4)) {
+        let expected_yiq = rgba_to_yiq(expected_pixel);
+        let actual_yiq = rgba_to_yiq(actual_pixel);
+        let delta_y = expected_yiq[0] - actual_yiq[0];
+        let delta_i = expected_yiq[1] - actual_yiq[1];
+        let delta_q = expected_yiq[2] - actual_yiq[2];
+        let delta = 0.5053 * delta_y * delta_y + 0.299 * delta_i * delta_i + 0.1957 * delta_q * delta_q;
+        if delta > max_delta {
+            different_pixels += 1;
+            diff_pixels.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            let faded = (255.0 + (expected_yiq[0] - 255.0) * 0.1) as u8;
+            diff_pixels.extend_from_slice(&[faded, faded, faded, 255]);
+        }
+    }
+
+    return Some(ImageComparison {
+        different_pixels,
+        total_pixels: (expected.width * expected.height) as usize,
+        diff_image: FrameCapture {
+            width: expected.width,
+            height: expected.height,
+            pixels: diff_pixels,
+        },
+    });
+}
+
+/// Converts an RGBA8 pixel, blended over white, to YIQ.
+fn rgba_to_yiq(pixel: &[u8]) -> [f32; 3] {
+    let alpha = pixel[3] as f32 / 255.0;
+    let blend = |channel: u8| 255.0 + (channel as f32 - 255.0) * alpha;
+    let (r, g, b) = (blend(pixel[0]), blend(pixel[1]), blend(pixel[2]));
+    return [
+        r * 0.29889531 + g * 0.58662247 + b * 0.11448223,
+        r * 0.59597799 - g * 0.27417610 - b * 0.32180189,
+        r * 0.21147017 - g * 0.52261711 + b * 0.31114694,
+    ];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid_image(width: u32, height: u32, color: [u8; 4]) -> FrameCapture {
+        return FrameCapture {
+            width,
+            height,
+            pixels: color.iter().copied().cycle().take((width * height * 4) as usize).collect(),
+        };
+    }
+
+    #[test]
+    fn identical_images_match() {
+        let image = solid_image(4, 4, [10, 200, 30, 255]);
+        let comparison = compare_images(&image, &image, 0.0).unwrap();
+        assert_eq!(comparison.different_pixels, 0);
+        assert_eq!(comparison.total_pixels, 16);
+        assert!(comparison.is_within(&Tolerance { pixel_threshold: 0.0, max_different_pixels: 0.0 }));
+    }
+
+    #[test]
+    fn small_color_differences_are_tolerated() {
+        let expected = solid_image(4, 4, [100, 100, 100, 255]);
+        let actual = solid_image(4, 4, [102, 101, 100, 255]);
+        assert_eq!(compare_images(&expected, &actual, 0.1).unwrap().different_pixels, 0);
+    }
+
+    #[test]
+    fn differing_pixels_are_counted_and_marked() {
+        let expected = solid_image(2, 2, [0, 0, 0, 255]);
+        let mut actual = expected.clone();
+        actual.pixels[4..8].copy_from_slice(&[255, 255, 255, 255]);
+
+        let comparison = compare_images(&expected, &actual, 0.1).unwrap();
+        assert_eq!(comparison.different_pixels, 1);
+        assert_eq!(comparison.different_pixel_ratio(), 0.25);
+        assert!(!comparison.is_within(&Tolerance::default()));
+        assert_eq!(&comparison.diff_image.pixels[4..8], &[255, 0, 0, 255]);
+        assert_ne!(&comparison.diff_image.pixels[0..4], &[255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn images_of_different_size_are_not_compared() {
+        assert_eq!(compare_images(&solid_image(2, 2, [0; 4]), &solid_image(2, 3, [0; 4]), 0.1), None);
+    }
+
+    #[test]
+    fn parses_scene_description() {
+        let scene: GoldenScene = ron::from_str(r#"GoldenScene(
+            width: 64,
+            height: 32,
+            frames: 2,
+            delta_time: 0.5,
+            camera: Orthographic(position: (0.0, 0.0, -5.0), direction: (0.0, 0.0, 1.0), height: 4.0, near: 0.1, far: 10.0),
+            lights: [ Point(position: (1.0, 2.0, 3.0), color: (1.0, 1.0, 1.0), intensity: 10.0, range: 5.0) ],
+        )"#).unwrap();
+        assert_eq!(scene.width, 64);
+        assert_eq!(scene.tolerance, Tolerance::default());
+        assert_eq!(scene.lights.len(), 1);
+        assert!(scene.models.is_empty());
+        assert!(matches!(scene.camera, GoldenCamera::Orthographic { height, .. } if height == 4.0));
+    }
+}
@@ -0,0 +1,240 @@
+use std::fmt;
+use std::fmt::Formatter;
+use std::sync::{Arc, RwLock};
+use wgpu::{Device, Queue, SurfaceConfiguration};
+use scenelib::ecs::ECSEntityHandle;
+use crate::capture::{read_texture_rgba8, FrameCapture};
+use crate::engine::{EngineInstance, ViewportRegion};
+
+/// The color format frames are rendered in when running headless.
+pub const HEADLESS_COLOR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+#[derive(Debug)]
+pub enum HeadlessError {
+    /// Neither a fallback (software) adapter nor a hardware adapter is available.
+    NoAdapter,
+    RequestDevice(wgpu::RequestDeviceError),
+    /// The scene has no camera to render from.
+    NoCamera,
+    /// Reading the rendered frame back from the GPU failed.
+    Readback(wgpu::BufferAsyncError),
+}
+
+impl fmt::Display for HeadlessError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            HeadlessError::NoAdapter => write!(f, "No graphics adapter available"),
+            HeadlessError::RequestDevice(error) => write!(f, "Failed to create device: {}", error),
+            HeadlessError::NoCamera => write!(f, "The scene has no camera"),
+            HeadlessError::Readback(error) => write!(f, "Failed to read back frame: {}", error),
+        }
+    }
+}
+
+impl From<wgpu::RequestDeviceError> for HeadlessError {
+    fn from(error: wgpu::RequestDeviceError) -> Self {
+        return HeadlessError::RequestDevice(error);
+    }
+}
+
+impl From<wgpu::BufferAsyncError> for HeadlessError {
+    fn from(error: wgpu::BufferAsyncError) -> Self {
+        return HeadlessError::Readback(error);
+    }
+}
+
+/// The texture a headless engine renders into instead of a surface texture.
+struct OffscreenTarget {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+}
+
+impl OffscreenTarget {
+    fn new(device: &Device, width: u32, height: u32) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("OffscreenTarget"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: HEADLESS_COLOR_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        return OffscreenTarget { texture, view };
+    }
+}
+
+/// An [EngineInstance] that runs without a window or surface, rendering into an offscreen texture.
+/// Prefers a fallback (software) adapter, so that it also works on machines without a GPU or display.
+pub struct HeadlessEngine {
+    // The instance must outlive the device
+    _instance: wgpu::Instance,
+    adapter_info: wgpu::AdapterInfo,
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    surface_config: Arc<RwLock<SurfaceConfiguration>>,
+    target: OffscreenTarget,
+    engine_instance: EngineInstance,
+}
+
+impl HeadlessEngine {
+    /// Creates a headless engine rendering frames of [width] x [height] pixels and starts it with the demo scene.
+    pub fn new(width: u32, height: u32) -> Result<Self, HeadlessError> {
+        return pollster::block_on(Self::new_async(width, height, false));
+    }
+
+    /// Creates a headless engine rendering frames of [width] x [height] pixels and starts it with an empty scene.
+    pub fn new_empty(width: u32, height: u32) -> Result<Self, HeadlessError> {
+        return pollster::block_on(Self::new_async(width, height, true));
+    }
+
+    async fn new_async(width: u32, height: u32, empty_scene: bool) -> Result<Self, HeadlessError> {
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+
+        // The fallback adapter renders the same on every machine, a hardware adapter is only used without one
+        let mut adapter = None;
+        for force_fallback_adapter in [true, false] {
+            adapter = instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::default(),
+                    force_fallback_adapter,
+                    compatible_surface: None,
+                })
+                .await;
+            if adapter.is_some() {
+                break;
+            }
+        }
+        let adapter = adapter.ok_or(HeadlessError::NoAdapter)?;
+
+        let device;
+        let queue;
+        {
+            let (d, q) = adapter
+                .request_device(
+                    &wgpu::DeviceDescriptor {
+                        label: None,
+                        features: wgpu::Features::default(),
+                        // Software adapters may not support the default limits
+                        limits: wgpu::Limits::downlevel_defaults().using_resolution(adapter.limits()),
+                    },
+                    None,
+                ).await?;
+            device = Arc::new(d);
+            queue = Arc::new(q);
+        }
+
+        // Never used to configure a surface, only describes the render target to the engine
+        let surface_config = Arc::new(RwLock::new(wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: HEADLESS_COLOR_FORMAT,
+            width: width.max(1),
+            height: height.max(1),
+            present_mode: wgpu::PresentMode::Fifo,
+        }));
+
+        let target = OffscreenTarget::new(&device, width.max(1), height.max(1));
+
+        let mut engine_instance = EngineInstance::new(device.clone(), queue.clone(), surface_config.clone());
+        if empty_scene {
+            engine_instance.start_empty();
+        } else {
+            engine_instance.start();
+        }
+
+        let mut headless_engine = HeadlessEngine {
+            _instance: instance,
+            adapter_info: adapter.get_info(),
+            device,
+            queue,
+            surface_config,
+            target,
+            engine_instance,
+        };
+        headless_engine.engine_instance.resize(&headless_engine.viewport_region());
+        return Ok(headless_engine);
+    }
+
+    /// Information about the adapter the engine renders with, eg. to tell whether a software adapter is used.
+    pub fn adapter_info(&self) -> &wgpu::AdapterInfo {
+        return &self.adapter_info;
+    }
+
+    pub fn device(&self) -> &Arc<Device> {
+        return &self.device;
+    }
+
+    pub fn queue(&self) -> &Arc<Queue> {
+        return &self.queue;
+    }
+
+    pub fn engine_instance(&self) -> &EngineInstance {
+        return &self.engine_instance;
+    }
+
+    pub fn engine_instance_mut(&mut self) -> &mut EngineInstance {
+        return &mut self.engine_instance;
+    }
+
+    pub fn width(&self) -> u32 {
+        return self.surface_config.read().unwrap().width;
+    }
+
+    pub fn height(&self) -> u32 {
+        return self.surface_config.read().unwrap().height;
+    }
+
+    /// Resizes the offscreen render target and updates the aspect ratio of all cameras.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        {
+            let mut surface_config = self.surface_config.write().unwrap();
+            surface_config.width = width.max(1);
+            surface_config.height = height.max(1);
+        }
+        self.target.texture.destroy();
+        self.target = OffscreenTarget::new(&self.device, width.max(1), height.max(1));
+        self.engine_instance.resize(&self.viewport_region());
+    }
+
+    /// Advances the ECS world by [delta_time] seconds without rendering.
+    pub fn update(&mut self, delta_time: f64) -> Result<(), HeadlessError> {
+        let camera_handle = self.primary_camera()?;
+        self.engine_instance.update(delta_time, camera_handle);
+        return Ok(());
+    }
+
+    /// Advances the ECS world by [delta_time] seconds, renders a frame from the primary camera and reads it back.
+    pub fn render_frame(&mut self, delta_time: f64) -> Result<FrameCapture, HeadlessError> {
+        let camera_handle = self.primary_camera()?;
+        let viewport_region = self.viewport_region();
+
+        let mut command_encoder = self.device.create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some("HeadlessRender") }
+        );
+        self.engine_instance.render(&mut command_encoder, &self.target.view, None, &viewport_region, camera_handle, delta_time);
+        self.queue.submit(Some(command_encoder.finish()));
+
+        let (width, height) = (self.width(), self.height());
+        let pixels = read_texture_rgba8(&self.device, &self.queue, &self.target.texture, HEADLESS_COLOR_FORMAT, width, height)?;
+        return Ok(FrameCapture { width, height, pixels });
+    }
+
+    fn primary_camera(&self) -> Result<ECSEntityHandle, HeadlessError> {
+        return self.engine_instance.primary_camera()
+            .ok_or(HeadlessError::NoCamera);
+    }
+
+    fn viewport_region(&self) -> ViewportRegion {
+        return ViewportRegion {
+            x: 0.0,
+            y: 0.0,
+            width: self.width() as f32,
+            height: self.height() as f32,
+        };
+    }
+}
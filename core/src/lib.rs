@@ -1,2 +1,7 @@
+pub mod capture;
 pub mod engine;
+pub mod golden;
+pub mod headless;
+pub mod simulation;
+mod depth_buffer;
 mod input;
\ No newline at end of file
@@ -0,0 +1,153 @@
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use scenelib::ecs::{ECSWorld, MovementInput};
+use scenelib::snapshot::RenderSnapshot;
+
+/// State shared between the render thread and the simulation thread.
+struct SimulationShared {
+    ecs_world: Arc<Mutex<ECSWorld>>,
+    /// The input consumed by the next tick.
+    movement_input: Mutex<MovementInput>,
+    latest_snapshot: Mutex<Option<Arc<RenderSnapshot>>>,
+    running: AtomicBool,
+}
+
+/// Runs the fixed-timestep ECS simulation on a thread of its own. After every batch of ticks, a [RenderSnapshot]
+/// is published, which the render thread applies to the render scene.
+/// The [ECSWorld] is locked while ticks run, entities may be added and removed by other threads in between.
+pub struct SimulationThread {
+    shared: Arc<SimulationShared>,
+    join_handle: Option<JoinHandle<()>>,
+}
+
+impl SimulationThread {
+    pub fn spawn(ecs_world: Arc<Mutex<ECSWorld>>) -> Self {
+        let shared = Arc::new(SimulationShared {
+            ecs_world,
+            movement_input: Mutex::new(MovementInput::new()),
+            latest_snapshot: Mutex::new(None),
+            running: AtomicBool::new(true),
+        });
+        let thread_shared = shared.clone();
+        let join_handle = thread::Builder::new()
+            .name("Simulation".to_string())
+            .spawn(move || run_simulation(&thread_shared))
+            .expect("Failed to spawn simulation thread");
+        return SimulationThread { shared, join_handle: Some(join_handle) };
+    }
+
+    /// Merges [movement_input] into the input consumed by the next tick.
+    /// Key states replace the previous ones, mouse movement accumulates until a tick consumes it.
+    pub fn push_input(&self, movement_input: &MovementInput) {
+        let mut pending_input = self.shared.movement_input.lock().unwrap();
+        merge_input(&mut pending_input, movement_input);
+    }
+
+    /// The snapshot taken after the most recent tick. None until the first tick has run.
+    pub fn latest_snapshot(&self) -> Option<Arc<RenderSnapshot>> {
+        return self.shared.latest_snapshot.lock().unwrap().clone();
+    }
+
+    /// Stops the simulation after the current batch of ticks and waits for the thread to exit.
+    pub fn stop(&mut self) {
+        self.shared.running.store(false, Ordering::Release);
+        if let Some(join_handle) = self.join_handle.take() {
+            join_handle.join().expect("Simulation thread panicked");
+        }
+    }
+}
+
+impl Drop for SimulationThread {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Merges [movement_input] into [pending_input], see [SimulationThread::push_input()].
+fn merge_input(pending_input: &mut MovementInput, movement_input: &MovementInput) {
+    let delta_yaw = pending_input.delta_yaw + movement_input.delta_yaw;
+    let delta_pitch = pending_input.delta_pitch + movement_input.delta_pitch;
+    *pending_input = movement_input.clone();
+    pending_input.delta_yaw = delta_yaw;
+    pending_input.delta_pitch = delta_pitch;
+}
+
+/// Removes the mouse movement of [consumed_input], which ticks have applied, from [pending_input].
+/// Mouse movement pushed while the ticks ran is kept for the next tick.
+fn consume_mouse_movement(pending_input: &mut MovementInput, consumed_input: &MovementInput) {
+    pending_input.delta_yaw -= consumed_input.delta_yaw;
+    pending_input.delta_pitch -= consumed_input.delta_pitch;
+}
+
+#[profiling::function]
+fn run_simulation(shared: &SimulationShared) {
+    profiling::register_thread!("Simulation");
+
+    let mut last_update = Instant::now();
+    while shared.running.load(Ordering::Acquire) {
+        let now = Instant::now();
+        let delta_time = now.duration_since(last_update).as_secs_f64();
+        last_update = now;
+
+        let time_until_next_tick;
+        {
+            let mut ecs_world = shared.ecs_world.lock().unwrap();
+            let movement_input = shared.movement_input.lock().unwrap().clone();
+            let ticks = ecs_world.simulate(delta_time, movement_input.clone());
+            if ticks > 0 {
+                consume_mouse_movement(&mut shared.movement_input.lock().unwrap(), &movement_input);
+                *shared.latest_snapshot.lock().unwrap() = Some(Arc::new(ecs_world.snapshot()));
+            }
+            let timestep = ecs_world.timestep();
+            time_until_next_tick = (1.0 - timestep.alpha() as f64) * timestep.tick_duration();
+        }
+        thread::sleep(Duration::from_secs_f64(time_until_next_tick.max(0.0)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mouse_input(delta_yaw: f32, delta_pitch: f32) -> MovementInput {
+        let mut movement_input = MovementInput::new();
+        movement_input.delta_yaw = delta_yaw;
+        movement_input.delta_pitch = delta_pitch;
+        return movement_input;
+    }
+
+    #[test]
+    fn accumulates_mouse_movement_and_replaces_keys() {
+        let mut pending_input = MovementInput::new();
+        let mut first = mouse_input(1.0, -2.0);
+        first.forward = true;
+        merge_input(&mut pending_input, &first);
+        let mut second = mouse_input(0.5, 0.5);
+        second.left = true;
+        merge_input(&mut pending_input, &second);
+
+        assert!(!pending_input.forward);
+        assert!(pending_input.left);
+        assert_eq!(pending_input.delta_yaw, 1.5);
+        assert_eq!(pending_input.delta_pitch, -1.5);
+    }
+
+    #[test]
+    fn keeps_mouse_movement_pushed_during_ticks() {
+        let mut pending_input = mouse_input(1.0, 2.0);
+        // The ticks consume the input as it was when they started
+        let consumed_input = pending_input.clone();
+        merge_input(&mut pending_input, &mouse_input(0.25, -0.5));
+        consume_mouse_movement(&mut pending_input, &consumed_input);
+
+        assert_eq!(pending_input.delta_yaw, 0.25);
+        assert_eq!(pending_input.delta_pitch, -0.5);
+
+        consume_mouse_movement(&mut pending_input, &pending_input.clone());
+        assert_eq!(pending_input.delta_yaw, 0.0);
+        assert_eq!(pending_input.delta_pitch, 0.0);
+    }
+}
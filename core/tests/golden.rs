@@ -0,0 +1,28 @@
+use std::path::Path;
+use dyngine_core::golden::{run_golden_test, GoldenOutcome};
+
+/// Renders the scene golden/[name].ron and compares it against golden/[name].png.
+fn check_golden_scene(name: &str) {
+    let golden_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let scene_path = golden_dir.join(format!("{}.ron", name));
+    let reference_path = golden_dir.join(format!("{}.png", name));
+    let output_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+
+    match run_golden_test(&scene_path, &reference_path, &output_dir) {
+        Ok(GoldenOutcome::Matched { .. }) => {}
+        Ok(GoldenOutcome::ReferenceWritten) => {
+            eprintln!("Wrote reference image {}, review and commit it", reference_path.display());
+        }
+        Ok(GoldenOutcome::Skipped) => {
+            eprintln!("Skipped golden test {}: no graphics adapter available", name);
+        }
+        Err(error) => panic!("Golden test {} failed: {}", name, error),
+    }
+}
+
+// The reference image has to be rendered on a machine with a graphics adapter, see UPDATE_GOLDEN_ENV_VAR
+#[test]
+#[ignore = "no reference image yet, render it with DYNGINE_UPDATE_GOLDEN=1 cargo test --test golden -- --ignored"]
+fn box_on_ground() {
+    check_golden_scene("box_on_ground");
+}
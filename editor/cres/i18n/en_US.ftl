@@ -4,6 +4,7 @@ menubar-file-new-project = Project
 menubar-file-open = Open
 menubar-file-open-project = Project
 menubar-file-saveall = Save All
+menubar-file-screenshot = Save Screenshot
 menubar-edit = Edit
 menubar-edit-undo = Undo
 menubar-edit-redo = Redo
@@ -15,4 +16,10 @@ menubar-edit-find = Find
 menubar-edit-find-find = Find
 menubar-edit-find-replace = Replace
 menubar-edit-find-findinfiles = Find in Files
-menubar-edit-find-replaceinfiles = Find in Files
\ No newline at end of file
+menubar-edit-find-replaceinfiles = Find in Files
+panel-render-stats = Render Statistics
+render-stats-total-nodes = Render nodes
+render-stats-visible-nodes = Visible
+render-stats-culled-nodes = Culled
+render-stats-skipped-nodes = Not updated
+render-stats-shadow-caster-draws = Shadow caster draws
\ No newline at end of file
@@ -8,12 +8,17 @@ use crate::i18n::Translator;
 
 use dyngine_core::engine::{EngineInstance, ViewportRegion};
 
+/// The scene file of the project, relative to the working directory.
+const PROJECT_SCENE_PATH: &str = "scene.ron";
+
 pub struct EngineApp {
     engine_instance: Rc<RefCell<EngineInstance>>,
     translator: Rc<Translator>,
     pub(crate) viewport_region: ViewportRegion,
     pub(crate) frame_time: Duration,
     pub(crate) fps_average_window: VecDeque<u32>,
+    /// Set when a screenshot of the viewport was requested, taken after the next engine render.
+    pub(crate) screenshot_requested: bool,
 }
 
 impl EngineApp {
@@ -24,6 +29,7 @@ impl EngineApp {
             viewport_region: ViewportRegion::ZERO,
             frame_time: Duration::new(0, 0),
             fps_average_window: VecDeque::new(),
+            screenshot_requested: false,
         };
     }
 }
@@ -58,11 +64,21 @@ impl epi::App for EngineApp {
                         });
                         ui.menu_button(self.translator.format("menubar-file-open", None).unwrap(), |ui| {
                             if ui.button(self.translator.format("menubar-file-open-project", None).unwrap()).clicked() {
-                                println!("Open Project");
+                                match engine_instance.load_scene(PROJECT_SCENE_PATH) {
+                                    Ok(()) => println!("Opened {}", PROJECT_SCENE_PATH),
+                                    Err(error) => eprintln!("Failed to open {}: {}", PROJECT_SCENE_PATH, error),
+                                }
                             }
                         });
                         if ui.button(self.translator.format("menubar-file-saveall", None).unwrap()).clicked() {
-                            println!("Save All");
+                            match engine_instance.save_scene(PROJECT_SCENE_PATH) {
+                                Ok(()) => println!("Saved {}", PROJECT_SCENE_PATH),
+                                Err(error) => eprintln!("Failed to save {}: {}", PROJECT_SCENE_PATH, error),
+                            }
+                        }
+                        ui.separator();
+                        if ui.button(self.translator.format("menubar-file-screenshot", None).unwrap()).clicked() {
+                            self.screenshot_requested = true;
                         }
                     });
                     ui.menu_button(self.translator.format("menubar-edit", None).unwrap(), |ui| {
@@ -115,6 +131,16 @@ impl epi::App for EngineApp {
                     .show(ui, |ui| {
                         ui.label("Sub Label 1");
                     });
+                if let Some(culling_stats) = engine_instance.culling_stats() {
+                    egui::CollapsingHeader::new(self.translator.format("panel-render-stats", None).unwrap())
+                        .show(ui, |ui| {
+                            ui.label(format!("{}: {}", self.translator.format("render-stats-total-nodes", None).unwrap(), culling_stats.total_nodes));
+                            ui.label(format!("{}: {}", self.translator.format("render-stats-visible-nodes", None).unwrap(), culling_stats.visible_nodes));
+                            ui.label(format!("{}: {}", self.translator.format("render-stats-culled-nodes", None).unwrap(), culling_stats.culled_nodes));
+                            ui.label(format!("{}: {}", self.translator.format("render-stats-skipped-nodes", None).unwrap(), culling_stats.skipped_nodes));
+                            ui.label(format!("{}: {}", self.translator.format("render-stats-shadow-caster-draws", None).unwrap(), culling_stats.shadow_caster_draws));
+                        });
+                }
             });
         egui::CentralPanel::default()
             .frame(Frame {
@@ -2,6 +2,7 @@ use std::iter;
 use std::cell::RefCell;
 use std::ops::{Deref};
 use std::rc::Rc;
+use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
 
 use egui::{Color32, FontDefinitions, Style, TextStyle, Visuals};
@@ -20,6 +21,7 @@ use winit::event::Event::UserEvent;
 use winit::event_loop::ControlFlow;
 use winit::window::WindowBuilder;
 
+use dyngine_core::capture;
 use dyngine_core::engine::{EngineInstance, ViewportRegion};
 
 use crate::gui::EngineApp;
@@ -69,13 +71,13 @@ async fn run(event_loop: EventLoop<ExampleEvent>, window: Window) {
                 None,
             ).await
             .expect("Failed to create device");
-        device = Rc::new(d);
-        queue = Rc::new(q);
+        device = Arc::new(d);
+        queue = Arc::new(q);
     }
 
     let surface_format = surface.get_preferred_format(&adapter).unwrap();
 
-    let surface_config: Rc<RefCell<SurfaceConfiguration>> = Rc::new(RefCell::new(wgpu::SurfaceConfiguration {
+    let surface_config: Arc<RwLock<SurfaceConfiguration>> = Arc::new(RwLock::new(wgpu::SurfaceConfiguration {
         usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
         format: surface_format,
         width: size.width,
@@ -106,8 +108,9 @@ async fn run(event_loop: EventLoop<ExampleEvent>, window: Window) {
     let mut multisampled_frame_buffer = create_multi_sampled_frame_buffer(&device, &size, engine_instance.borrow().multisample_state.count, surface_format);
 
     engine_instance.borrow_mut().start();
+    engine_instance.borrow_mut().start_simulation_thread();
 
-    surface.configure(&device, surface_config.borrow_mut().deref());
+    surface.configure(&device, surface_config.write().unwrap().deref());
 
     let repaint_signal = std::sync::Arc::new(ExampleRepaintSignal(std::sync::Mutex::new(
         event_loop.create_proxy(),
@@ -212,9 +215,9 @@ async fn run(event_loop: EventLoop<ExampleEvent>, window: Window) {
             } => match event {
                 WindowEvent::Resized(size) => {
                     if size.width > 0 && size.height > 0 {
-                        let mut surface_config_mut = surface_config.borrow_mut();
                         // Resize surface
                         {
+                            let mut surface_config_mut = surface_config.write().unwrap();
                             surface_config_mut.width = size.width;
                             surface_config_mut.height = size.height;
                             surface.configure(&device, surface_config_mut.deref());
@@ -226,13 +229,14 @@ async fn run(event_loop: EventLoop<ExampleEvent>, window: Window) {
                         }
 
                         // Resize engine
+                        // (the engine reads the surface configuration, so it must not be borrowed here)
                         {
                             let scale_factor = window.scale_factor() as f32;
                             let scaled_viewport_region = ViewportRegion {
                                 x: 0.0,
                                 y: 0.0,
-                                width: surface_config_mut.width as f32 * scale_factor,
-                                height: surface_config_mut.height as f32 * scale_factor,
+                                width: size.width as f32 * scale_factor,
+                                height: size.height as f32 * scale_factor,
                             };
                             engine_instance.borrow_mut().resize(&scaled_viewport_region);
                         }
@@ -307,7 +311,7 @@ async fn run(event_loop: EventLoop<ExampleEvent>, window: Window) {
                         window.set_cursor_grab(true).unwrap();
                         grabbed_cursor = true;
                     }
-                    // window.set_cursor_position(PhysicalPosition::new(surface_config.borrow().width / 2, surface_config.borrow().height / 2)).unwrap();
+                    // window.set_cursor_position(PhysicalPosition::new(surface_config.read().unwrap().width / 2, surface_config.read().unwrap().height / 2)).unwrap();
                 } else {
                     if grabbed_cursor {
                         window.set_cursor_grab(false).unwrap();
@@ -333,11 +337,20 @@ async fn run(event_loop: EventLoop<ExampleEvent>, window: Window) {
                         height: viewport_region.height * scale_factor,
                     };
 
-                    let primary_camera = engine_instance.borrow_mut().engine_core_state.as_ref().unwrap().ecs_world.get_primary_camera().unwrap();
+                    let primary_camera = engine_instance.borrow().primary_camera().unwrap();
                     let delta_time = last_frame_time.as_secs_f64();
 
                     engine_instance.borrow_mut().render(&mut command_encoder, &viewport_view, Some(&multisampled_frame_buffer), &scaled_viewport_region, primary_camera, delta_time);
                     queue.submit(Some(command_encoder.finish()));
+
+                    if egui_app.screenshot_requested {
+                        egui_app.screenshot_requested = false;
+                        let path = capture::screenshot_file_name();
+                        match engine_instance.borrow_mut().save_screenshot(&scaled_viewport_region, &path) {
+                            Ok(()) => println!("Saved screenshot to {}", path.display()),
+                            Err(error) => eprintln!("Failed to save screenshot: {}", error),
+                        }
+                    }
                 }
 
                 // egui render
@@ -378,7 +391,7 @@ async fn run(event_loop: EventLoop<ExampleEvent>, window: Window) {
                         &wgpu::CommandEncoderDescriptor { label: Some("EguiRender") }
                     );
 
-                    let surface_config_mut = surface_config.borrow_mut();
+                    let surface_config_mut = surface_config.write().unwrap();
                     let screen_descriptor = ScreenDescriptor {
                         physical_width: surface_config_mut.width,
                         physical_height: surface_config_mut.height,
@@ -0,0 +1,352 @@
+use std::fmt;
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+use rapier3d::math::{Isometry, Point, Real, Vector};
+use rapier3d::na::{DMatrix, Vector3};
+use rapier3d::parry::transformation::vhacd::VHACDParameters;
+use rapier3d::prelude::SharedShape;
+
+/// The shape of a [crate::PhysicsObject].
+/// Shapes are relative to the body they are attached to. The mass properties of the body are computed from the
+/// shape and the density of the body.
+pub trait Collider: Debug + Send + Sync {
+    fn shape(&self) -> SharedShape;
+}
+
+/// An indexed triangle list that mesh based colliders are built from, eg. the geometry of a loaded model.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ColliderMesh {
+    pub vertices: Vec<Point<Real>>,
+    pub indices: Vec<[u32; 3]>,
+}
+
+impl ColliderMesh {
+    /// [indices] is a triangle list into [positions].
+    pub fn new(positions: &[[Real; 3]], indices: &[u32]) -> Self {
+        let mut mesh = ColliderMesh::default();
+        mesh.append(positions, indices);
+        return mesh;
+    }
+
+    /// Adds the triangles of [indices] into [positions] to the mesh.
+    /// A trailing incomplete triangle is ignored.
+    pub fn append(&mut self, positions: &[[Real; 3]], indices: &[u32]) {
+        let first_index = self.vertices.len() as u32;
+        self.vertices.extend(positions.iter().map(|position| Point::from(*position)));
+        self.indices.extend(indices.chunks_exact(3)
+            .map(|triangle| [first_index + triangle[0], first_index + triangle[1], first_index + triangle[2]]));
+    }
+
+    /// Whether the mesh has at least one triangle and all indices refer to one of its vertices.
+    /// Colliders cannot be built from other meshes.
+    pub fn is_valid(&self) -> bool {
+        let vertex_count = self.vertices.len();
+        return !self.indices.is_empty()
+            && self.indices.iter().flatten().all(|index| (*index as usize) < vertex_count);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CubeCollider {
+    pub dimension: Vector3<f32>,
+}
+
+impl Collider for CubeCollider {
+    fn shape(&self) -> SharedShape {
+        return SharedShape::cuboid(self.dimension.x / 2.0, self.dimension.y / 2.0, self.dimension.z / 2.0);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SphereCollider {
+    pub radius: f32,
+}
+
+impl Collider for SphereCollider {
+    fn shape(&self) -> SharedShape {
+        return SharedShape::ball(self.radius);
+    }
+}
+
+/// A cylinder with hemispherical caps along the Y axis.
+/// [half_height] is the distance from the center to the center of either cap.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapsuleCollider {
+    pub half_height: f32,
+    pub radius: f32,
+}
+
+impl Collider for CapsuleCollider {
+    fn shape(&self) -> SharedShape {
+        return SharedShape::capsule_y(self.half_height, self.radius);
+    }
+}
+
+/// A cylinder along the Y axis.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CylinderCollider {
+    pub half_height: f32,
+    pub radius: f32,
+}
+
+impl Collider for CylinderCollider {
+    fn shape(&self) -> SharedShape {
+        return SharedShape::cylinder(self.half_height, self.radius);
+    }
+}
+
+/// A cone along the Y axis, with its base at -[half_height] and its tip at +[half_height].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConeCollider {
+    pub half_height: f32,
+    pub radius: f32,
+}
+
+impl Collider for ConeCollider {
+    fn shape(&self) -> SharedShape {
+        return SharedShape::cone(self.half_height, self.radius);
+    }
+}
+
+/// A grid of heights in the XZ plane, centered at the origin. Has no mass, so it is meant for static bodies.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeightfieldCollider {
+    heights: DMatrix<Real>,
+    scale: Vector<Real>,
+}
+
+impl HeightfieldCollider {
+    /// [heights] contains [rows] rows of [columns] heights each, one row after the other.
+    /// None if there are less than two rows or columns, or if [heights] does not contain [rows] * [columns] heights.
+    pub fn from_rows(rows: usize, columns: usize, heights: &[Real], scale: Vector<Real>) -> Option<Self> {
+        if rows < 2 || columns < 2 || rows.checked_mul(columns) != Some(heights.len()) {
+            return None;
+        }
+        return Some(HeightfieldCollider { heights: DMatrix::from_row_slice(rows, columns, heights), scale });
+    }
+
+    /// The height of each grid point. Rows are along the Z axis, columns along the X axis.
+    pub fn heights(&self) -> &DMatrix<Real> {
+        return &self.heights;
+    }
+
+    /// The size of the whole grid along X and Z, and the factor the heights are multiplied by along Y.
+    pub fn scale(&self) -> Vector<Real> {
+        return self.scale;
+    }
+}
+
+impl Collider for HeightfieldCollider {
+    fn shape(&self) -> SharedShape {
+        return SharedShape::heightfield(self.heights.clone(), self.scale);
+    }
+}
+
+/// The triangles of a mesh as they are. Meant for static bodies, as triangle meshes have no volume.
+/// Use a [ConvexHullCollider] or a [ConvexDecompositionCollider] for dynamic bodies.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TriMeshCollider {
+    mesh: ColliderMesh,
+}
+
+impl TriMeshCollider {
+    /// None if [mesh] is not valid, see [ColliderMesh::is_valid()].
+    pub fn new(mesh: ColliderMesh) -> Option<Self> {
+        if !mesh.is_valid() {
+            return None;
+        }
+        return Some(TriMeshCollider { mesh });
+    }
+
+    pub fn mesh(&self) -> &ColliderMesh {
+        return &self.mesh;
+    }
+}
+
+impl Collider for TriMeshCollider {
+    fn shape(&self) -> SharedShape {
+        return SharedShape::trimesh(self.mesh.vertices.clone(), self.mesh.indices.clone());
+    }
+}
+
+/// The smallest convex shape containing a set of points, computed once on construction.
+#[derive(Clone)]
+pub struct ConvexHullCollider {
+    hull: SharedShape,
+}
+
+impl ConvexHullCollider {
+    /// None if the points do not span a volume, eg. if all of them lie on a plane.
+    pub fn from_points(points: &[Point<Real>]) -> Option<Self> {
+        let hull = SharedShape::convex_hull(points)?;
+        return Some(ConvexHullCollider { hull });
+    }
+
+    /// The convex hull of the vertices of [mesh], see [Self::from_points()].
+    pub fn from_mesh(mesh: &ColliderMesh) -> Option<Self> {
+        return Self::from_points(&mesh.vertices);
+    }
+}
+
+impl Debug for ConvexHullCollider {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let vertex_count = self.hull.as_convex_polyhedron().map_or(0, |hull| hull.points().len());
+        return f.debug_struct("ConvexHullCollider").field("vertex_count", &vertex_count).finish();
+    }
+}
+
+impl Collider for ConvexHullCollider {
+    fn shape(&self) -> SharedShape {
+        return self.hull.clone();
+    }
+}
+
+/// Approximates a concave mesh by a set of convex parts, computed once on construction with V-HACD.
+/// Unlike a [TriMeshCollider], the result has a volume and is suitable for dynamic bodies.
+#[derive(Clone)]
+pub struct ConvexDecompositionCollider {
+    parts: SharedShape,
+}
+
+impl ConvexDecompositionCollider {
+    /// None if [mesh] is not valid, see [ColliderMesh::is_valid()].
+    pub fn from_mesh(mesh: &ColliderMesh) -> Option<Self> {
+        return Self::from_mesh_with_parameters(mesh, &VHACDParameters::default());
+    }
+
+    /// Decomposes [mesh] with the given V-HACD [parameters], eg. to limit the number of parts or to trade
+    /// accuracy for speed.
+    /// None if [mesh] is not valid, see [ColliderMesh::is_valid()].
+    pub fn from_mesh_with_parameters(mesh: &ColliderMesh, parameters: &VHACDParameters) -> Option<Self> {
+        if !mesh.is_valid() {
+            return None;
+        }
+        let parts = SharedShape::convex_decomposition_with_params(&mesh.vertices, &mesh.indices, parameters);
+        return Some(ConvexDecompositionCollider { parts });
+    }
+
+    /// The number of convex parts the mesh was decomposed into.
+    pub fn part_count(&self) -> usize {
+        return self.parts.as_compound().map_or(0, |compound| compound.shapes().len());
+    }
+}
+
+impl Debug for ConvexDecompositionCollider {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        return f.debug_struct("ConvexDecompositionCollider").field("part_count", &self.part_count()).finish();
+    }
+}
+
+impl Collider for ConvexDecompositionCollider {
+    fn shape(&self) -> SharedShape {
+        return self.parts.clone();
+    }
+}
+
+/// Several colliders, each placed relative to the body. There is always at least one part.
+/// Parts that are compounds themselves, eg. a [ConvexDecompositionCollider], are flattened into this compound.
+#[derive(Debug, Clone)]
+pub struct CompoundCollider {
+    parts: Vec<(Isometry<Real>, Arc<dyn Collider>)>,
+}
+
+impl CompoundCollider {
+    /// A compound of [collider] placed at [position] relative to the body, see [Self::with_part()] to add more parts.
+    pub fn new<C: Collider + 'static>(position: Isometry<Real>, collider: C) -> Self {
+        return CompoundCollider { parts: vec![(position, Arc::new(collider))] };
+    }
+
+    /// Adds [collider] placed at [position] relative to the body.
+    pub fn with_part<C: Collider + 'static>(mut self, position: Isometry<Real>, collider: C) -> Self {
+        self.parts.push((position, Arc::new(collider)));
+        return self;
+    }
+
+    pub fn parts(&self) -> &[(Isometry<Real>, Arc<dyn Collider>)] {
+        return &self.parts;
+    }
+}
+
+impl Collider for CompoundCollider {
+    fn shape(&self) -> SharedShape {
+        let mut shapes = Vec::new();
+        for (position, collider) in &self.parts {
+            let shape = collider.shape();
+            // Compounds cannot be nested
+            match shape.as_compound() {
+                Some(compound) => {
+                    for (part_position, part_shape) in compound.shapes() {
+                        shapes.push((position * part_position, part_shape.clone()));
+                    }
+                }
+                None => shapes.push((*position, shape)),
+            }
+        }
+        return SharedShape::compound(shapes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A closed unit cube centered at the origin.
+    fn cube_mesh() -> ColliderMesh {
+        let positions = [
+            [-0.5, -0.5, -0.5], [0.5, -0.5, -0.5], [0.5, 0.5, -0.5], [-0.5, 0.5, -0.5],
+            [-0.5, -0.5, 0.5], [0.5, -0.5, 0.5], [0.5, 0.5, 0.5], [-0.5, 0.5, 0.5],
+        ];
+        let indices = [
+            0, 2, 1, 0, 3, 2,
+            4, 5, 6, 4, 6, 7,
+            0, 1, 5, 0, 5, 4,
+            3, 6, 2, 3, 7, 6,
+            0, 4, 7, 0, 7, 3,
+            1, 2, 6, 1, 6, 5,
+        ];
+        return ColliderMesh::new(&positions, &indices);
+    }
+
+    #[test]
+    fn appends_mesh_triangles() {
+        let mut mesh = ColliderMesh::new(&[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]], &[0, 1, 2]);
+        mesh.append(&[[0.0, 0.0, 1.0], [1.0, 0.0, 1.0], [0.0, 1.0, 1.0]], &[0, 2, 1, 0]);
+
+        assert_eq!(mesh.vertices.len(), 6);
+        assert_eq!(mesh.indices, vec![[0, 1, 2], [3, 5, 4]]);
+    }
+
+    #[test]
+    fn computes_convex_hull_of_mesh() {
+        let mut mesh = cube_mesh();
+        // A vertex inside the cube is not part of the hull
+        mesh.append(&[[0.0, 0.0, 0.0]], &[]);
+
+        let hull = ConvexHullCollider::from_mesh(&mesh).unwrap();
+        assert_eq!(hull.shape().as_convex_polyhedron().unwrap().points().len(), 8);
+    }
+
+    #[test]
+    fn rejects_input_without_shape() {
+        assert!(HeightfieldCollider::from_rows(2, 2, &[0.0; 4], Vector::new(1.0, 1.0, 1.0)).is_some());
+        assert!(HeightfieldCollider::from_rows(2, 3, &[0.0; 4], Vector::new(1.0, 1.0, 1.0)).is_none());
+        assert!(HeightfieldCollider::from_rows(1, 4, &[0.0; 4], Vector::new(1.0, 1.0, 1.0)).is_none());
+
+        assert!(TriMeshCollider::new(cube_mesh()).is_some());
+        assert!(TriMeshCollider::new(ColliderMesh::default()).is_none());
+        // A triangle referring to a missing vertex
+        assert!(TriMeshCollider::new(ColliderMesh::new(&[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0]], &[0, 1, 2])).is_none());
+        assert!(ConvexDecompositionCollider::from_mesh(&ColliderMesh::default()).is_none());
+    }
+
+    #[test]
+    fn flattens_nested_compounds() {
+        let decomposition = ConvexDecompositionCollider::from_mesh(&cube_mesh()).unwrap();
+        assert!(decomposition.part_count() >= 1);
+
+        let compound = CompoundCollider::new(Isometry::translation(0.0, 1.0, 0.0), CapsuleCollider { half_height: 0.5, radius: 0.25 })
+            .with_part(Isometry::identity(), decomposition.clone());
+        let shape = compound.shape();
+        assert_eq!(shape.as_compound().unwrap().shapes().len(), 1 + decomposition.part_count());
+    }
+}
@@ -0,0 +1,175 @@
+use std::sync::Mutex;
+use rapier3d::math::Real;
+use rapier3d::prelude::{ColliderHandle, ContactEvent, ContactPair, EventHandler, IntersectionEvent, RigidBodyHandle};
+use crate::PhysicsWorld;
+
+/// A collider taking part in a [PhysicsEvent].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EventCollider {
+    pub collider: ColliderHandle,
+    /// The body the collider is attached to. None if the collider has been removed before the step.
+    pub rigid_body: Option<RigidBodyHandle>,
+}
+
+/// Something that happened between two colliders during a [PhysicsWorld::step()], see [PhysicsWorld::events()].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PhysicsEvent {
+    /// Two solid colliders started touching.
+    ContactStarted { collider1: EventCollider, collider2: EventCollider },
+    /// Two solid colliders stopped touching, or one of them has been removed.
+    ContactStopped { collider1: EventCollider, collider2: EventCollider },
+    /// [collider] started intersecting [sensor], see [crate::BodySettings::is_sensor].
+    SensorEntered { sensor: EventCollider, collider: EventCollider },
+    /// [collider] stopped intersecting [sensor], or one of them has been removed.
+    SensorExited { sensor: EventCollider, collider: EventCollider },
+    /// The force between two touching colliders exceeded the contact force threshold of either of them, see
+    /// [crate::BodySettings::contact_force_threshold]. Reported every step while it is exceeded.
+    /// unit: [force] is in mass times world units per second squared
+    ContactForce { collider1: EventCollider, collider2: EventCollider, force: Real },
+}
+
+/// Collects the events Rapier reports while stepping, which may happen on several threads.
+#[derive(Default)]
+pub(crate) struct EventCollector {
+    contact_events: Mutex<Vec<ContactEvent>>,
+    intersection_events: Mutex<Vec<IntersectionEvent>>,
+}
+
+impl EventHandler for EventCollector {
+    fn handle_intersection_event(&self, event: IntersectionEvent) {
+        self.intersection_events.lock().unwrap().push(event);
+    }
+
+    fn handle_contact_event(&self, event: ContactEvent, _contact_pair: &ContactPair) {
+        self.contact_events.lock().unwrap().push(event);
+    }
+}
+
+impl PhysicsWorld {
+    /// The events of the last [Self::step()], in no particular order.
+    pub fn events(&self) -> &[PhysicsEvent] {
+        return &self.events;
+    }
+
+    /// Replaces the events with the ones collected during the step that just finished, plus the contact force
+    /// events of the step.
+    pub(crate) fn collect_events(&mut self, dt: f32) {
+        self.events.clear();
+
+        let contact_events: Vec<ContactEvent> = self.event_collector.contact_events.lock().unwrap().drain(..).collect();
+        for contact_event in contact_events {
+            let event = match contact_event {
+                ContactEvent::Started(collider1, collider2) => PhysicsEvent::ContactStarted {
+                    collider1: self.event_collider(collider1),
+                    collider2: self.event_collider(collider2),
+                },
+                ContactEvent::Stopped(collider1, collider2) => PhysicsEvent::ContactStopped {
+                    collider1: self.event_collider(collider1),
+                    collider2: self.event_collider(collider2),
+                },
+            };
+            self.events.push(event);
+        }
+
+        let intersection_events: Vec<IntersectionEvent> = self.event_collector.intersection_events.lock().unwrap().drain(..).collect();
+        for intersection_event in intersection_events {
+            // Whether a removed collider was the sensor is unknown, the other one is assumed to be the sensor then
+            let first_is_sensor = self.collider_set.get(intersection_event.collider1)
+                .map_or(false, |collider| collider.is_sensor());
+            let (sensor, collider) = if first_is_sensor {
+                (intersection_event.collider1, intersection_event.collider2)
+            } else {
+                (intersection_event.collider2, intersection_event.collider1)
+            };
+            let sensor = self.event_collider(sensor);
+            let collider = self.event_collider(collider);
+            self.events.push(if intersection_event.intersecting {
+                PhysicsEvent::SensorEntered { sensor, collider }
+            } else {
+                PhysicsEvent::SensorExited { sensor, collider }
+            });
+        }
+
+        if !self.contact_force_thresholds.is_empty() && dt > 0.0 {
+            let mut force_events = Vec::new();
+            for contact_pair in self.narrow_phase.contact_pairs() {
+                let threshold = [contact_pair.collider1, contact_pair.collider2].iter()
+                    .filter_map(|collider| self.contact_force_thresholds.get(collider).copied())
+                    .reduce(Real::min);
+                let threshold = match threshold {
+                    Some(threshold) if contact_pair.has_any_active_contact => threshold,
+                    _ => continue,
+                };
+                let impulse: Real = contact_pair.manifolds.iter()
+                    .flat_map(|manifold| manifold.points.iter())
+                    .map(|point| point.data.impulse)
+                    .sum();
+                let force = impulse / dt;
+                if force > threshold {
+                    force_events.push(PhysicsEvent::ContactForce {
+                        collider1: self.event_collider(contact_pair.collider1),
+                        collider2: self.event_collider(contact_pair.collider2),
+                        force,
+                    });
+                }
+            }
+            self.events.extend(force_events);
+        }
+    }
+
+    fn event_collider(&self, collider: ColliderHandle) -> EventCollider {
+        return EventCollider {
+            collider,
+            rigid_body: self.collider_set.get(collider).and_then(|collider| collider.parent()),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use rapier3d::math::Vector;
+    use crate::{CubeCollider, PhysicsObject, SphereCollider};
+    use super::*;
+
+    #[test]
+    fn reports_contacts_and_sensor_intersections() {
+        let mut physics_world = PhysicsWorld::new();
+        let ground = PhysicsObject::builder(Arc::new(CubeCollider { dimension: Vector::new(10.0, 1.0, 10.0) }))
+            .with_static(true)
+            .with_contact_force_threshold(Some(0.0))
+            .build(&mut physics_world);
+        let trigger = PhysicsObject::builder(Arc::new(CubeCollider { dimension: Vector::new(1.0, 1.0, 1.0) }))
+            .with_position(Vector::new(0.0, 3.0, 0.0))
+            .with_static(true)
+            .with_sensor(true)
+            .build(&mut physics_world);
+        let ball = PhysicsObject::builder(Arc::new(SphereCollider { radius: 0.5 }))
+            .with_position(Vector::new(0.0, 5.0, 0.0))
+            .with_restitution(0.0)
+            .build(&mut physics_world);
+
+        let mut events = Vec::new();
+        for _ in 0..120 {
+            physics_world.step(1.0 / 60.0);
+            events.extend_from_slice(physics_world.events());
+        }
+
+        let rigid_body = |event_collider: &EventCollider| event_collider.rigid_body.unwrap();
+        assert!(events.iter().any(|event| matches!(event,
+            PhysicsEvent::SensorEntered { sensor, collider } if rigid_body(sensor) == trigger.rigid_body_handle() && rigid_body(collider) == ball.rigid_body_handle())));
+        assert!(events.iter().any(|event| matches!(event,
+            PhysicsEvent::SensorExited { sensor, collider } if rigid_body(sensor) == trigger.rigid_body_handle() && rigid_body(collider) == ball.rigid_body_handle())));
+        let touches_ground = |collider1: &EventCollider, collider2: &EventCollider| {
+            let rigid_bodies = [rigid_body(collider1), rigid_body(collider2)];
+            return rigid_bodies.contains(&ground.rigid_body_handle()) && rigid_bodies.contains(&ball.rigid_body_handle());
+        };
+        assert!(events.iter().any(|event| matches!(event,
+            PhysicsEvent::ContactStarted { collider1, collider2 } if touches_ground(collider1, collider2))));
+        assert!(events.iter().any(|event| matches!(event,
+            PhysicsEvent::ContactForce { collider1, collider2, force } if touches_ground(collider1, collider2) && *force > 0.0)));
+        // The sensor never touches anything
+        assert!(!events.iter().any(|event| matches!(event,
+            PhysicsEvent::ContactStarted { collider1, collider2 } if [rigid_body(collider1), rigid_body(collider2)].contains(&trigger.rigid_body_handle()))));
+    }
+}
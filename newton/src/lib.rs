@@ -1,7 +1,9 @@
-use rapier3d::math::{AngVector, Real};
+use std::collections::HashMap;
+use std::sync::Arc;
+use rapier3d::math::{AngVector, Isometry, Real};
 use rapier3d::math::Vector;
 use rapier3d::na::Vector3;
-use rapier3d::prelude::{MassProperties, RigidBodyHandle as RapierRigidBodyHandle};
+use rapier3d::prelude::{ActiveEvents, ColliderHandle, InteractionGroups, RigidBodyHandle as RapierRigidBodyHandle};
 use rapier3d::prelude::RigidBodySet as RapierRigidBodySet;
 use rapier3d::prelude::ColliderSet as RapierColliderSet;
 use rapier3d::prelude::JointSet as RapierJointSet;
@@ -10,57 +12,143 @@ use rapier3d::prelude::IslandManager as RapierIslandManager;
 use rapier3d::prelude::BroadPhase as RapierBroadPhase;
 use rapier3d::prelude::NarrowPhase as RapierNarrowPhase;
 use rapier3d::prelude::CCDSolver as RapierCCDSolver;
+use rapier3d::prelude::QueryPipeline as RapierQueryPipeline;
 use rapier3d::prelude::IntegrationParameters as RapierIntegrationParameters;
 use rapier3d::prelude::RigidBodyType as RapierRigidBodyType;
 use rapier3d::prelude::RigidBodyBuilder as RapierRigidBodyBuilder;
+use rapier3d::prelude::RigidBodyActivation as RapierRigidBodyActivation;
 use rapier3d::prelude::ColliderBuilder as RapierColliderBuilder;
 
-struct PhysicsWorld {
+pub use rapier3d;
+pub use collider::*;
+pub use event::*;
+pub use query::*;
+
+pub mod collider;
+pub mod event;
+pub mod query;
+
+/// The gravity of a [PhysicsWorld] unless configured otherwise.
+/// unit: world units per second squared
+pub const DEFAULT_GRAVITY: [Real; 3] = [0.0, -9.81, 0.0];
+
+/// The rigid bodies and colliders simulated by Rapier, see [PhysicsObject].
+pub struct PhysicsWorld {
     rigid_body_set: RapierRigidBodySet,
     collider_set: RapierColliderSet,
     joint_set: RapierJointSet,
     gravity: Vector<Real>,
+    /// Kept between steps, only [RapierIntegrationParameters::dt] is updated by [PhysicsWorld::step()].
+    integration_parameters: RapierIntegrationParameters,
     physics_pipeline: RapierPhysicsPipeline,
     island_manager: RapierIslandManager,
     broad_phase: RapierBroadPhase,
     narrow_phase: RapierNarrowPhase,
     ccd_solver: RapierCCDSolver,
+    /// Updated after every step, see [PhysicsWorld::raycast()].
+    query_pipeline: RapierQueryPipeline,
+    event_collector: EventCollector,
+    /// The events of the last step.
+    events: Vec<PhysicsEvent>,
+    /// The colliders with a [BodySettings::contact_force_threshold].
+    contact_force_thresholds: HashMap<ColliderHandle, Real>,
 }
 
-impl PhysicsWorld {
-    pub fn new() -> Self {
-        let rigid_body_set = RapierRigidBodySet::new();
-        let collider_set = RapierColliderSet::new();
-        let joint_set = RapierJointSet::new();
+/// Configures the gravity and the solver of a [PhysicsWorld], see [PhysicsWorld::builder()].
+pub struct PhysicsWorldBuilder {
+    gravity: Vector<Real>,
+    integration_parameters: RapierIntegrationParameters,
+}
 
-        let physics_pipeline = RapierPhysicsPipeline::new();
-        let island_manager = RapierIslandManager::new();
-        let broad_phase = RapierBroadPhase::new();
-        let narrow_phase = RapierNarrowPhase::new();
-        let ccd_solver = RapierCCDSolver::new();
+impl PhysicsWorldBuilder {
+    /// unit: world units per second squared
+    pub fn with_gravity(mut self, gravity: Vector<Real>) -> Self {
+        self.gravity = gravity;
+        return self;
+    }
+
+    /// The number of velocity and position solver iterations per step.
+    /// More iterations make stacks and joints more stable at the cost of performance.
+    pub fn with_solver_iterations(mut self, velocity_iterations: usize, position_iterations: usize) -> Self {
+        self.integration_parameters.max_velocity_iterations = velocity_iterations;
+        self.integration_parameters.max_position_iterations = position_iterations;
+        return self;
+    }
 
+    /// Replaces all integration parameters. Their [RapierIntegrationParameters::dt] is overwritten by every step.
+    pub fn with_integration_parameters(mut self, integration_parameters: RapierIntegrationParameters) -> Self {
+        self.integration_parameters = integration_parameters;
+        return self;
+    }
+
+    pub fn build(self) -> PhysicsWorld {
         return PhysicsWorld {
-            rigid_body_set,
-            collider_set,
-            joint_set,
-            gravity: Vector::new(0.0, -9.81, 0.0),
-            physics_pipeline,
-            island_manager,
-            broad_phase,
-            narrow_phase,
-            ccd_solver,
+            rigid_body_set: RapierRigidBodySet::new(),
+            collider_set: RapierColliderSet::new(),
+            joint_set: RapierJointSet::new(),
+            gravity: self.gravity,
+            integration_parameters: self.integration_parameters,
+            physics_pipeline: RapierPhysicsPipeline::new(),
+            island_manager: RapierIslandManager::new(),
+            broad_phase: RapierBroadPhase::new(),
+            narrow_phase: RapierNarrowPhase::new(),
+            ccd_solver: RapierCCDSolver::new(),
+            query_pipeline: RapierQueryPipeline::new(),
+            event_collector: EventCollector::default(),
+            events: Vec::new(),
+            contact_force_thresholds: HashMap::new(),
+        };
+    }
+}
+
+impl PhysicsWorld {
+    /// A world with [DEFAULT_GRAVITY] and the default solver parameters of Rapier.
+    pub fn new() -> Self {
+        return PhysicsWorld::builder().build();
+    }
+
+    pub fn builder() -> PhysicsWorldBuilder {
+        return PhysicsWorldBuilder {
+            gravity: Vector::from(DEFAULT_GRAVITY),
+            integration_parameters: RapierIntegrationParameters::default(),
         };
     }
+
+    /// unit: world units per second squared
+    pub fn gravity(&self) -> Vector<Real> {
+        return self.gravity;
+    }
+
+    pub fn set_gravity(&mut self, gravity: Vector<Real>) {
+        self.gravity = gravity;
+    }
+
+    pub fn integration_parameters(&self) -> &RapierIntegrationParameters {
+        return &self.integration_parameters;
+    }
+
+    /// Allows tuning the solver between steps. [RapierIntegrationParameters::dt] is overwritten by every step.
+    pub fn integration_parameters_mut(&mut self) -> &mut RapierIntegrationParameters {
+        return &mut self.integration_parameters;
+    }
+}
+
+impl Default for PhysicsWorld {
+    fn default() -> Self {
+        return PhysicsWorld::new();
+    }
 }
 
 impl PhysicsWorld {
+    /// Advances all bodies by [dt]. The contacts and sensor intersections that started or stopped during the step
+    /// are reported by [PhysicsWorld::events()] until the next step.
+    /// unit: seconds
     pub fn step(&mut self, dt: f32) {
-        let mut integration_parameters = RapierIntegrationParameters::default();
-        integration_parameters.dt = dt;
+        self.integration_parameters.dt = dt;
 
         self.physics_pipeline.step(
             &self.gravity,
-            &integration_parameters,
+            &self.integration_parameters,
             &mut self.island_manager,
             &mut self.broad_phase,
             &mut self.narrow_phase,
@@ -69,32 +157,195 @@ impl PhysicsWorld {
             &mut self.joint_set,
             &mut self.ccd_solver,
             &(),
-            &(),
+            &self.event_collector,
         );
+        self.collect_events(dt);
+        self.query_pipeline.update(&self.island_manager, &self.rigid_body_set, &self.collider_set);
     }
 }
 
-trait Collider {}
-
-struct CubeCollider {
-    dimension: Vector3<f32>,
+/// How a [PhysicsObject] moves and collides.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BodySettings {
+    /// Static bodies are never moved by the simulation.
+    pub is_static: bool,
+    /// unit: mass per cubic world unit
+    pub density: f32,
+    /// The friction coefficient, combined with the one of the other collider by averaging.
+    pub friction: f32,
+    /// The bounciness, from 0 (no bounce) to 1 (no energy lost).
+    pub restitution: f32,
+    /// How quickly the linear and angular velocity decrease without contacts.
+    pub linear_damping: f32,
+    pub angular_damping: f32,
+    /// Continuous collision detection, which keeps fast bodies from tunneling through thin colliders.
+    pub ccd_enabled: bool,
+    /// The pseudo kinetic energy below which the body falls asleep, see [RapierRigidBodyActivation].
+    /// None if the body never sleeps.
+    pub sleep_threshold: Option<Real>,
+    /// The groups the body is a member of and the groups it collides with.
+    pub collision_groups: InteractionGroups,
+    /// Sensors detect the colliders intersecting them without touching them, eg. for trigger volumes.
+    /// See [PhysicsEvent::SensorEntered].
+    pub is_sensor: bool,
+    /// The contact force above which [PhysicsEvent::ContactForce] events are reported for the body.
+    /// None if they are never reported.
+    /// unit: mass times world units per second squared
+    pub contact_force_threshold: Option<Real>,
 }
 
-impl Collider for CubeCollider {}
+impl Default for BodySettings {
+    fn default() -> Self {
+        return BodySettings {
+            is_static: false,
+            density: 1.0,
+            friction: 0.5,
+            restitution: 0.7,
+            linear_damping: 0.0,
+            angular_damping: 0.0,
+            ccd_enabled: true,
+            sleep_threshold: Some(RapierRigidBodyActivation::default_threshold()),
+            collision_groups: InteractionGroups::all(),
+            is_sensor: false,
+            contact_force_threshold: None,
+        };
+    }
+}
 
-struct SphereCollider {
-    radius: f32,
+/// Configures a [PhysicsObject] before adding it to a [PhysicsWorld], see [PhysicsObject::builder()].
+pub struct PhysicsObjectBuilder {
+    collider: Arc<dyn Collider>,
+    position: Vector<f32>,
+    orientation: AngVector<f32>,
+    settings: BodySettings,
 }
 
-impl Collider for SphereCollider {}
+impl PhysicsObjectBuilder {
+    pub fn with_position(mut self, position: Vector<f32>) -> Self {
+        self.position = position;
+        return self;
+    }
+
+    /// [orientation] is the rotation axis scaled by the rotation angle in radians.
+    pub fn with_orientation(mut self, orientation: AngVector<f32>) -> Self {
+        self.orientation = orientation;
+        return self;
+    }
+
+    /// Replaces all settings configured so far.
+    pub fn with_settings(mut self, settings: BodySettings) -> Self {
+        self.settings = settings;
+        return self;
+    }
+
+    pub fn with_static(mut self, is_static: bool) -> Self {
+        self.settings.is_static = is_static;
+        return self;
+    }
+
+    pub fn with_density(mut self, density: f32) -> Self {
+        self.settings.density = density;
+        return self;
+    }
+
+    pub fn with_friction(mut self, friction: f32) -> Self {
+        self.settings.friction = friction;
+        return self;
+    }
+
+    pub fn with_restitution(mut self, restitution: f32) -> Self {
+        self.settings.restitution = restitution;
+        return self;
+    }
 
-struct PhysicsObject {
-    collider: Box<dyn Collider>,
+    pub fn with_damping(mut self, linear_damping: f32, angular_damping: f32) -> Self {
+        self.settings.linear_damping = linear_damping;
+        self.settings.angular_damping = angular_damping;
+        return self;
+    }
+
+    pub fn with_ccd(mut self, ccd_enabled: bool) -> Self {
+        self.settings.ccd_enabled = ccd_enabled;
+        return self;
+    }
+
+    /// None keeps the body from ever falling asleep.
+    pub fn with_sleep_threshold(mut self, sleep_threshold: Option<Real>) -> Self {
+        self.settings.sleep_threshold = sleep_threshold;
+        return self;
+    }
+
+    pub fn with_collision_groups(mut self, collision_groups: InteractionGroups) -> Self {
+        self.settings.collision_groups = collision_groups;
+        return self;
+    }
+
+    pub fn with_sensor(mut self, is_sensor: bool) -> Self {
+        self.settings.is_sensor = is_sensor;
+        return self;
+    }
+
+    pub fn with_contact_force_threshold(mut self, contact_force_threshold: Option<Real>) -> Self {
+        self.settings.contact_force_threshold = contact_force_threshold;
+        return self;
+    }
+
+    /// Adds the body and its collider to [physics_world].
+    pub fn build(self, physics_world: &mut PhysicsWorld) -> PhysicsObject {
+        let settings = &self.settings;
+        let mut rigid_body = RapierRigidBodyBuilder::new(if settings.is_static { RapierRigidBodyType::Static } else { RapierRigidBodyType::Dynamic })
+            .translation(Vector3::new(self.position.x, self.position.y, self.position.z))
+            .rotation(self.orientation)
+            .linear_damping(settings.linear_damping)
+            .angular_damping(settings.angular_damping)
+            .ccd_enabled(settings.ccd_enabled)
+            .can_sleep(settings.sleep_threshold.is_some())
+            .build();
+        if let Some(sleep_threshold) = settings.sleep_threshold {
+            rigid_body.activation_mut().threshold = sleep_threshold;
+        }
+
+        let collider = RapierColliderBuilder::new(self.collider.shape())
+            .density(settings.density)
+            .friction(settings.friction)
+            .restitution(settings.restitution)
+            .collision_groups(settings.collision_groups)
+            .sensor(settings.is_sensor)
+            .active_events(ActiveEvents::CONTACT_EVENTS | ActiveEvents::INTERSECTION_EVENTS)
+            .build();
+
+        let body_handle = physics_world.rigid_body_set.insert(rigid_body);
+        let collider_handle = physics_world.collider_set.insert_with_parent(collider, body_handle, &mut physics_world.rigid_body_set);
+        if let Some(contact_force_threshold) = settings.contact_force_threshold {
+            physics_world.contact_force_thresholds.insert(collider_handle, contact_force_threshold);
+        }
+
+        return PhysicsObject {
+            collider: self.collider,
+            mesh_handle: body_handle,
+        };
+    }
+}
+
+/// A rigid body with a single collider, added to a [PhysicsWorld].
+/// The body stays in the world until it is removed with [PhysicsObject::remove()].
+pub struct PhysicsObject {
+    collider: Arc<dyn Collider>,
     mesh_handle: RapierRigidBodyHandle,
 }
 
 impl PhysicsObject {
 
+    /// Starts configuring a dynamic body shaped by [collider] with the default [BodySettings], at the origin.
+    pub fn builder(collider: Arc<dyn Collider>) -> PhysicsObjectBuilder {
+        return PhysicsObjectBuilder {
+            collider,
+            position: Vector::zeros(),
+            orientation: AngVector::zeros(),
+            settings: BodySettings::default(),
+        };
+    }
+
     pub fn new_box(
         physics_world: &mut PhysicsWorld,
         density: f32,
@@ -103,24 +354,12 @@ impl PhysicsObject {
         orientation: AngVector<f32>,
         is_static: bool,
     ) -> Self {
-        let rigid_body = RapierRigidBodyBuilder::new(if is_static { RapierRigidBodyType::Static } else { RapierRigidBodyType::Dynamic })
-            .translation(Vector3::new(position.x, position.y, position.z))
-            .rotation(orientation)
-            .ccd_enabled(true)
-            .build();
-
-        let collider = RapierColliderBuilder::cuboid(dimension.x / 2.0, dimension.y / 2.0, dimension.z / 2.0)
-            .restitution(0.7)
-            .mass_properties(MassProperties::from_cuboid(density, dimension / 2.0))
-            .build();
-
-        let body_handle = physics_world.rigid_body_set.insert(rigid_body);
-        physics_world.collider_set.insert_with_parent(collider, body_handle, &mut physics_world.rigid_body_set);
-
-        return PhysicsObject {
-            collider: Box::new(CubeCollider { dimension }),
-            mesh_handle: body_handle,
-        };
+        return Self::builder(Arc::new(CubeCollider { dimension }))
+            .with_density(density)
+            .with_position(position)
+            .with_orientation(orientation)
+            .with_static(is_static)
+            .build(physics_world);
     }
 
     pub fn new_sphere(
@@ -131,24 +370,80 @@ impl PhysicsObject {
         orientation: AngVector<f32>,
         is_static: bool,
     ) -> Self {
-        let rigid_body = RapierRigidBodyBuilder::new(if is_static { RapierRigidBodyType::Static } else { RapierRigidBodyType::Dynamic })
-            .translation(Vector3::new(position.x, position.y, position.z))
-            .rotation(orientation)
-            .ccd_enabled(true)
-            .build();
+        return Self::builder(Arc::new(SphereCollider { radius }))
+            .with_density(density)
+            .with_position(position)
+            .with_orientation(orientation)
+            .with_static(is_static)
+            .build(physics_world);
+    }
 
-        let collider = RapierColliderBuilder::ball(radius)
-            .restitution(0.7)
-            .mass_properties(MassProperties::from_ball(density, radius))
-            .build();
+    pub fn collider(&self) -> &Arc<dyn Collider> {
+        return &self.collider;
+    }
 
-        let body_handle = physics_world.rigid_body_set.insert(rigid_body);
-        physics_world.collider_set.insert_with_parent(collider, body_handle, &mut physics_world.rigid_body_set);
+    pub fn rigid_body_handle(&self) -> RapierRigidBodyHandle {
+        return self.mesh_handle;
+    }
 
-        return PhysicsObject {
-            collider: Box::new(SphereCollider { radius }),
-            mesh_handle: body_handle,
-        };
+    /// The position and orientation of the body after the last step.
+    pub fn position(&self, physics_world: &PhysicsWorld) -> Isometry<Real> {
+        return *physics_world.rigid_body_set[self.mesh_handle].position();
+    }
+
+    /// Teleports the body to [position] and wakes it up.
+    pub fn set_position(&self, physics_world: &mut PhysicsWorld, position: Isometry<Real>) {
+        physics_world.rigid_body_set[self.mesh_handle].set_position(position, true);
+    }
+
+    /// unit: world units per second
+    pub fn linear_velocity(&self, physics_world: &PhysicsWorld) -> Vector<Real> {
+        return *physics_world.rigid_body_set[self.mesh_handle].linvel();
     }
 
-}
\ No newline at end of file
+    pub fn set_linear_velocity(&self, physics_world: &mut PhysicsWorld, linear_velocity: Vector<Real>) {
+        physics_world.rigid_body_set[self.mesh_handle].set_linvel(linear_velocity, true);
+    }
+
+    /// Removes the body and its collider from [physics_world].
+    /// Contacts and sensor intersections of the collider are reported as stopped by the next step.
+    pub fn remove(self, physics_world: &mut PhysicsWorld) {
+        for collider in physics_world.rigid_body_set[self.mesh_handle].colliders() {
+            physics_world.contact_force_thresholds.remove(collider);
+        }
+        physics_world.rigid_body_set.remove(
+            self.mesh_handle,
+            &mut physics_world.island_manager,
+            &mut physics_world.collider_set,
+            &mut physics_world.joint_set,
+        );
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uses_configured_gravity() {
+        let mut physics_world = PhysicsWorld::builder()
+            .with_gravity(Vector::new(0.0, 0.0, 0.0))
+            .with_solver_iterations(8, 2)
+            .build();
+        let floating = PhysicsObject::builder(Arc::new(SphereCollider { radius: 0.5 }))
+            .with_position(Vector::new(0.0, 5.0, 0.0))
+            .with_sleep_threshold(None)
+            .build(&mut physics_world);
+
+        for _ in 0..10 {
+            physics_world.step(1.0 / 60.0);
+        }
+        assert_eq!(floating.position(&physics_world).translation.vector, Vector::new(0.0, 5.0, 0.0));
+        assert_eq!(physics_world.integration_parameters().max_velocity_iterations, 8);
+
+        physics_world.set_gravity(Vector::from(DEFAULT_GRAVITY));
+        physics_world.step(1.0 / 60.0);
+        assert!(floating.position(&physics_world).translation.y < 5.0);
+    }
+}
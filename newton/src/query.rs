@@ -0,0 +1,193 @@
+use rapier3d::math::{Isometry, Point, Real, Vector};
+use rapier3d::prelude::{ColliderHandle, InteractionGroups, Ray, RigidBodyHandle};
+use crate::{Collider, PhysicsWorld};
+
+/// Restricts the colliders hit by the scene queries of a [PhysicsWorld].
+#[derive(Copy, Clone)]
+pub struct QueryFilter<'a> {
+    /// Only colliders whose collision groups interact with these groups are hit.
+    pub groups: InteractionGroups,
+    /// The colliders of this body are never hit, eg. the body of the character casting a ray.
+    pub excluded_body: Option<RigidBodyHandle>,
+    /// If set, only colliders attached to bodies it returns true for are hit.
+    pub predicate: Option<&'a dyn Fn(RigidBodyHandle) -> bool>,
+}
+
+impl<'a> QueryFilter<'a> {
+    /// A filter letting all colliders be hit.
+    pub fn new() -> Self {
+        return QueryFilter {
+            groups: InteractionGroups::all(),
+            excluded_body: None,
+            predicate: None,
+        };
+    }
+
+    pub fn with_groups(mut self, groups: InteractionGroups) -> Self {
+        self.groups = groups;
+        return self;
+    }
+
+    pub fn excluding(mut self, rigid_body: RigidBodyHandle) -> Self {
+        self.excluded_body = Some(rigid_body);
+        return self;
+    }
+
+    pub fn with_predicate(mut self, predicate: &'a dyn Fn(RigidBodyHandle) -> bool) -> Self {
+        self.predicate = Some(predicate);
+        return self;
+    }
+}
+
+impl<'a> Default for QueryFilter<'a> {
+    fn default() -> Self {
+        return QueryFilter::new();
+    }
+}
+
+/// The first collider hit by a ray or a shape cast.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct QueryHit {
+    pub collider: ColliderHandle,
+    /// The body the collider is attached to.
+    pub rigid_body: Option<RigidBodyHandle>,
+    /// The point where the collider was hit, in world space.
+    pub point: Point<Real>,
+    /// The surface normal of the collider at [QueryHit::point], in world space.
+    pub normal: Vector<Real>,
+    /// How far the ray or the shape travelled until the hit.
+    /// unit: world units
+    pub distance: Real,
+}
+
+/// A collider intersecting the shape of [PhysicsWorld::intersect_shape()].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ShapeOverlap {
+    pub collider: ColliderHandle,
+    /// The body the collider is attached to.
+    pub rigid_body: Option<RigidBodyHandle>,
+}
+
+/// Scene queries. They see the colliders as they were after the last [PhysicsWorld::step()], colliders added since
+/// are not hit yet.
+impl PhysicsWorld {
+    /// Casts a ray from [origin] along [direction] and returns the closest hit within [max_distance].
+    /// A ray starting inside a collider hits it at [origin], with a zero normal.
+    /// None if nothing is hit or [direction] is zero.
+    pub fn raycast(&self, origin: Point<Real>, direction: Vector<Real>, max_distance: Real, filter: &QueryFilter) -> Option<QueryHit> {
+        let direction = direction.try_normalize(Real::EPSILON)?;
+        let ray = Ray::new(origin, direction);
+        let predicate = |collider: ColliderHandle| self.is_hit_by_query(collider, filter);
+
+        let (collider, intersection) = self.query_pipeline.cast_ray_and_get_normal(
+            &self.collider_set,
+            &ray,
+            max_distance,
+            true,
+            filter.groups,
+            Some(&predicate),
+        )?;
+        return Some(QueryHit {
+            collider,
+            rigid_body: self.collider_set[collider].parent(),
+            point: ray.point_at(intersection.toi),
+            normal: intersection.normal,
+            distance: intersection.toi,
+        });
+    }
+
+    /// Moves the shape of [collider] from [position] along [direction] and returns the first collider it touches
+    /// within [max_distance]. [QueryHit::point] is the point of the hit collider touched first.
+    /// None if nothing is hit or [direction] is zero.
+    pub fn cast_shape(&self, collider: &dyn Collider, position: &Isometry<Real>, direction: Vector<Real>, max_distance: Real, filter: &QueryFilter) -> Option<QueryHit> {
+        let direction = direction.try_normalize(Real::EPSILON)?;
+        let shape = collider.shape();
+        let predicate = |collider: ColliderHandle| self.is_hit_by_query(collider, filter);
+
+        let (hit_collider, toi) = self.query_pipeline.cast_shape(
+            &self.collider_set,
+            position,
+            &direction,
+            &*shape,
+            max_distance,
+            filter.groups,
+            Some(&predicate),
+        )?;
+        // The hit collider is the first shape of the time of impact, its witness and normal are in world space
+        return Some(QueryHit {
+            collider: hit_collider,
+            rigid_body: self.collider_set[hit_collider].parent(),
+            point: toi.witness1,
+            normal: toi.normal1.into_inner(),
+            distance: toi.toi,
+        });
+    }
+
+    /// All colliders intersecting the shape of [collider] placed at [position].
+    pub fn intersect_shape(&self, collider: &dyn Collider, position: &Isometry<Real>, filter: &QueryFilter) -> Vec<ShapeOverlap> {
+        let shape = collider.shape();
+        let predicate = |collider: ColliderHandle| self.is_hit_by_query(collider, filter);
+
+        let mut overlaps = Vec::new();
+        self.query_pipeline.intersections_with_shape(
+            &self.collider_set,
+            position,
+            &*shape,
+            filter.groups,
+            Some(&predicate),
+            |collider| {
+                overlaps.push(ShapeOverlap { collider, rigid_body: self.collider_set[collider].parent() });
+                // Continue with the next intersection
+                return true;
+            },
+        );
+        return overlaps;
+    }
+
+    /// Whether [collider] passes the excluded body and the predicate of [filter]. The groups are checked by Rapier.
+    fn is_hit_by_query(&self, collider: ColliderHandle, filter: &QueryFilter) -> bool {
+        let rigid_body = self.collider_set[collider].parent();
+        if rigid_body.is_some() && rigid_body == filter.excluded_body {
+            return false;
+        }
+        return match filter.predicate {
+            Some(predicate) => rigid_body.map_or(false, predicate),
+            None => true,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::{CubeCollider, PhysicsObject, SphereCollider};
+    use super::*;
+
+    #[test]
+    fn raycast_respects_filter() {
+        let mut physics_world = PhysicsWorld::new();
+        let ground = PhysicsObject::builder(Arc::new(CubeCollider { dimension: Vector::new(10.0, 1.0, 10.0) }))
+            .with_static(true)
+            .with_collision_groups(InteractionGroups::new(0b01, u32::MAX))
+            .build(&mut physics_world);
+        physics_world.step(1.0 / 60.0);
+
+        let origin = Point::new(0.0, 10.0, 0.0);
+        let down = Vector::new(0.0, -2.0, 0.0);
+        let hit = physics_world.raycast(origin, down, 100.0, &QueryFilter::new()).unwrap();
+        assert_eq!(hit.rigid_body, Some(ground.rigid_body_handle()));
+        assert!((hit.distance - 9.5).abs() < 1e-4);
+        assert!((hit.point.y - 0.5).abs() < 1e-4);
+        assert!((hit.normal - Vector::new(0.0, 1.0, 0.0)).norm() < 1e-4);
+
+        assert!(physics_world.raycast(origin, down, 5.0, &QueryFilter::new()).is_none());
+        assert!(physics_world.raycast(origin, down, 100.0, &QueryFilter::new().excluding(ground.rigid_body_handle())).is_none());
+        assert!(physics_world.raycast(origin, down, 100.0, &QueryFilter::new().with_groups(InteractionGroups::new(u32::MAX, 0b10))).is_none());
+
+        let probe = SphereCollider { radius: 1.0 };
+        let overlaps = physics_world.intersect_shape(&probe, &Isometry::translation(0.0, 1.0, 0.0), &QueryFilter::new());
+        assert_eq!(overlaps, vec![ShapeOverlap { collider: hit.collider, rigid_body: hit.rigid_body }]);
+        let hit = physics_world.cast_shape(&probe, &Isometry::translation(0.0, 10.0, 0.0), down, 100.0, &QueryFilter::new()).unwrap();
+        assert!((hit.distance - 8.5).abs() < 1e-3);
+    }
+}
@@ -1,12 +1,12 @@
-use std::cell::RefCell;
 use std::ops::Deref;
-use std::rc::Rc;
+use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
 use wgpu::SurfaceConfiguration;
 use winit::dpi::{LogicalSize};
-use winit::event::{DeviceEvent, Event, WindowEvent};
+use winit::event::{DeviceEvent, ElementState, Event, VirtualKeyCode, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
 use winit::window::{Window, WindowBuilder};
+use dyngine_core::capture;
 use dyngine_core::engine::{EngineInstance, ViewportRegion};
 
 async fn run(event_loop: EventLoop<()>, window: Window) {
@@ -37,13 +37,13 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                 None,
             ).await
             .expect("Failed to create device");
-        device = Rc::new(d);
-        queue = Rc::new(q);
+        device = Arc::new(d);
+        queue = Arc::new(q);
     }
 
     let surface_format = surface.get_preferred_format(&adapter).unwrap();
 
-    let surface_config: Rc<RefCell<SurfaceConfiguration>> = Rc::new(RefCell::new(wgpu::SurfaceConfiguration {
+    let surface_config: Arc<RwLock<SurfaceConfiguration>> = Arc::new(RwLock::new(wgpu::SurfaceConfiguration {
         usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
         format: surface_format,
         width: size.width,
@@ -54,7 +54,8 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
     let mut engine_instance = EngineInstance::new(device.clone(), queue.clone(), surface_config.clone());
 
     engine_instance.start();
-    surface.configure(&device, surface_config.borrow_mut().deref());
+    engine_instance.start_simulation_thread();
+    surface.configure(&device, surface_config.write().unwrap().deref());
 
     let mut last_frame_end = Instant::now();
     let mut last_frame_time = Duration::from_secs(0);
@@ -71,6 +72,8 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
 
     let mut mouse_input_valid = false;
 
+    let mut screenshot_requested = false;
+
     event_loop.run(move |event, _, control_flow| {
         // event_loop.run never returns, therefore we must take ownership of the resources
         // to ensure the resources are properly cleaned up.
@@ -82,23 +85,23 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
             } => match event {
                 WindowEvent::Resized(size) => {
                     if size.width > 0 && size.height > 0 {
-                        let mut surface_config_mut = surface_config.borrow_mut();
-
                         // Resize surface to match window
                         {
+                            let mut surface_config_mut = surface_config.write().unwrap();
                             surface_config_mut.width = size.width;
                             surface_config_mut.height = size.height;
                             surface.configure(&device, surface_config_mut.deref());
                         }
 
                         // Resize engine instance
+                        // (the engine reads the surface configuration, so it must not be borrowed here)
                         {
                             let scale_factor = window.scale_factor() as f32;
                             let scaled_viewport_region = ViewportRegion {
                                 x: 0.0,
                                 y: 0.0,
-                                width: surface_config_mut.width as f32 * scale_factor,
-                                height: surface_config_mut.height as f32 * scale_factor,
+                                width: size.width as f32 * scale_factor,
+                                height: size.height as f32 * scale_factor,
                             };
                             engine_instance.resize(&scaled_viewport_region);
                         }
@@ -107,6 +110,9 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                 WindowEvent::KeyboardInput { device_id, input, is_synthetic } => {
                     match input.virtual_keycode {
                         Some(key_code) => {
+                            if key_code == VirtualKeyCode::F12 && input.state == ElementState::Pressed {
+                                screenshot_requested = true;
+                            }
                             if engine_instance.window_state.has_focus() {
                                 engine_instance.handle_key_state(device_id, key_code, input.state, is_synthetic, last_frame_time.as_secs_f64());
                             }
@@ -179,7 +185,7 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                             window.set_cursor_visible(false);
                             grabbed_cursor = true;
                         }
-                        // window.set_cursor_position(PhysicalPosition::new(surface_config.borrow().width / 2, surface_config.borrow().height / 2)).unwrap();
+                        // window.set_cursor_position(PhysicalPosition::new(surface_config.read().unwrap().width / 2, surface_config.read().unwrap().height / 2)).unwrap();
                     } else {
                         if grabbed_cursor {
                             window.set_cursor_grab(false).unwrap();
@@ -199,21 +205,32 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                         &wgpu::CommandEncoderDescriptor { label: Some("EngineRender") }
                     );
 
-                    let surface_config_mut = surface_config.borrow_mut();
-
                     // No scale factor needed to render correctly
-                    let viewport_region = ViewportRegion {
-                        x: 0.0,
-                        y: 0.0,
-                        width: surface_config_mut.width as f32,
-                        height: surface_config_mut.height as f32,
-                    };
-
-                    let primary_camera = engine_instance.engine_core_state.as_ref().unwrap().ecs_world.get_primary_camera().unwrap();
+                    let viewport_region;
+                    {
+                        let surface_config = surface_config.read().unwrap();
+                        viewport_region = ViewportRegion {
+                            x: 0.0,
+                            y: 0.0,
+                            width: surface_config.width as f32,
+                            height: surface_config.height as f32,
+                        };
+                    }
+
+                    let primary_camera = engine_instance.primary_camera().unwrap();
                     let delta_time = last_frame_time.as_secs_f64();
 
                     engine_instance.render(&mut command_encoder, &viewport_view, None, &viewport_region, primary_camera, delta_time);
                     queue.submit(Some(command_encoder.finish()));
+
+                    if screenshot_requested {
+                        screenshot_requested = false;
+                        let path = capture::screenshot_file_name();
+                        match engine_instance.save_screenshot(&viewport_region, &path) {
+                            Ok(()) => println!("Saved screenshot to {}", path.display()),
+                            Err(error) => eprintln!("Failed to save screenshot: {}", error),
+                        }
+                    }
                 }
 
                 output_frame.present();